@@ -0,0 +1,30 @@
+/* C interface of socketio-rust-emitter, built with the `ffi` feature:
+ * cargo rustc --release --features ffi --crate-type cdylib */
+#ifndef SOCKETIO_EMITTER_H
+#define SOCKETIO_EMITTER_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SioEmitter SioEmitter;
+
+/* Returns NULL on failure, see sio_last_error. */
+SioEmitter *sio_emitter_new(const char *url);
+void sio_emitter_free(SioEmitter *emitter);
+
+/* These return 0 on success and -1 on failure, including a Rust panic. */
+int sio_emitter_set_namespace(SioEmitter *emitter, const char *nsp);
+int sio_emitter_add_room(SioEmitter *emitter, const char *room);
+int sio_emitter_clear_rooms(SioEmitter *emitter);
+/* json may be NULL for an event without arguments. */
+int sio_emitter_emit_json(SioEmitter *emitter, const char *event, const char *json);
+
+/* The reason the last failing call on this thread failed, or NULL. */
+const char *sio_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
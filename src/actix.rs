@@ -0,0 +1,214 @@
+//! actix-web integration. Register one `EmitterData` with `App::app_data`
+//! so all workers share its emitter, and take it as a handler argument:
+//!
+//! ```no_run
+//! use actix_web::{post, App, HttpResponse, HttpServer};
+//! use socketio_rust_emitter::{Emitter, EmitterData};
+//!
+//! #[post("/notify")]
+//! async fn notify(io: EmitterData) -> actix_web::Result<HttpResponse> {
+//!     io.emit(vec!["news"], "message", vec!["hello"])
+//!         .await
+//!         .map_err(actix_web::error::ErrorServiceUnavailable)?;
+//!     Ok(HttpResponse::Accepted().finish())
+//! }
+//!
+//! #[actix_web::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let io = EmitterData::new(Emitter::new("redis://127.0.0.1/"));
+//!     let data = io.clone();
+//!     HttpServer::new(move || App::new().app_data(data.clone()).service(notify))
+//!         .bind("127.0.0.1:8080")?
+//!         .run()
+//!         .await?;
+//!     io.shutdown(std::time::Duration::from_secs(5)).await;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::{Emitter, IntoRooms};
+use actix_web::dev::Payload;
+use actix_web::{error, FromRequest, HttpRequest};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::future::{ready, Ready};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A shared emitter for actix-web apps, cheap to clone. Emits run on the
+/// blocking thread pool so they never stall a worker.
+#[derive(Debug, Clone)]
+pub struct EmitterData {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    emitter: Emitter,
+    in_flight: AtomicUsize,
+    closed: AtomicBool,
+}
+
+impl EmitterData {
+    pub fn new(emitter: Emitter) -> EmitterData {
+        EmitterData {
+            inner: Arc::new(Inner {
+                emitter,
+                in_flight: AtomicUsize::new(0),
+                closed: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// The shared emitter, e.g. to build a selection for `emit_with`.
+    pub fn emitter(&self) -> &Emitter {
+        &self.inner.emitter
+    }
+
+    /// Emits `event` with `args` to `rooms` in the emitter's namespace.
+    pub async fn emit<R: IntoRooms>(
+        &self,
+        rooms: R,
+        event: &str,
+        args: Vec<&str>,
+    ) -> RedisResult<()> {
+        let event = event.to_string();
+        let args: Vec<String> = args.into_iter().map(str::to_string).collect();
+        let rooms = rooms.into_rooms();
+        self.emit_with(self.emitter().clone(), move |mut io| {
+            io.rooms.extend(rooms);
+            let mut message = vec![event.as_str()];
+            message.extend(args.iter().map(String::as_str));
+            io.send(&io.rooms, &message)
+        })
+        .await
+    }
+
+    /// Runs `f` with `emitter` on the blocking thread pool, counting it as
+    /// in flight until it returns. Fails once `shutdown` has been called.
+    pub async fn emit_with<F>(&self, emitter: Emitter, f: F) -> RedisResult<()>
+    where
+        F: FnOnce(Emitter) -> RedisResult<()> + Send + 'static,
+    {
+        // Counted before checking `closed`, so `shutdown` either waits for
+        // this emit or the emit sees it closed.
+        let in_flight = InFlight::new(self.inner.clone());
+        if self.inner.closed.load(Ordering::SeqCst) {
+            return Err(RedisError::from((
+                ErrorKind::ClientError,
+                "emitter is shutting down",
+            )));
+        }
+        let result = actix_web::rt::task::spawn_blocking(move || {
+            let _in_flight = in_flight;
+            f(emitter)
+        })
+        .await;
+        result.unwrap_or_else(|e| {
+            Err(RedisError::from((
+                ErrorKind::ClientError,
+                "emit task failed",
+                e.to_string(),
+            )))
+        })
+    }
+
+    /// Stops accepting emits and waits up to `timeout` for the ones in
+    /// flight, returning whether they all finished. Call it after the
+    /// server future resolves.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.inner.closed.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+        while self.inner.in_flight.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        true
+    }
+}
+
+/// Counts an emit as in flight until dropped, also when it panics.
+struct InFlight(Arc<Inner>);
+
+impl InFlight {
+    fn new(inner: Arc<Inner>) -> InFlight {
+        inner.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(inner)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl FromRequest for EmitterData {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<EmitterData, actix_web::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(req.app_data::<EmitterData>().cloned().ok_or_else(|| {
+            error::ErrorInternalServerError("EmitterData is not registered with App::app_data")
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryBroker;
+    use actix_web::{test, web, App, HttpResponse};
+
+    async fn notify(io: EmitterData) -> HttpResponse {
+        match io.emit(vec!["news"], "message", vec!["hello"]).await {
+            Ok(()) => HttpResponse::Accepted().finish(),
+            Err(_) => HttpResponse::ServiceUnavailable().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn extractor_and_shutdown() {
+        let broker = InMemoryBroker::new();
+        let io = EmitterData::new(Emitter::with_broker(broker.clone()));
+        let app = test::init_service(
+            App::new()
+                .app_data(io.clone())
+                .route("/notify", web::post().to(notify)),
+        )
+        .await;
+
+        let res =
+            test::call_service(&app, test::TestRequest::post().uri("/notify").to_request()).await;
+        assert_eq!(202, res.status().as_u16());
+        let published = broker.published();
+        assert_eq!(1, published.len());
+        assert_eq!("socket.io#/#news#", published[0].channel);
+        assert_eq!(Some("message"), published[0].event());
+
+        assert!(io.shutdown(Duration::from_secs(1)).await);
+        let res =
+            test::call_service(&app, test::TestRequest::post().uri("/notify").to_request()).await;
+        assert_eq!(503, res.status().as_u16());
+
+        let app = test::init_service(App::new().route("/notify", web::post().to(notify))).await;
+        let res =
+            test::call_service(&app, test::TestRequest::post().uri("/notify").to_request()).await;
+        assert_eq!(500, res.status().as_u16());
+    }
+
+    #[actix_web::test]
+    async fn shutdown_after_panicked_emit() {
+        let io = EmitterData::new(Emitter::with_broker(InMemoryBroker::new()));
+        let panicked = io
+            .emit_with(io.emitter().clone(), |_| panic!("emit failed"))
+            .await;
+        assert!(panicked.is_err());
+
+        let started = Instant::now();
+        assert!(io.shutdown(Duration::from_secs(5)).await);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
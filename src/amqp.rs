@@ -0,0 +1,93 @@
+use crate::Broker;
+use amiquip::{Channel, Connection, ExchangeDeclareOptions, ExchangeType, Publish};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Publishes packets to an AMQP topic exchange. The routing key is the
+/// socket.io channel with `#` separators turned into `.`, e.g.
+/// `socket.io#/chat#room1#` becomes `socket_io./chat.room1`.
+#[derive(Clone)]
+pub struct AmqpBroker {
+    inner: Arc<Mutex<Inner>>,
+    exchange: String,
+}
+
+struct Inner {
+    // Kept alive for as long as the channel is in use.
+    _connection: Connection,
+    channel: Channel,
+}
+
+impl AmqpBroker {
+    /// Connects to `url` and declares `exchange` as a durable topic exchange.
+    pub fn connect(url: &str, exchange: &str) -> RedisResult<AmqpBroker> {
+        let mut connection = Connection::insecure_open(url).map_err(amqp_error)?;
+        let channel = connection.open_channel(None).map_err(amqp_error)?;
+        channel
+            .exchange_declare(
+                ExchangeType::Topic,
+                exchange,
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+            )
+            .map_err(amqp_error)?;
+        Ok(AmqpBroker {
+            inner: Arc::new(Mutex::new(Inner {
+                _connection: connection,
+                channel,
+            })),
+            exchange: exchange.to_string(),
+        })
+    }
+}
+
+fn routing_key(channel: &str) -> String {
+    channel
+        .split('#')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| segment.replace('.', "_"))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+impl Broker for AmqpBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .channel
+            .basic_publish(
+                self.exchange.as_str(),
+                Publish::new(payload, routing_key(channel)),
+            )
+            .map_err(amqp_error)
+    }
+}
+
+impl fmt::Debug for AmqpBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AmqpBroker")
+            .field("exchange", &self.exchange)
+            .finish()
+    }
+}
+
+fn amqp_error(e: amiquip::Error) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "AMQP error", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::routing_key;
+
+    #[test]
+    fn maps_channels_to_routing_keys() {
+        assert_eq!("socket_io./", routing_key("socket.io#/#"));
+        assert_eq!(
+            "socket_io./chat.room1",
+            routing_key("socket.io#/chat#room1#")
+        );
+    }
+}
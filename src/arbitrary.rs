@@ -0,0 +1,89 @@
+use crate::{Opts, Packet};
+use proptest::collection::{btree_map, hash_map, vec};
+use proptest::prelude::*;
+
+/// Opts fields that metadata keys must not shadow.
+const RESERVED: &[&str] = &["rooms", "flags", "traceparent", "correlationId"];
+
+fn nsp() -> impl Strategy<Value = String> {
+    prop_oneof![Just("/".to_string()), "/[a-z][a-z0-9-]{0,11}"]
+}
+
+/// Metadata values that survive the round trip through the flattened map,
+/// which widens 32-bit floats.
+fn metadata_value() -> impl Strategy<Value = rmpv::Value> {
+    prop_oneof![
+        Just(rmpv::Value::Nil),
+        any::<bool>().prop_map(rmpv::Value::from),
+        any::<i64>().prop_map(rmpv::Value::from),
+        any::<u64>().prop_map(rmpv::Value::from),
+        any::<String>().prop_map(rmpv::Value::from),
+        vec(any::<i32>(), 0..4)
+            .prop_map(|items| items.into_iter().map(rmpv::Value::from).collect()),
+    ]
+}
+
+/// Event packets as the emitter publishes them: an event name followed by
+/// its arguments, to `/` or another namespace.
+impl Arbitrary for Packet {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Packet>;
+
+    fn arbitrary_with(_: ()) -> BoxedStrategy<Packet> {
+        (nsp(), vec(any::<String>(), 1..5))
+            .prop_map(|(nsp, data)| Packet {
+                _type: 2,
+                data,
+                nsp,
+            })
+            .boxed()
+    }
+}
+
+/// Opts with rooms, the socket.io flags, and optionally a traceparent,
+/// correlation id and metadata next to them.
+impl Arbitrary for Opts {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Opts>;
+
+    fn arbitrary_with(_: ()) -> BoxedStrategy<Opts> {
+        let flag = prop_oneof![Just("json"), Just("volatile"), Just("broadcast")];
+        let key = "[a-zA-Z][a-zA-Z0-9_]{0,11}".prop_filter("shadows an opts field", |key| {
+            !RESERVED.contains(&key.as_str())
+        });
+        (
+            vec("[a-z0-9:_-]{1,16}", 0..4),
+            hash_map(flag.prop_map(String::from), any::<bool>(), 0..3),
+            proptest::option::of("00-[0-9a-f]{32}-[0-9a-f]{16}-0[01]"),
+            proptest::option::of("[a-zA-Z0-9-]{1,36}"),
+            btree_map(key, metadata_value(), 0..4),
+        )
+            .prop_map(
+                |(rooms, flags, traceparent, correlation_id, metadata)| Opts {
+                    rooms,
+                    flags,
+                    traceparent,
+                    correlation_id,
+                    metadata,
+                },
+            )
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{decode, encode, Opts, Packet};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn round_trips(uid in "[a-zA-Z0-9]{1,12}", packet: Packet, opts: Opts) {
+            let (decoded_uid, decoded_packet, decoded_opts) =
+                decode(&encode(&uid, &packet, &opts)).unwrap();
+            prop_assert_eq!(uid, decoded_uid);
+            prop_assert_eq!(packet, decoded_packet);
+            prop_assert_eq!(opts, decoded_opts);
+        }
+    }
+}
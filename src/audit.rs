@@ -0,0 +1,38 @@
+use redis::RedisError;
+use std::time::SystemTime;
+
+/// What happened to an audited packet.
+#[derive(Debug, Clone, Copy)]
+pub enum AuditOutcome<'a> {
+    Published,
+    /// The emitter was in dry-run mode and the packet was only collected.
+    DryRun,
+    Failed(&'a RedisError),
+}
+
+/// One emitted packet, as reported to an `AuditSink`.
+#[derive(Debug, Clone, Copy)]
+pub struct AuditRecord<'a> {
+    pub timestamp: SystemTime,
+    pub channel: &'a str,
+    pub uid: &'a str,
+    pub event: Option<&'a str>,
+    /// Size of the encoded packet in bytes.
+    pub size: usize,
+    pub outcome: AuditOutcome<'a>,
+}
+
+/// Receives a record of every packet an emitter publishes, whether or not the
+/// publish succeeded. Called synchronously from `emit`.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord<'_>);
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditRecord<'_>) + Send + Sync,
+{
+    fn record(&self, record: &AuditRecord<'_>) {
+        self(record)
+    }
+}
@@ -0,0 +1,174 @@
+//! axum integration. Add an `EmitterExtension` to the router and take a
+//! `RequestEmitter` as a handler argument; it targets the rooms named by the
+//! configured path params:
+//!
+//! ```no_run
+//! use axum::{routing::post, Router};
+//! use socketio_rust_emitter::{Emitter, EmitterExtension, RequestEmitter};
+//!
+//! async fn notify(io: RequestEmitter) -> &'static str {
+//!     match io.emit("message", vec!["hello"]).await {
+//!         Ok(()) => "sent",
+//!         Err(_) => "failed",
+//!     }
+//! }
+//!
+//! let app: Router = Router::new()
+//!     .route("/rooms/{room}/notify", post(notify))
+//!     .layer(EmitterExtension::new(Emitter::new("redis://127.0.0.1/")).room_param("room").layer());
+//! ```
+
+use crate::Emitter;
+use axum::extract::{FromRequestParts, RawPathParams};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::Extension;
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// The emitter shared by an axum app. Clones share the emitter and its
+/// settings, so one can be added as a layer for the whole router.
+#[derive(Debug, Clone)]
+pub struct EmitterExtension {
+    emitter: Emitter,
+    room_params: Arc<Vec<String>>,
+}
+
+impl EmitterExtension {
+    pub fn new(emitter: Emitter) -> EmitterExtension {
+        EmitterExtension {
+            emitter,
+            room_params: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Adds the value of the path param `name`, when the route has one, to
+    /// the rooms of each `RequestEmitter`.
+    pub fn room_param(mut self, name: &str) -> EmitterExtension {
+        Arc::make_mut(&mut self.room_params).push(name.to_string());
+        self
+    }
+
+    /// The layer adding this extension to requests, for `Router::layer`.
+    pub fn layer(self) -> Extension<EmitterExtension> {
+        Extension(self)
+    }
+
+    pub fn emitter(&self) -> &Emitter {
+        &self.emitter
+    }
+}
+
+/// A child of the shared emitter for a single request, with the rooms from
+/// its path params already selected.
+#[derive(Debug, Clone)]
+pub struct RequestEmitter(Emitter);
+
+impl RequestEmitter {
+    pub fn into_inner(self) -> Emitter {
+        self.0
+    }
+
+    /// Emits `event` with `args` to the selected rooms, publishing on the
+    /// Tokio blocking pool.
+    pub async fn emit(&self, event: &str, args: Vec<&str>) -> RedisResult<()> {
+        let emitter = self.0.clone();
+        let mut message = vec![event.to_string()];
+        message.extend(args.into_iter().map(str::to_string));
+        tokio::task::spawn_blocking(move || {
+            let message: Vec<&str> = message.iter().map(String::as_str).collect();
+            emitter.send(&emitter.rooms, &message)
+        })
+        .await
+        .unwrap_or_else(|e| {
+            Err(RedisError::from((
+                ErrorKind::ClientError,
+                "emit task failed",
+                e.to_string(),
+            )))
+        })
+    }
+}
+
+impl Deref for RequestEmitter {
+    type Target = Emitter;
+
+    fn deref(&self) -> &Emitter {
+        &self.0
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestEmitter {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let extension = parts.extensions.get::<EmitterExtension>().cloned().ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "EmitterExtension is not added to the router",
+        ))?;
+        let mut emitter = extension.emitter;
+        if extension.room_params.is_empty() {
+            return Ok(RequestEmitter(emitter));
+        }
+        // Routes without params have none to read, which is not an error.
+        if let Ok(params) = RawPathParams::from_request_parts(parts, state).await {
+            for (key, value) in &params {
+                if extension.room_params.iter().any(|name| name == key) {
+                    emitter = emitter.to(value);
+                }
+            }
+        }
+        Ok(RequestEmitter(emitter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryBroker;
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::routing::post;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    async fn notify(io: RequestEmitter) -> StatusCode {
+        match io.emit("message", vec!["hello"]).await {
+            Ok(()) => StatusCode::ACCEPTED,
+            Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    #[test]
+    fn rooms_from_path_params() {
+        let broker = InMemoryBroker::new();
+        let io = EmitterExtension::new(Emitter::with_broker(broker.clone()).to("all"))
+            .room_param("room")
+            .room_param("user");
+        let app = Router::new()
+            .route("/rooms/{room}/users/{user}", post(notify))
+            .route("/broadcast", post(notify))
+            .layer(io.layer());
+        let request = |uri: &str| Request::post(uri).body(Body::empty()).unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let res = app.clone().oneshot(request("/rooms/news/users/u1")).await;
+            assert_eq!(StatusCode::ACCEPTED, res.unwrap().status());
+            let res = app.oneshot(request("/broadcast")).await;
+            assert_eq!(StatusCode::ACCEPTED, res.unwrap().status());
+        });
+
+        let published = broker.published();
+        assert_eq!(2, published.len());
+        assert_eq!(vec!["all", "news", "u1"], published[0].opts.rooms());
+        assert_eq!(vec!["all"], published[1].opts.rooms());
+
+        let app = Router::new().route("/broadcast", post(notify));
+        let res = runtime.block_on(app.oneshot(request("/broadcast")));
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.unwrap().status());
+    }
+}
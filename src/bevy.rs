@@ -0,0 +1,137 @@
+//! A Bevy plugin for game servers. Systems send `SocketIoEmit` events and
+//! the plugin publishes each frame's events as one batch from a background
+//! thread, so publishing never blocks the schedule:
+//!
+//! ```no_run
+//! use bevy_app::{App, Update};
+//! use bevy_ecs::prelude::EventWriter;
+//! use socketio_rust_emitter::{Emitter, SocketIoEmit, SocketIoPlugin};
+//!
+//! fn announce(mut emits: EventWriter<SocketIoEmit>) {
+//!     emits.send(SocketIoEmit::new(vec!["tick", "{}"]).to("lobby"));
+//! }
+//!
+//! App::new()
+//!     .add_plugins(SocketIoPlugin::new(Emitter::new("redis://127.0.0.1/")))
+//!     .add_systems(Update, announce)
+//!     .run();
+//! ```
+
+use crate::{Emitter, IntoRooms};
+use bevy_app::{App, Last, Plugin};
+use bevy_ecs::prelude::{Event, EventReader, Res, Resource};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+/// An emit sent from systems with `EventWriter<SocketIoEmit>`: the message,
+/// starting with the event name, and the rooms it goes to in addition to
+/// the emitter's.
+#[derive(Event, Debug, PartialEq, Clone, Default)]
+pub struct SocketIoEmit {
+    pub rooms: Vec<String>,
+    pub message: Vec<String>,
+}
+
+impl SocketIoEmit {
+    pub fn new(message: Vec<&str>) -> SocketIoEmit {
+        SocketIoEmit {
+            rooms: Vec::new(),
+            message: message.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    pub fn to<R: IntoRooms>(mut self, rooms: R) -> SocketIoEmit {
+        self.rooms.extend(rooms.into_rooms());
+        self
+    }
+}
+
+/// Publishes the `SocketIoEmit` events of each frame in one batch, at the
+/// end of the frame. Sampling and rate limits are not applied.
+#[derive(Debug, Clone)]
+pub struct SocketIoPlugin {
+    emitter: Emitter,
+}
+
+impl SocketIoPlugin {
+    pub fn new(emitter: Emitter) -> SocketIoPlugin {
+        SocketIoPlugin { emitter }
+    }
+}
+
+/// Hands the frames to the publishing thread, which stops once the app and
+/// this resource are dropped.
+#[derive(Resource)]
+struct Publisher {
+    frames: Sender<Vec<SocketIoEmit>>,
+}
+
+impl Plugin for SocketIoPlugin {
+    fn build(&self, app: &mut App) {
+        let (frames, received) = mpsc::channel::<Vec<SocketIoEmit>>();
+        let emitter = self.emitter.clone();
+        thread::spawn(move || {
+            for frame in received {
+                let items = frame
+                    .iter()
+                    .filter_map(|emit| {
+                        let (event, args) = emit.message.split_first()?;
+                        let args = args.iter().map(String::as_str).collect();
+                        Some((event.as_str(), args, &emit.rooms))
+                    })
+                    .collect();
+                for _result in emitter.emit_batch(items) {
+                    #[cfg(feature = "logging")]
+                    if let Err(e) = _result {
+                        log::warn!("dropped frame emit: {}", e);
+                    }
+                }
+            }
+        });
+        app.add_event::<SocketIoEmit>()
+            .insert_resource(Publisher { frames })
+            .add_systems(Last, publish_frame);
+    }
+}
+
+fn publish_frame(mut emits: EventReader<SocketIoEmit>, publisher: Res<Publisher>) {
+    let frame: Vec<SocketIoEmit> = emits.read().cloned().collect();
+    if !frame.is_empty() {
+        // Only fails when the publishing thread is gone.
+        let _ = publisher.frames.send(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryBroker;
+    use bevy_app::Update;
+    use bevy_ecs::prelude::EventWriter;
+    use std::time::{Duration, Instant};
+
+    fn announce(mut emits: EventWriter<SocketIoEmit>) {
+        emits.send(SocketIoEmit::new(vec!["tick", "1"]).to("lobby"));
+        emits.send(SocketIoEmit::new(vec!["score", "2"]));
+    }
+
+    #[test]
+    fn publishes_frames() {
+        let broker = InMemoryBroker::new();
+        let mut app = App::new();
+        app.add_plugins(SocketIoPlugin::new(Emitter::with_broker(broker.clone())))
+            .add_systems(Update, announce);
+        app.update();
+        app.update();
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while broker.published().len() < 4 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let published = broker.published();
+        let events: Vec<_> = published.iter().map(|p| p.event().unwrap()).collect();
+        assert_eq!(vec!["tick", "score", "tick", "score"], events);
+        assert_eq!(vec!["lobby"], published[0].opts.rooms());
+        assert!(published[1].opts.rooms().is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+//! Emits a socket.io event from the shell, e.g.
+//! `sio-emit --redis redis://127.0.0.1/ --nsp /chat --room r1 event '{"json":"payload"}'`,
+//! or one event per line of JSON read from stdin with `--stdin`:
+//! `{"event": "news", "rooms": ["r1"], "data": [{"title": "hi"}]}`.
+
+use clap::Parser;
+use serde_derive::Deserialize;
+use socketio_rust_emitter::{Emitter, InMemoryBroker};
+use std::io::{self, BufRead};
+use std::process::ExitCode;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "sio-emit",
+    version,
+    about = "Emit a socket.io event through Redis"
+)]
+struct Args {
+    /// Redis URL, may be given more than once for failover
+    #[arg(long, default_value = "redis://127.0.0.1/")]
+    redis: Vec<String>,
+    /// Channel prefix of the socket.io Redis adapter
+    #[arg(long, default_value = "socket.io")]
+    key: String,
+    #[arg(long, default_value = "/")]
+    nsp: String,
+    /// Room to emit to, may be given more than once; all sockets by default
+    #[arg(long = "room")]
+    rooms: Vec<String>,
+    #[arg(long)]
+    uid: Option<String>,
+    #[arg(long)]
+    volatile: bool,
+    /// Print the packet instead of publishing it
+    #[arg(long)]
+    dry_run: bool,
+    /// Read events as JSON lines from stdin instead of the command line
+    #[arg(long, conflicts_with_all = ["event", "args"])]
+    stdin: bool,
+    #[arg(required_unless_present = "stdin")]
+    event: Option<String>,
+    /// Event arguments, usually JSON
+    args: Vec<String>,
+}
+
+/// One line of `--stdin` input. Rooms are added to the `--room` ones.
+#[derive(Debug, Deserialize)]
+struct Line {
+    event: String,
+    #[serde(default)]
+    rooms: Vec<String>,
+    #[serde(default)]
+    data: Vec<serde_json::Value>,
+}
+
+impl Line {
+    /// Strings are passed on as they are, anything else as JSON text.
+    fn args(&self) -> Vec<String> {
+        self.data
+            .iter()
+            .map(|value| match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .collect()
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let stdin = io::stdin();
+    match run(&args, stdin.lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("sio-emit: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run<R: BufRead>(args: &Args, input: R) -> Result<(), Box<dyn std::error::Error>> {
+    let mut builder = Emitter::builder().prefix(&args.key).nsp(&args.nsp);
+    for url in &args.redis {
+        builder = builder.endpoint(url);
+    }
+    if let Some(uid) = &args.uid {
+        builder = builder.uid(uid);
+    }
+    let mut io = builder.build()?;
+    let collector = InMemoryBroker::new();
+    if args.dry_run {
+        io = io.dry_run(collector.clone());
+    }
+    if args.volatile {
+        io = io.volatile();
+    }
+    let rooms: Vec<&str> = args.rooms.iter().map(String::as_str).collect();
+    if let Some(event) = &args.event {
+        let data = args.args.iter().map(String::as_str).collect();
+        for result in io.emit_batch(vec![(event.as_str(), data, rooms.clone())]) {
+            result?;
+        }
+    }
+    // Bad lines are reported and skipped, failing the run at the end.
+    let mut failed = 0;
+    if args.stdin {
+        for (number, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let result = serde_json::from_str::<Line>(&line)
+                .map_err(|e| e.to_string())
+                .and_then(|line| {
+                    let data = line.args();
+                    let data = data.iter().map(String::as_str).collect();
+                    let mut targets = rooms.clone();
+                    targets.extend(line.rooms.iter().map(String::as_str));
+                    let results = io.emit_batch(vec![(line.event.as_str(), data, targets)]);
+                    results
+                        .into_iter()
+                        .collect::<Result<(), _>>()
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = result {
+                eprintln!("sio-emit: line {}: {}", number + 1, e);
+                failed += 1;
+            }
+        }
+    }
+    for published in collector.published() {
+        println!(
+            "{} {}",
+            published.channel,
+            published.packet.to_pretty_string()
+        );
+    }
+    if failed > 0 {
+        return Err(format!("{} lines failed", failed).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, Args, Line};
+    use clap::Parser;
+    use std::io;
+
+    #[test]
+    fn parse_and_dry_run() {
+        let args = Args::try_parse_from([
+            "sio-emit",
+            "--nsp",
+            "/chat",
+            "--room",
+            "r1",
+            "--room",
+            "r2",
+            "--dry-run",
+            "event",
+            r#"{"json":"payload"}"#,
+        ])
+        .unwrap();
+        assert_eq!(vec!["redis://127.0.0.1/"], args.redis);
+        assert_eq!(vec!["r1", "r2"], args.rooms);
+        assert_eq!(Some("event"), args.event.as_deref());
+        run(&args, io::empty()).unwrap();
+
+        assert!(Args::try_parse_from(["sio-emit"]).is_err());
+        assert!(Args::try_parse_from(["sio-emit", "--stdin", "event"]).is_err());
+    }
+
+    #[test]
+    fn json_lines() {
+        let args = Args::try_parse_from(["sio-emit", "--stdin", "--dry-run"]).unwrap();
+        let input = concat!(
+            r#"{"event": "news", "rooms": ["r1"], "data": ["text", {"n": 1}]}"#,
+            "\n\n",
+            r#"{"event": "tick"}"#,
+            "\n",
+        );
+        run(&args, input.as_bytes()).unwrap();
+
+        let line: Line = serde_json::from_str(r#"{"event": "e", "data": ["a", 2, null]}"#).unwrap();
+        assert_eq!(vec!["a", "2", "null"], line.args());
+        let err = run(&args, "not json\n".as_bytes()).unwrap_err();
+        assert_eq!("1 lines failed", err.to_string());
+    }
+}
@@ -0,0 +1,477 @@
+use crate::{Emitter, IntoRooms};
+use redis::streams::{StreamReadOptions, StreamReadReply};
+use redis::{Commands, RedisResult, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A message read by a bridge `Source`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceMessage {
+    /// The Kafka topic, NATS subject or Redis stream it was read from.
+    pub topic: String,
+    /// The Kafka message key or Redis stream entry id, if any.
+    pub key: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+/// Where a `Bridge` reads messages from. Messages returned by `poll` are
+/// only acknowledged by `commit`, so a source that is not committed
+/// delivers them again, at the latest after a restart.
+pub trait Source: Send {
+    /// Waits up to `timeout` for messages, returning at most about `max`.
+    fn poll(&mut self, max: usize, timeout: Duration) -> RedisResult<Vec<SourceMessage>>;
+
+    /// Acknowledges every message returned by `poll` so far.
+    fn commit(&mut self) -> RedisResult<()>;
+}
+
+/// The emit a bridged message is mapped to: the message, starting with the
+/// event name, and the rooms it goes to in addition to the emitter's.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct BridgedEmit {
+    pub rooms: Vec<String>,
+    pub message: Vec<String>,
+}
+
+impl BridgedEmit {
+    pub fn new(message: Vec<&str>) -> BridgedEmit {
+        BridgedEmit {
+            rooms: Vec::new(),
+            message: message.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    pub fn to<R: IntoRooms>(mut self, rooms: R) -> BridgedEmit {
+        self.rooms.extend(rooms.into_rooms());
+        self
+    }
+}
+
+type Mapper = Box<dyn Fn(&SourceMessage) -> Option<BridgedEmit> + Send>;
+
+/// Consumes a `Source` and re-emits its messages through an emitter, using
+/// `mapper` to turn each message into an emit; `None` skips a message.
+///
+/// Each batch is published in one pipeline, failed emits are retried and
+/// the source is committed once the whole batch went out, so delivery is
+/// at least once. Sampling and rate limits are not applied.
+pub struct Bridge<S> {
+    source: S,
+    emitter: Emitter,
+    mapper: Mapper,
+    batch_size: usize,
+    poll_timeout: Duration,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl<S: Source> Bridge<S> {
+    pub fn new<F>(source: S, emitter: Emitter, mapper: F) -> Bridge<S>
+    where
+        F: Fn(&SourceMessage) -> Option<BridgedEmit> + Send + 'static,
+    {
+        Bridge {
+            source,
+            emitter,
+            mapper: Box::new(mapper),
+            batch_size: 100,
+            poll_timeout: Duration::from_secs(1),
+            retries: 3,
+            retry_delay: Duration::from_millis(100),
+        }
+    }
+
+    /// The most messages polled per batch, 100 by default.
+    pub fn batch_size(mut self, batch_size: usize) -> Bridge<S> {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// How long a poll waits for messages, 1 second by default.
+    pub fn poll_timeout(mut self, timeout: Duration) -> Bridge<S> {
+        self.poll_timeout = timeout;
+        self
+    }
+
+    /// How often failed emits of a batch are retried, waiting `delay`
+    /// before each attempt. Defaults to 3 times after 100ms.
+    pub fn retries(mut self, retries: u32, delay: Duration) -> Bridge<S> {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Bridges one batch, returning how many emits it published. The batch
+    /// is not committed when an emit still fails after the retries.
+    pub fn run_once(&mut self) -> RedisResult<usize> {
+        let messages = self.source.poll(self.batch_size, self.poll_timeout)?;
+        if messages.is_empty() {
+            return Ok(0);
+        }
+        let mut pending: Vec<BridgedEmit> = messages
+            .iter()
+            .filter_map(&self.mapper)
+            .filter(|emit| !emit.message.is_empty())
+            .collect();
+        let emitted = pending.len();
+        let mut attempt = 0;
+        while !pending.is_empty() {
+            let items = pending
+                .iter()
+                .map(|emit| {
+                    let args = emit.message[1..].iter().map(String::as_str).collect();
+                    (emit.message[0].as_str(), args, &emit.rooms)
+                })
+                .collect();
+            let results = self.emitter.emit_batch(items);
+            let mut error = None;
+            let mut results = results.into_iter();
+            pending.retain(|_| match results.next() {
+                Some(Err(e)) => {
+                    error = Some(e);
+                    true
+                }
+                _ => false,
+            });
+            if let Some(e) = error {
+                if attempt == self.retries {
+                    return Err(e);
+                }
+                attempt += 1;
+                thread::sleep(self.retry_delay);
+            }
+        }
+        self.source.commit()?;
+        Ok(emitted)
+    }
+
+    /// Runs the bridge on a background thread until the returned handle is
+    /// stopped. Failed batches are logged and polled again.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spawn(mut self) -> BridgeHandle
+    where
+        S: 'static,
+    {
+        BridgeHandle::spawn(move |stop| {
+            while !stop.load(Ordering::SeqCst) {
+                if let Err(_e) = self.run_once() {
+                    #[cfg(feature = "logging")]
+                    log::warn!("bridge batch failed: {}", _e);
+                    thread::sleep(self.retry_delay);
+                }
+            }
+        })
+    }
+}
+
+impl<S> fmt::Debug for Bridge<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bridge")
+            .field("emitter", &self.emitter)
+            .field("batch_size", &self.batch_size)
+            .finish()
+    }
+}
+
+/// Stops a bridge started with `Bridge::spawn`, a `KeyspaceListener`, a
+/// `Relay` or a `Heartbeat`.
+#[derive(Debug)]
+pub struct BridgeHandle {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BridgeHandle {
+    /// Runs `run` on a new thread, which should return once the flag it is
+    /// given is set.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn spawn<F>(run: F) -> BridgeHandle
+    where
+        F: FnOnce(&AtomicBool) + Send + 'static,
+    {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stop = stopped.clone();
+        let thread = thread::spawn(move || run(&stop));
+        BridgeHandle {
+            stopped,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stops the bridge after its current batch and waits for it.
+    pub fn stop(mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Pattern-subscribes to `patterns` on a background thread and passes each
+/// message to `on_message` until the returned handle is stopped. An error,
+/// from the connection or `on_message`, is logged as a failure of `_name`
+/// and the subscription is reopened a second later.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn psubscribe<F>(
+    client: redis::Client,
+    patterns: Vec<String>,
+    _name: &'static str,
+    mut on_message: F,
+) -> BridgeHandle
+where
+    F: FnMut(&redis::Msg) -> RedisResult<()> + Send + 'static,
+{
+    BridgeHandle::spawn(move |stop| {
+        while !stop.load(Ordering::SeqCst) {
+            if let Err(_e) = listen(&client, &patterns, stop, &mut on_message) {
+                #[cfg(feature = "logging")]
+                log::warn!("{} failed: {}", _name, _e);
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn listen<F>(
+    client: &redis::Client,
+    patterns: &[String],
+    stop: &AtomicBool,
+    on_message: &mut F,
+) -> RedisResult<()>
+where
+    F: FnMut(&redis::Msg) -> RedisResult<()>,
+{
+    let mut con = client.get_connection()?;
+    let mut pubsub = con.as_pubsub();
+    for pattern in patterns {
+        pubsub.psubscribe(pattern)?;
+    }
+    // Wakes up regularly to notice when the handle is stopped.
+    pubsub.set_read_timeout(Some(Duration::from_millis(200)))?;
+    while !stop.load(Ordering::SeqCst) {
+        match pubsub.get_message() {
+            Ok(msg) => on_message(&msg)?,
+            Err(e) if e.is_timeout() => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Reads a Redis stream as a member of a consumer group, taking the payload
+/// from one field of each entry. Committing acknowledges the entries; ones
+/// left unacknowledged, e.g. by a crash, are read again first.
+#[derive(Debug, Clone)]
+pub struct RedisStreamSource {
+    client: redis::Client,
+    stream: String,
+    group: String,
+    consumer: String,
+    field: String,
+    unacked: Vec<String>,
+    recover: bool,
+}
+
+impl RedisStreamSource {
+    /// Reads `stream` as `consumer` of `group`, creating the group at the
+    /// end of the stream if needed.
+    pub fn new(
+        client: redis::Client,
+        stream: &str,
+        group: &str,
+        consumer: &str,
+    ) -> RedisResult<RedisStreamSource> {
+        let mut con = client.get_connection()?;
+        let created: RedisResult<()> = con.xgroup_create_mkstream(stream, group, "$");
+        match created {
+            Err(e) if e.code() == Some("BUSYGROUP") => {}
+            result => result?,
+        }
+        Ok(RedisStreamSource {
+            client,
+            stream: stream.to_string(),
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            field: "data".to_string(),
+            unacked: Vec::new(),
+            recover: true,
+        })
+    }
+
+    /// The entry field holding the payload, `data` by default.
+    pub fn field(mut self, field: &str) -> RedisStreamSource {
+        self.field = field.to_string();
+        self
+    }
+
+    fn read(
+        &self,
+        con: &mut redis::Connection,
+        id: &str,
+        max: usize,
+        timeout: Duration,
+    ) -> RedisResult<StreamReadReply> {
+        let mut options = StreamReadOptions::default()
+            .group(&self.group, &self.consumer)
+            .count(max);
+        if id == ">" {
+            options = options.block(timeout.as_millis().max(1) as usize);
+        }
+        con.xread_options(&[&self.stream], &[id], &options)
+    }
+}
+
+impl Source for RedisStreamSource {
+    fn poll(&mut self, max: usize, timeout: Duration) -> RedisResult<Vec<SourceMessage>> {
+        let mut con = self.client.get_connection()?;
+        con.set_read_timeout(Some(timeout + Duration::from_secs(1)))?;
+        let mut reply = StreamReadReply::default();
+        // Re-reading the pending entries returns the unacknowledged ones again.
+        self.unacked.clear();
+        if self.recover {
+            // Entries delivered to this consumer but never acknowledged.
+            reply = self.read(&mut con, "0", max, timeout)?;
+        }
+        if reply.keys.iter().all(|key| key.ids.is_empty()) {
+            reply = self.read(&mut con, ">", max, timeout)?;
+        }
+        let mut messages = Vec::new();
+        for key in reply.keys {
+            for entry in key.ids {
+                self.unacked.push(entry.id.clone());
+                // Entries without the field, e.g. deleted ones, are only acknowledged.
+                let payload = match entry.map.get(&self.field) {
+                    Some(Value::Data(payload)) => payload.clone(),
+                    _ => continue,
+                };
+                messages.push(SourceMessage {
+                    topic: key.key.clone(),
+                    key: Some(entry.id),
+                    payload,
+                });
+            }
+        }
+        self.recover = !self.unacked.is_empty();
+        Ok(messages)
+    }
+
+    fn commit(&mut self) -> RedisResult<()> {
+        if self.unacked.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.client.get_connection()?;
+        let _: i64 = con.xack(&self.stream, &self.group, &self.unacked)?;
+        self.unacked.clear();
+        self.recover = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Broker, InMemoryBroker};
+    use redis::{ErrorKind, RedisError};
+    use std::collections::VecDeque;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct QueueSource {
+        queue: VecDeque<SourceMessage>,
+        polled: Vec<SourceMessage>,
+        commits: Arc<AtomicUsize>,
+    }
+
+    impl Source for QueueSource {
+        fn poll(&mut self, max: usize, _: Duration) -> RedisResult<Vec<SourceMessage>> {
+            // Uncommitted messages are delivered again.
+            if self.polled.is_empty() {
+                let n = max.min(self.queue.len());
+                self.polled = self.queue.drain(..n).collect();
+            }
+            Ok(self.polled.clone())
+        }
+
+        fn commit(&mut self) -> RedisResult<()> {
+            self.polled.clear();
+            self.commits.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Fails the first `failures` publishes.
+    struct FlakyBroker {
+        inner: InMemoryBroker,
+        failures: Mutex<usize>,
+    }
+
+    impl Broker for FlakyBroker {
+        fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(RedisError::from((ErrorKind::IoError, "unavailable")));
+            }
+            self.inner.publish(channel, payload)
+        }
+    }
+
+    fn message(topic: &str, payload: &str) -> SourceMessage {
+        SourceMessage {
+            topic: topic.to_string(),
+            key: None,
+            payload: payload.as_bytes().to_vec(),
+        }
+    }
+
+    fn map(message: &SourceMessage) -> Option<BridgedEmit> {
+        let payload = std::str::from_utf8(&message.payload).ok()?;
+        if payload == "skip" {
+            return None;
+        }
+        Some(BridgedEmit::new(vec!["update", payload]).to(message.topic.as_str()))
+    }
+
+    #[test]
+    fn bridges_batches() {
+        let commits = Arc::new(AtomicUsize::new(0));
+        let source = QueueSource {
+            queue: vec![
+                message("orders", "1"),
+                message("orders", "skip"),
+                message("users", "2"),
+            ]
+            .into(),
+            commits: commits.clone(),
+            ..Default::default()
+        };
+        let broker = InMemoryBroker::new();
+        let flaky = FlakyBroker {
+            inner: broker.clone(),
+            failures: Mutex::new(3),
+        };
+        let mut bridge = Bridge::new(source, Emitter::with_broker(flaky), map)
+            .batch_size(2)
+            .retries(1, Duration::from_millis(1));
+
+        // Both attempts fail, so the batch is left uncommitted.
+        assert!(bridge.run_once().is_err());
+        assert_eq!(0, commits.load(Ordering::SeqCst));
+        // Redelivered, the retry succeeds.
+        assert_eq!(1, bridge.run_once().unwrap());
+        assert_eq!(1, bridge.run_once().unwrap());
+        assert_eq!(0, bridge.run_once().unwrap());
+        assert_eq!(2, commits.load(Ordering::SeqCst));
+
+        let published = broker.published();
+        let rooms: Vec<_> = published.iter().map(|p| p.opts.rooms().to_vec()).collect();
+        assert_eq!(vec![vec!["orders"], vec!["users"]], rooms);
+        assert_eq!(vec!["update", "1"], published[0].packet.data());
+    }
+}
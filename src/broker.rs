@@ -0,0 +1,520 @@
+use crate::auth::TokenCache;
+use crate::{raw, Failover, Mirror, TokenProvider};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+use std::time::Duration;
+
+#[cfg(feature = "streams")]
+use crate::RedisStreams;
+#[cfg(feature = "socks5")]
+use crate::Socks5Proxy;
+#[cfg(feature = "srv")]
+use crate::SrvDiscovery;
+
+/// A message received on a subscribed channel.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BrokerMessage {
+    pub channel: String,
+    pub payload: Vec<u8>,
+}
+
+pub trait Subscription: Send {
+    /// Waits up to `timeout` for the next message; `Ok(None)` means the
+    /// timeout elapsed.
+    fn next_message(&mut self, timeout: Duration) -> RedisResult<Option<BrokerMessage>>;
+}
+
+/// The transport packets are published through. `RedisBroker` is used unless
+/// the emitter is created with `Emitter::with_broker`.
+pub trait Broker: Send + Sync {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()>;
+
+    /// Publishes several packets, returning a result for each. Brokers that
+    /// can send them in a single round trip override this.
+    fn publish_batch(&self, messages: &[(&str, &[u8])]) -> Vec<RedisResult<()>> {
+        messages
+            .iter()
+            .map(|(channel, payload)| self.publish(channel, payload))
+            .collect()
+    }
+
+    /// Subscribes to `channels`, used to collect responses to requests sent
+    /// to the socket.io servers.
+    fn subscribe(&self, _channels: &[String]) -> RedisResult<Box<dyn Subscription>> {
+        Err(RedisError::from((
+            ErrorKind::ClientError,
+            "this broker does not support subscriptions",
+        )))
+    }
+
+    /// How many subscribers `channel` has, e.g. socket.io servers listening
+    /// for requests. `None` when the broker can't tell.
+    fn subscribers(&self, _channel: &str) -> RedisResult<Option<usize>> {
+        Ok(None)
+    }
+
+    /// Publishes `payload` on `channel` after subscribing to `response_channel`,
+    /// so no response can be missed.
+    fn request(
+        &self,
+        channel: &str,
+        payload: &[u8],
+        response_channel: &str,
+    ) -> RedisResult<Box<dyn Subscription>> {
+        let subscription = self.subscribe(&[response_channel.to_string()])?;
+        self.publish(channel, payload)?;
+        Ok(subscription)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RedisBroker {
+    pub(crate) client: redis::Client,
+    pub(crate) command_timeout: Option<Duration>,
+    #[cfg(feature = "socks5")]
+    pub(crate) proxy: Option<Socks5Proxy>,
+    #[cfg(feature = "srv")]
+    pub(crate) srv: Option<SrvDiscovery>,
+    pub(crate) failover: Option<Failover>,
+    pub(crate) mirrors: Vec<Mirror>,
+    pub(crate) token_provider: Option<TokenCache>,
+    pub(crate) sharded: bool,
+    pub(crate) resp3: bool,
+    #[cfg(feature = "streams")]
+    pub(crate) streams: Option<RedisStreams>,
+}
+
+impl RedisBroker {
+    pub fn new(client: redis::Client) -> RedisBroker {
+        RedisBroker {
+            client,
+            command_timeout: None,
+            #[cfg(feature = "socks5")]
+            proxy: None,
+            #[cfg(feature = "srv")]
+            srv: None,
+            failover: None,
+            mirrors: Vec::new(),
+            token_provider: None,
+            sharded: false,
+            resp3: false,
+            #[cfg(feature = "streams")]
+            streams: None,
+        }
+    }
+
+    /// Sets the read/write timeout for commands, so a stalled Redis fails the
+    /// publish instead of blocking the caller indefinitely.
+    pub fn command_timeout(mut self, timeout: Duration) -> RedisBroker {
+        self.command_timeout = Some(timeout);
+        self
+    }
+
+    #[cfg(feature = "socks5")]
+    pub fn proxy(mut self, proxy: Socks5Proxy) -> RedisBroker {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Resolves the Redis endpoint through DNS SRV instead of the configured
+    /// host; credentials are still taken from the original connection info.
+    #[cfg(feature = "srv")]
+    pub fn discover(mut self, srv: SrvDiscovery) -> RedisBroker {
+        self.srv = Some(srv);
+        self
+    }
+
+    /// Publishes a copy of every packet to `mirror` as well.
+    pub fn mirror(mut self, mirror: Mirror) -> RedisBroker {
+        self.mirrors.push(mirror);
+        self
+    }
+
+    /// Authenticates with credentials from `provider`, fetching a new token
+    /// shortly before the current one expires.
+    pub fn token_provider<P: TokenProvider + 'static>(mut self, provider: P) -> RedisBroker {
+        self.token_provider = Some(TokenCache::new(provider));
+        self
+    }
+
+    /// Publishes with `SPUBLISH` for adapters using Redis 7 sharded pub/sub.
+    pub fn sharded(mut self) -> RedisBroker {
+        self.sharded = true;
+        self
+    }
+
+    /// Speaks RESP3 (`HELLO 3`) on the connection.
+    pub fn resp3(mut self) -> RedisBroker {
+        self.resp3 = true;
+        self
+    }
+
+    /// Appends packets to a Redis stream instead of publishing them.
+    #[cfg(feature = "streams")]
+    pub fn streams(mut self, streams: RedisStreams) -> RedisBroker {
+        self.streams = Some(streams);
+        self
+    }
+
+    fn client(&self) -> RedisResult<redis::Client> {
+        #[cfg(feature = "srv")]
+        if let Some(srv) = &self.srv {
+            return srv.client(self.client.get_connection_info());
+        }
+        Ok(self.client.clone())
+    }
+
+    /// Runs `attempt` on the endpoint in use: the active one of the failover,
+    /// or the configured or discovered one.
+    fn on_endpoint<T, F>(&self, attempt: F) -> RedisResult<T>
+    where
+        F: Fn(&redis::Client) -> RedisResult<T>,
+    {
+        match &self.failover {
+            Some(failover) => failover.run(attempt),
+            None => attempt(&self.client()?),
+        }
+    }
+
+    fn authenticate(&self, client: &redis::Client) -> RedisResult<redis::Client> {
+        if let Some(provider) = &self.token_provider {
+            let token = provider.token()?;
+            let mut info = client.get_connection_info().clone();
+            if token.username.is_some() {
+                info.redis.username = token.username;
+            }
+            info.redis.password = Some(token.password);
+            return redis::Client::open(info);
+        }
+        Ok(client.clone())
+    }
+
+    /// Opens an authenticated connection to `client`, through the proxy or
+    /// speaking RESP3 if configured.
+    fn connect(&self, client: &redis::Client) -> RedisResult<Connection> {
+        let client = self.authenticate(client)?;
+        let info = client.get_connection_info();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("connect").entered();
+        #[cfg(feature = "socks5")]
+        if let Some(proxy) = &self.proxy {
+            let stream = proxy.connect(info, self.command_timeout)?;
+            let con = raw::Connection::open(stream, &info.redis, self.resp3)?;
+            return Ok(Connection::Raw(con));
+        }
+        if self.resp3 {
+            let stream = raw::connect(info, self.command_timeout)?;
+            let con = raw::Connection::open(stream, &info.redis, true)?;
+            return Ok(Connection::Raw(con));
+        }
+        let con = client.get_connection()?;
+        con.set_read_timeout(self.command_timeout)?;
+        con.set_write_timeout(self.command_timeout)?;
+        Ok(Connection::Redis(con))
+    }
+
+    fn publish_to(&self, client: &redis::Client, channel: &str, msg: &[u8]) -> RedisResult<()> {
+        let cmd = self.command(channel, msg)?;
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("redis_publish", addr = %client.get_connection_info().addr)
+                .entered();
+        self.connect(client)?.query(&cmd)
+    }
+
+    /// Sends all messages in one pipeline.
+    fn pipeline_to(&self, client: &redis::Client, messages: &[(&str, &[u8])]) -> RedisResult<()> {
+        let cmds = messages
+            .iter()
+            .map(|(channel, payload)| self.command(channel, payload))
+            .collect::<RedisResult<Vec<_>>>()?;
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("redis_publish", addr = %client.get_connection_info().addr)
+                .entered();
+        match self.connect(client)? {
+            Connection::Redis(mut con) => {
+                let mut pipe = redis::pipe();
+                for cmd in cmds {
+                    pipe.add_command(cmd);
+                }
+                pipe.query(&mut con)
+            }
+            Connection::Raw(mut con) => con.pipeline(&cmds),
+        }
+    }
+
+    fn command(&self, channel: &str, msg: &[u8]) -> RedisResult<redis::Cmd> {
+        #[cfg(feature = "streams")]
+        if let Some(streams) = &self.streams {
+            return streams.command(msg);
+        }
+        let mut cmd = redis::cmd(self.publish_command());
+        cmd.arg(channel).arg(msg);
+        Ok(cmd)
+    }
+
+    /// `SSUBSCRIBE` when sharded, as the servers answer with `SPUBLISH`.
+    fn subscribe_command(&self) -> &'static str {
+        if self.sharded {
+            "SSUBSCRIBE"
+        } else {
+            "SUBSCRIBE"
+        }
+    }
+
+    fn publish_command(&self) -> &'static str {
+        if self.sharded {
+            "SPUBLISH"
+        } else {
+            "PUBLISH"
+        }
+    }
+}
+
+impl Broker for RedisBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let result = self.on_endpoint(|client| self.publish_to(client, channel, payload));
+        for mirror in &self.mirrors {
+            if let Err(e) = self.publish_to(&mirror.client, channel, payload) {
+                mirror_failed(mirror, &e);
+            }
+        }
+        result
+    }
+
+    fn publish_batch(&self, messages: &[(&str, &[u8])]) -> Vec<RedisResult<()>> {
+        let result = self.on_endpoint(|client| self.pipeline_to(client, messages));
+        for mirror in &self.mirrors {
+            if let Err(e) = self.pipeline_to(&mirror.client, messages) {
+                mirror_failed(mirror, &e);
+            }
+        }
+        // A pipeline fails as a whole, so every item gets the same outcome.
+        messages
+            .iter()
+            .map(|_| match &result {
+                Ok(()) => Ok(()),
+                Err(e) => Err(RedisError::from((
+                    e.kind(),
+                    "pipelined publish failed",
+                    e.to_string(),
+                ))),
+            })
+            .collect()
+    }
+
+    fn subscribers(&self, channel: &str) -> RedisResult<Option<usize>> {
+        let numsub = if self.sharded {
+            "SHARDNUMSUB"
+        } else {
+            "NUMSUB"
+        };
+        self.on_endpoint(|client| {
+            let (_, count): (String, usize) = self
+                .connect(client)?
+                .query(redis::cmd("PUBSUB").arg(numsub).arg(channel))?;
+            Ok(Some(count))
+        })
+    }
+
+    fn subscribe(&self, channels: &[String]) -> RedisResult<Box<dyn Subscription>> {
+        self.on_endpoint(|client| {
+            let mut con = self.connect(client)?;
+            con.send(redis::cmd(self.subscribe_command()).arg(channels))?;
+            Ok(Box::new(RedisSubscription { con }) as Box<dyn Subscription>)
+        })
+    }
+}
+
+fn mirror_failed(mirror: &Mirror, e: &RedisError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(error = %e, "mirror publish failed");
+    #[cfg(feature = "logging")]
+    log::warn!("dropped packet for mirror {:?}: {}", mirror.client, e);
+    mirror.report(e);
+}
+
+/// A connection opened by the redis crate, or a raw one for proxied and
+/// RESP3 connections, which it can't drive.
+enum Connection {
+    Redis(redis::Connection),
+    Raw(raw::Connection),
+}
+
+impl Connection {
+    fn query<T: redis::FromRedisValue>(&mut self, cmd: &redis::Cmd) -> RedisResult<T> {
+        match self {
+            Connection::Redis(con) => cmd.query(con),
+            Connection::Raw(con) => redis::from_redis_value(&con.query(cmd)?),
+        }
+    }
+
+    fn send(&mut self, cmd: &redis::Cmd) -> RedisResult<()> {
+        match self {
+            Connection::Redis(con) => con.send_packed_command(&cmd.get_packed_command()),
+            Connection::Raw(con) => con.send(cmd),
+        }
+    }
+
+    /// Waits up to `timeout` for the next reply or message.
+    fn recv(&mut self, timeout: Duration) -> RedisResult<Option<redis::Value>> {
+        let con = match self {
+            Connection::Redis(con) => con,
+            Connection::Raw(con) => return con.recv(timeout),
+        };
+        con.set_read_timeout(Some(timeout))?;
+        match con.recv_response() {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.is_timeout() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+struct RedisSubscription {
+    con: Connection,
+}
+
+impl Subscription for RedisSubscription {
+    fn next_message(&mut self, timeout: Duration) -> RedisResult<Option<BrokerMessage>> {
+        loop {
+            let value = match self.con.recv(timeout)? {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+            // Subscribe confirmations are not messages and are skipped.
+            if let Some(msg) = message(value) {
+                return Ok(Some(msg));
+            }
+        }
+    }
+}
+
+/// A `message` or, for sharded subscriptions, `smessage` push.
+fn message(value: redis::Value) -> Option<BrokerMessage> {
+    let mut items = match value {
+        redis::Value::Bulk(items) if items.len() == 3 => items.into_iter(),
+        _ => return None,
+    };
+    match items.next()? {
+        redis::Value::Data(kind) if kind == b"message" || kind == b"smessage" => {}
+        _ => return None,
+    }
+    match (items.next()?, items.next()?) {
+        (redis::Value::Data(channel), redis::Value::Data(payload)) => Some(BrokerMessage {
+            channel: String::from_utf8(channel).ok()?,
+            payload,
+        }),
+        _ => None,
+    }
+}
+
+#[derive(Clone)]
+pub(crate) enum Transport {
+    Redis(std::sync::Arc<RedisBroker>),
+    Custom(std::sync::Arc<dyn Broker>),
+}
+
+impl Transport {
+    pub(crate) fn broker(&self) -> &dyn Broker {
+        match self {
+            Transport::Redis(redis) => redis.as_ref(),
+            Transport::Custom(broker) => broker.as_ref(),
+        }
+    }
+
+    /// The Redis broker to change a setting of, copied on write so other
+    /// emitters sharing it are unaffected. `None` for custom brokers.
+    pub(crate) fn redis_mut(&mut self) -> Option<&mut RedisBroker> {
+        match self {
+            Transport::Redis(redis) => Some(std::sync::Arc::make_mut(redis)),
+            Transport::Custom(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Redis(redis) => f.debug_tuple("Redis").field(redis).finish(),
+            Transport::Custom(_) => f.debug_tuple("Custom").finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Broker, BrokerMessage, RedisBroker};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Records the first command sent to it, then pushes `push`.
+    fn fake_redis(push: &'static [u8]) -> (u16, mpsc::Receiver<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let count: usize = line.trim()[1..].parse().unwrap();
+            let mut args = Vec::new();
+            for _ in 0..count {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let mut arg = vec![0; line.trim()[1..].parse::<usize>().unwrap() + 2];
+                reader.read_exact(&mut arg).unwrap();
+                arg.truncate(arg.len() - 2);
+                args.push(String::from_utf8(arg).unwrap());
+            }
+            tx.send(args).unwrap();
+            stream.write_all(push).unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+        (port, rx)
+    }
+
+    fn broker(port: u16) -> RedisBroker {
+        let client = redis::Client::open(format!("redis://127.0.0.1:{}", port)).unwrap();
+        RedisBroker::new(client)
+    }
+
+    #[test]
+    fn subscribes() {
+        let (port, commands) = fake_redis(b"*3\r\n$7\r\nmessage\r\n$1\r\nc\r\n$1\r\nx\r\n");
+        let mut subscription = broker(port).subscribe(&["c".to_string()]).unwrap();
+        assert_eq!(vec!["SUBSCRIBE", "c"], commands.recv().unwrap());
+        assert_eq!(
+            Some(BrokerMessage {
+                channel: "c".to_string(),
+                payload: b"x".to_vec(),
+            }),
+            subscription.next_message(Duration::from_secs(1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn subscribes_sharded() {
+        let (port, commands) = fake_redis(
+            b"*3\r\n$10\r\nssubscribe\r\n$1\r\nc\r\n:1\r\n\
+              *3\r\n$8\r\nsmessage\r\n$1\r\nc\r\n$1\r\nx\r\n",
+        );
+        let mut subscription = broker(port)
+            .sharded()
+            .subscribe(&["c".to_string()])
+            .unwrap();
+        assert_eq!(vec!["SSUBSCRIBE", "c"], commands.recv().unwrap());
+        assert_eq!(
+            Some(BrokerMessage {
+                channel: "c".to_string(),
+                payload: b"x".to_vec(),
+            }),
+            subscription.next_message(Duration::from_secs(1)).unwrap()
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use crate::broker::Transport;
+use crate::{create_emitter, Emitter, Failover, RedisBroker};
+use redis::RedisError;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The Redis protocol version spoken on the connection.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+/// A configuration error returned by `EmitterBuilder::build`.
+#[derive(Debug)]
+pub enum BuildError {
+    NoEndpoint,
+    InvalidEndpoint {
+        url: String,
+        source: RedisError,
+    },
+    /// The prefix is empty or contains the `#` channel separator.
+    InvalidPrefix(String),
+    /// The namespace does not start with `/` or contains `#`.
+    InvalidNamespace(String),
+    EmptyUid,
+    ZeroTimeout,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::NoEndpoint => write!(f, "no Redis endpoint configured"),
+            BuildError::InvalidEndpoint { url, source } => {
+                write!(f, "invalid Redis endpoint {:?}: {}", url, source)
+            }
+            BuildError::InvalidPrefix(prefix) => write!(f, "invalid channel prefix {:?}", prefix),
+            BuildError::InvalidNamespace(nsp) => write!(f, "invalid namespace {:?}", nsp),
+            BuildError::EmptyUid => write!(f, "emitter uid must not be empty"),
+            BuildError::ZeroTimeout => write!(f, "command timeout must not be zero"),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::InvalidEndpoint { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Validated configuration for a Redis-backed `Emitter`, created with
+/// `Emitter::builder()`. Adding several endpoints fails over between them in
+/// the order given.
+#[derive(Debug, Clone)]
+pub struct EmitterBuilder {
+    endpoints: Vec<String>,
+    prefix: String,
+    nsp: String,
+    uid: String,
+    protocol: Protocol,
+    command_timeout: Option<Duration>,
+    retries: u32,
+    probe_interval: Option<Duration>,
+}
+
+impl Default for EmitterBuilder {
+    fn default() -> EmitterBuilder {
+        EmitterBuilder {
+            endpoints: Vec::new(),
+            prefix: "socket.io".to_string(),
+            nsp: "/".to_string(),
+            uid: "emitter".to_string(),
+            protocol: Protocol::Resp2,
+            command_timeout: None,
+            retries: 0,
+            probe_interval: None,
+        }
+    }
+}
+
+impl EmitterBuilder {
+    /// A Redis URL such as `redis://127.0.0.1:6379`.
+    pub fn endpoint(mut self, url: &str) -> EmitterBuilder {
+        self.endpoints.push(url.to_string());
+        self
+    }
+
+    pub fn prefix(mut self, prefix: &str) -> EmitterBuilder {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    pub fn nsp(mut self, nsp: &str) -> EmitterBuilder {
+        self.nsp = nsp.to_string();
+        self
+    }
+
+    pub fn uid(mut self, uid: &str) -> EmitterBuilder {
+        self.uid = uid.to_string();
+        self
+    }
+
+    pub fn protocol(mut self, protocol: Protocol) -> EmitterBuilder {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn command_timeout(mut self, timeout: Duration) -> EmitterBuilder {
+        self.command_timeout = Some(timeout);
+        self
+    }
+
+    /// Additional attempts against each endpoint before failing over.
+    pub fn retries(mut self, retries: u32) -> EmitterBuilder {
+        self.retries = retries;
+        self
+    }
+
+    /// How often higher-priority endpoints are probed after a failover.
+    pub fn probe_interval(mut self, interval: Duration) -> EmitterBuilder {
+        self.probe_interval = Some(interval);
+        self
+    }
+
+    pub fn build(self) -> Result<Emitter, BuildError> {
+        if self.prefix.is_empty() || self.prefix.contains('#') {
+            return Err(BuildError::InvalidPrefix(self.prefix));
+        }
+        if !self.nsp.starts_with('/') || self.nsp.contains('#') {
+            return Err(BuildError::InvalidNamespace(self.nsp));
+        }
+        if self.uid.is_empty() {
+            return Err(BuildError::EmptyUid);
+        }
+        if self.command_timeout == Some(Duration::ZERO) {
+            return Err(BuildError::ZeroTimeout);
+        }
+        let clients = self
+            .endpoints
+            .iter()
+            .map(|url| {
+                redis::Client::open(url.as_str()).map_err(|source| BuildError::InvalidEndpoint {
+                    url: url.clone(),
+                    source,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if clients.is_empty() {
+            return Err(BuildError::NoEndpoint);
+        }
+
+        let mut redis = RedisBroker::new(clients[0].clone());
+        redis.command_timeout = self.command_timeout;
+        redis.resp3 = self.protocol == Protocol::Resp3;
+        if clients.len() > 1 || self.retries > 0 {
+            let mut failover = Failover::from_clients(clients)
+                .map_err(|_| BuildError::NoEndpoint)?
+                .retries(self.retries);
+            if let Some(interval) = self.probe_interval {
+                failover = failover.probe_interval(interval);
+            }
+            redis.failover = Some(failover);
+        }
+        let mut emitter =
+            create_emitter(Transport::Redis(Arc::new(redis)), &self.prefix, &self.nsp);
+        emitter.uid = self.uid.into();
+        Ok(emitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildError, Protocol};
+    use crate::Emitter;
+    use std::time::Duration;
+
+    #[test]
+    fn validates() {
+        let build = |builder: super::EmitterBuilder| builder.build().unwrap_err();
+        let builder = || Emitter::builder().endpoint("redis://127.0.0.1");
+
+        assert!(matches!(build(Emitter::builder()), BuildError::NoEndpoint));
+        assert!(matches!(
+            build(Emitter::builder().endpoint("http://127.0.0.1")),
+            BuildError::InvalidEndpoint { .. }
+        ));
+        assert!(matches!(
+            build(builder().prefix("a#b")),
+            BuildError::InvalidPrefix(_)
+        ));
+        assert!(matches!(
+            build(builder().nsp("admin")),
+            BuildError::InvalidNamespace(_)
+        ));
+        assert!(matches!(build(builder().uid("")), BuildError::EmptyUid));
+        assert!(matches!(
+            build(builder().command_timeout(Duration::ZERO)),
+            BuildError::ZeroTimeout
+        ));
+
+        let emitter = builder()
+            .endpoint("redis://127.0.0.2")
+            .prefix("app")
+            .nsp("/admin")
+            .uid("worker-1")
+            .protocol(Protocol::Resp3)
+            .retries(2)
+            .build()
+            .unwrap();
+        assert_eq!("app#/admin#", &*emitter.channel_for(&Default::default()));
+        assert_eq!("worker-1", &*emitter.uid);
+    }
+}
@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Builds the Redis channel a packet is published on. Implement it for
+/// adapters that deviate from the socket.io channel names. Emitters cache
+/// the channels, so they must only depend on the arguments.
+pub trait ChannelScheme: Send + Sync {
+    /// `room` is set when the packet targets exactly one room.
+    fn channel(&self, prefix: &str, nsp: &str, room: Option<&str>) -> String;
+
+    /// The channel requests to the socket.io servers are published on.
+    fn request_channel(&self, prefix: &str, nsp: &str) -> String {
+        format!("{}-request#{}#", prefix, nsp)
+    }
+
+    /// The channel the socket.io servers publish responses to requests on.
+    fn response_channel(&self, prefix: &str, nsp: &str) -> String {
+        format!("{}-response#{}#", prefix, nsp)
+    }
+}
+
+impl<F> ChannelScheme for F
+where
+    F: Fn(&str, &str, Option<&str>) -> String + Send + Sync,
+{
+    fn channel(&self, prefix: &str, nsp: &str, room: Option<&str>) -> String {
+        self(prefix, nsp, room)
+    }
+}
+
+/// Channels made of the prefix, namespace and room, each followed by a
+/// separator. The default matches the socket.io adapter: `prefix#nsp#room#`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ChannelFormat {
+    separator: String,
+    nsp: bool,
+    room: bool,
+    trailing: bool,
+    shards: u32,
+}
+
+impl Default for ChannelFormat {
+    fn default() -> ChannelFormat {
+        ChannelFormat {
+            separator: "#".to_string(),
+            nsp: true,
+            room: true,
+            trailing: true,
+            shards: 1,
+        }
+    }
+}
+
+impl ChannelFormat {
+    pub fn new() -> ChannelFormat {
+        ChannelFormat::default()
+    }
+
+    pub fn separator(mut self, separator: &str) -> ChannelFormat {
+        self.separator = separator.to_string();
+        self
+    }
+
+    /// Leaves the namespace out of the channel.
+    pub fn without_nsp(mut self) -> ChannelFormat {
+        self.nsp = false;
+        self
+    }
+
+    /// Always publishes on the namespace channel, even to a single room.
+    pub fn without_room(mut self) -> ChannelFormat {
+        self.room = false;
+        self
+    }
+
+    /// Leaves out the separator after the last segment, e.g. `prefix#nsp`.
+    pub fn without_trailing_separator(mut self) -> ChannelFormat {
+        self.trailing = false;
+        self
+    }
+
+    /// Spreads room channels over `shards` channels each, adding the shard
+    /// after the room, e.g. `prefix#nsp#room#3#`, so no single channel runs
+    /// hot. A room's shard is the jump consistent hash of the 64-bit FNV-1a
+    /// hash of its name, so few rooms move when shards are added. Packets to
+    /// a whole namespace stay on its channel.
+    pub fn shards(mut self, shards: u32) -> ChannelFormat {
+        self.shards = shards.max(1);
+        self
+    }
+
+    /// The shard of `room`, from 0 to `shards - 1`.
+    pub fn shard(&self, room: &str) -> u32 {
+        jump_hash(fnv1a(room.as_bytes()), self.shards)
+    }
+
+    /// The request or response channel, e.g. `prefix-request#nsp#`.
+    fn join_kind(&self, prefix: &str, kind: &str, nsp: &str) -> String {
+        let prefix = format!("{}-{}", prefix, kind);
+        if self.nsp {
+            self.join(&[&prefix, nsp])
+        } else {
+            self.join(&[&prefix])
+        }
+    }
+
+    fn join(&self, segments: &[&str]) -> String {
+        let mut channel = segments.join(&self.separator);
+        if self.trailing {
+            channel.push_str(&self.separator);
+        }
+        channel
+    }
+}
+
+impl ChannelScheme for ChannelFormat {
+    fn channel(&self, prefix: &str, nsp: &str, room: Option<&str>) -> String {
+        let mut segments = vec![prefix];
+        if self.nsp {
+            segments.push(nsp);
+        }
+        let shard;
+        if let (true, Some(room)) = (self.room, room) {
+            segments.push(room);
+            if self.shards > 1 {
+                shard = self.shard(room).to_string();
+                segments.push(&shard);
+            }
+        }
+        self.join(&segments)
+    }
+
+    fn request_channel(&self, prefix: &str, nsp: &str) -> String {
+        self.join_kind(prefix, "request", nsp)
+    }
+
+    fn response_channel(&self, prefix: &str, nsp: &str) -> String {
+        self.join_kind(prefix, "response", nsp)
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Lamping and Veach's jump consistent hash of `key` into `buckets`.
+fn jump_hash(mut key: u64, buckets: u32) -> u32 {
+    let (mut b, mut j) = (-1i64, 0i64);
+    while j < i64::from(buckets) {
+        b = j;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b as u32
+}
+
+/// Channel names used by known adapter variants, selected with
+/// `Emitter::channel_layout`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ChannelLayout {
+    /// `prefix#nsp#room#`, with requests on `prefix-request#nsp#`.
+    Standard,
+    /// `prefix#nsp#room`, with requests on `prefix-request#nsp`.
+    NoTrailingSeparator,
+    /// `prefix#nsp#room#`, with requests on `prefix#request#nsp#`.
+    HashRequest,
+}
+
+impl ChannelScheme for ChannelLayout {
+    fn channel(&self, prefix: &str, nsp: &str, room: Option<&str>) -> String {
+        match self {
+            ChannelLayout::Standard | ChannelLayout::HashRequest => {
+                ChannelFormat::new().channel(prefix, nsp, room)
+            }
+            ChannelLayout::NoTrailingSeparator => ChannelFormat::new()
+                .without_trailing_separator()
+                .channel(prefix, nsp, room),
+        }
+    }
+
+    fn request_channel(&self, prefix: &str, nsp: &str) -> String {
+        match self {
+            ChannelLayout::Standard => ChannelFormat::new().request_channel(prefix, nsp),
+            ChannelLayout::NoTrailingSeparator => ChannelFormat::new()
+                .without_trailing_separator()
+                .request_channel(prefix, nsp),
+            ChannelLayout::HashRequest => format!("{}#request#{}#", prefix, nsp),
+        }
+    }
+
+    fn response_channel(&self, prefix: &str, nsp: &str) -> String {
+        match self {
+            ChannelLayout::Standard => ChannelFormat::new().response_channel(prefix, nsp),
+            ChannelLayout::NoTrailingSeparator => ChannelFormat::new()
+                .without_trailing_separator()
+                .response_channel(prefix, nsp),
+            ChannelLayout::HashRequest => format!("{}#response#{}#", prefix, nsp),
+        }
+    }
+}
+
+/// The most single-room channels cached per namespace before the cache for
+/// that namespace starts over.
+const CACHED_ROOMS: usize = 1024;
+
+/// The most prefix and namespace pairs cached before the whole cache starts
+/// over.
+const CACHED_NAMESPACES: usize = 64;
+
+/// Channels built by a scheme, by prefix and namespace.
+type Channels = HashMap<String, HashMap<String, NspChannels>>;
+
+struct NspChannels {
+    broadcast: Arc<str>,
+    rooms: HashMap<String, Arc<str>>,
+}
+
+/// The emitter's scheme with the channels it built, shared between clones
+/// so emits to the same rooms skip formatting them again.
+#[derive(Clone)]
+pub(crate) struct Scheme {
+    scheme: Arc<dyn ChannelScheme>,
+    cache: Arc<RwLock<Channels>>,
+}
+
+impl Scheme {
+    pub(crate) fn new(scheme: Arc<dyn ChannelScheme>) -> Scheme {
+        Scheme {
+            scheme,
+            cache: Default::default(),
+        }
+    }
+
+    #[cfg(feature = "remote")]
+    pub(crate) fn request_channel(&self, prefix: &str, nsp: &str) -> String {
+        self.scheme.request_channel(prefix, nsp)
+    }
+
+    #[cfg(feature = "remote")]
+    pub(crate) fn response_channel(&self, prefix: &str, nsp: &str) -> String {
+        self.scheme.response_channel(prefix, nsp)
+    }
+
+    pub(crate) fn channel(&self, prefix: &str, nsp: &str, room: Option<&str>) -> Arc<str> {
+        {
+            let cache = self.cache.read().unwrap();
+            let cached = cache.get(prefix).and_then(|nsps| nsps.get(nsp));
+            match (cached, room) {
+                (Some(channels), None) => return channels.broadcast.clone(),
+                (Some(channels), Some(room)) => {
+                    if let Some(channel) = channels.rooms.get(room) {
+                        return channel.clone();
+                    }
+                }
+                (None, _) => {}
+            }
+        }
+        let mut cache = self.cache.write().unwrap();
+        let cached = cache.get(prefix).is_some_and(|nsps| nsps.contains_key(nsp));
+        if !cached && cache.values().map(HashMap::len).sum::<usize>() >= CACHED_NAMESPACES {
+            cache.clear();
+        }
+        let channels = cache
+            .entry(prefix.to_string())
+            .or_default()
+            .entry(nsp.to_string())
+            .or_insert_with(|| NspChannels {
+                broadcast: self.scheme.channel(prefix, nsp, None).into(),
+                rooms: HashMap::new(),
+            });
+        match room {
+            None => channels.broadcast.clone(),
+            Some(room) => {
+                if channels.rooms.len() >= CACHED_ROOMS {
+                    channels.rooms.clear();
+                }
+                channels
+                    .rooms
+                    .entry(room.to_string())
+                    .or_insert_with(|| self.scheme.channel(prefix, nsp, Some(room)).into())
+                    .clone()
+            }
+        }
+    }
+}
+
+impl Default for Scheme {
+    fn default() -> Scheme {
+        Scheme::new(Arc::new(ChannelFormat::default()))
+    }
+}
+
+impl fmt::Debug for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Scheme").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fnv1a, jump_hash, ChannelFormat, ChannelLayout, ChannelScheme, Scheme, CACHED_NAMESPACES,
+        CACHED_ROOMS,
+    };
+    use crate::{Emitter, InMemoryBroker};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn channel_formats() {
+        let default = ChannelFormat::new();
+        assert_eq!("socket.io#/#", default.channel("socket.io", "/", None));
+        assert_eq!(
+            "socket.io#/#r#",
+            default.channel("socket.io", "/", Some("r"))
+        );
+
+        let colons = ChannelFormat::new().separator(":").without_nsp();
+        assert_eq!("app:r:", colons.channel("app", "/chat", Some("r")));
+        let no_room = ChannelFormat::new().without_room();
+        assert_eq!("app#/chat#", no_room.channel("app", "/chat", Some("r")));
+
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .channel_scheme(|prefix: &str, nsp: &str, room: Option<&str>| {
+                format!("{}:tenant-1:{}:{}", prefix, nsp, room.unwrap_or("*"))
+            })
+            .of("/chat")
+            .to("r")
+            .emit(vec!["test"]);
+        broker.assert_published_to("socket.io:tenant-1:/chat:r");
+    }
+
+    #[test]
+    fn sharded_channels() {
+        let sharded = ChannelFormat::new().shards(8);
+        let shard = sharded.shard("lobby");
+        assert_eq!(
+            format!("p#/#lobby#{}#", shard),
+            sharded.channel("p", "/", Some("lobby"))
+        );
+        assert_eq!("p#/#", sharded.channel("p", "/", None));
+        assert_eq!(
+            "p#/#lobby#",
+            ChannelFormat::new().channel("p", "/", Some("lobby"))
+        );
+        assert_eq!(0, ChannelFormat::new().shard("lobby"));
+
+        let rooms: Vec<String> = (0..1000).map(|i| format!("room-{}", i)).collect();
+        let shards: Vec<u32> = rooms.iter().map(|room| sharded.shard(room)).collect();
+        assert!((0..8).all(|shard| shards.contains(&shard)));
+        let more = ChannelFormat::new().shards(9);
+        let moved: Vec<u32> = rooms
+            .iter()
+            .zip(&shards)
+            .filter(|(room, shard)| more.shard(room) != **shard)
+            .map(|(room, _)| more.shard(room))
+            .collect();
+        // Only rooms moving to the new shard change, about one in nine.
+        assert!(moved.iter().all(|&shard| shard == 8));
+        assert!(moved.len() > 50 && moved.len() < 200);
+        assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(b"a"));
+        assert_eq!([6, 2], [jump_hash(1, 8), jump_hash(fnv1a(b"a"), 8)]);
+    }
+
+    #[test]
+    fn channel_layouts() {
+        let cases = [
+            (
+                ChannelLayout::Standard,
+                "p#/#r#",
+                "p-request#/#",
+                "p-response#/#",
+            ),
+            (
+                ChannelLayout::NoTrailingSeparator,
+                "p#/#r",
+                "p-request#/",
+                "p-response#/",
+            ),
+            (
+                ChannelLayout::HashRequest,
+                "p#/#r#",
+                "p#request#/#",
+                "p#response#/#",
+            ),
+        ];
+        for (layout, channel, request, response) in cases.iter() {
+            assert_eq!(*channel, layout.channel("p", "/", Some("r")));
+            assert_eq!(*request, layout.request_channel("p", "/"));
+            assert_eq!(*response, layout.response_channel("p", "/"));
+        }
+
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .channel_layout(ChannelLayout::NoTrailingSeparator)
+            .emit(vec!["test"]);
+        broker.assert_published_to("socket.io#/");
+    }
+
+    #[test]
+    fn cached_channels() {
+        let built = Arc::new(AtomicUsize::new(0));
+        let counter = built.clone();
+        let scheme = Scheme::new(Arc::new(
+            move |prefix: &str, nsp: &str, room: Option<&str>| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                ChannelFormat::new().channel(prefix, nsp, room)
+            },
+        ));
+        let clone = scheme.clone();
+        for _ in 0..3 {
+            assert_eq!("p#/#r#", &*scheme.channel("p", "/", Some("r")));
+            assert_eq!("p#/#", &*clone.channel("p", "/", None));
+            assert_eq!("q#/#r#", &*clone.channel("q", "/", Some("r")));
+        }
+        let first = scheme.channel("p", "/", Some("r"));
+        assert!(Arc::ptr_eq(&first, &clone.channel("p", "/", Some("r"))));
+        // One broadcast and one room channel per prefix.
+        assert_eq!(4, built.load(Ordering::SeqCst));
+
+        for i in 0..CACHED_ROOMS {
+            scheme.channel("p", "/", Some(&i.to_string()));
+        }
+        scheme.channel("p", "/", Some("r"));
+        assert_eq!(4 + CACHED_ROOMS + 1, built.load(Ordering::SeqCst));
+
+        built.store(0, Ordering::SeqCst);
+        for i in 0..CACHED_NAMESPACES {
+            scheme.channel("ns", &format!("/{}", i), None);
+        }
+        let namespaces: usize = scheme.cache.read().unwrap().values().map(|n| n.len()).sum();
+        assert!(namespaces <= CACHED_NAMESPACES);
+        scheme.channel("ns", "/0", None);
+        assert_eq!(CACHED_NAMESPACES + 1, built.load(Ordering::SeqCst));
+    }
+
+    /// Run with `cargo test --release channel_lookups -- --ignored` to check
+    /// that cached lookups beat formatting each channel.
+    #[test]
+    #[ignore]
+    fn channel_lookups() {
+        const LOOKUPS: u32 = 1_000_000;
+        let format = ChannelFormat::new();
+        let scheme = Scheme::new(Arc::new(format.clone()));
+        let rooms: Vec<String> = (0..64).map(|i| format!("room-{}", i)).collect();
+
+        let started = Instant::now();
+        for i in 0..LOOKUPS {
+            let room = &rooms[i as usize % rooms.len()];
+            std::hint::black_box(format.channel("socket.io", "/chat", Some(room)));
+        }
+        let formatted = started.elapsed() / LOOKUPS;
+        let started = Instant::now();
+        for i in 0..LOOKUPS {
+            let room = &rooms[i as usize % rooms.len()];
+            std::hint::black_box(scheme.channel("socket.io", "/chat", Some(room)));
+        }
+        let cached = started.elapsed() / LOOKUPS;
+        assert!(
+            cached < formatted,
+            "cached {:?} vs formatted {:?} per lookup",
+            cached,
+            formatted
+        );
+        assert!(Arc::ptr_eq(
+            &scheme.channel("socket.io", "/chat", Some(&rooms[0])),
+            &scheme.channel("socket.io", "/chat", Some(&rooms[0]))
+        ));
+    }
+}
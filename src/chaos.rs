@@ -0,0 +1,144 @@
+use crate::sampling::uniform;
+use crate::{Broker, Subscription};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+use std::io;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Wraps a broker and injects faults into its publishes, each with its own
+/// probability from 0.0 to 1.0, to test how an application degrades around
+/// the emitter. Dropped connections fail like a reset socket, so retries and
+/// failover react to them as to real ones. Enabled with the `chaos` feature;
+/// not meant for production.
+#[derive(Clone)]
+pub struct Chaos {
+    broker: Arc<dyn Broker>,
+    drops: f64,
+    errors: f64,
+    latency: f64,
+    delay: Duration,
+    state: Arc<AtomicU64>,
+}
+
+impl Chaos {
+    pub fn new<B: Broker + 'static>(broker: B) -> Chaos {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Chaos {
+            broker: Arc::new(broker),
+            drops: 0.0,
+            errors: 0.0,
+            latency: 0.0,
+            delay: Duration::ZERO,
+            state: Arc::new(AtomicU64::new(seed)),
+        }
+    }
+
+    /// Fails publishes with a dropped connection.
+    pub fn connection_drops(mut self, probability: f64) -> Chaos {
+        self.drops = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Fails publishes with an error response.
+    pub fn publish_errors(mut self, probability: f64) -> Chaos {
+        self.errors = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Delays publishes by `delay` before they go out or fail.
+    pub fn latency(mut self, probability: f64, delay: Duration) -> Chaos {
+        self.latency = probability.clamp(0.0, 1.0);
+        self.delay = delay;
+        self
+    }
+
+    /// Makes the injected faults reproducible, e.g. across CI runs.
+    pub fn seed(mut self, seed: u64) -> Chaos {
+        self.state = Arc::new(AtomicU64::new(seed));
+        self
+    }
+
+    fn happens(&self, probability: f64) -> bool {
+        probability > 0.0 && uniform(&self.state) < probability
+    }
+}
+
+impl Broker for Chaos {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        if self.happens(self.latency) {
+            thread::sleep(self.delay);
+        }
+        if self.happens(self.drops) {
+            let reset = io::Error::new(io::ErrorKind::ConnectionReset, "injected connection drop");
+            return Err(RedisError::from(reset));
+        }
+        if self.happens(self.errors) {
+            return Err(RedisError::from((
+                ErrorKind::ResponseError,
+                "injected publish error",
+            )));
+        }
+        self.broker.publish(channel, payload)
+    }
+
+    fn subscribe(&self, channels: &[String]) -> RedisResult<Box<dyn Subscription>> {
+        self.broker.subscribe(channels)
+    }
+
+    fn subscribers(&self, channel: &str) -> RedisResult<Option<usize>> {
+        self.broker.subscribers(channel)
+    }
+}
+
+impl fmt::Debug for Chaos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chaos")
+            .field("drops", &self.drops)
+            .field("errors", &self.errors)
+            .field("latency", &self.latency)
+            .field("delay", &self.delay)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode, Emitter, InMemoryBroker, Opts, Packet};
+    use std::time::Instant;
+
+    #[test]
+    fn injects_faults() {
+        let broker = InMemoryBroker::new();
+        let chaos = Chaos::new(broker.clone()).seed(7);
+        let packet = &encode(
+            "emitter",
+            &Packet::new("/", vec!["event"]),
+            &Opts::default(),
+        );
+        assert!(chaos.publish("c", packet).is_ok());
+
+        let dropped = chaos.clone().connection_drops(1.0).publish("c", packet);
+        assert!(dropped.unwrap_err().is_connection_dropped());
+        let failed = chaos.clone().publish_errors(1.0).publish("c", packet);
+        assert_eq!(ErrorKind::ResponseError, failed.unwrap_err().kind());
+
+        let slow = chaos.clone().latency(1.0, Duration::from_millis(20));
+        let started = Instant::now();
+        assert!(slow.publish("c", packet).is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(20));
+
+        let flaky = Emitter::with_broker(chaos.publish_errors(0.5)).to("r");
+        let failures = (0..200)
+            .filter(|_| flaky.send(&flaky.rooms, &["event"]).is_err())
+            .count();
+        assert!(failures > 60 && failures < 140, "{} failures", failures);
+        assert_eq!(202 - failures, broker.published().len());
+    }
+}
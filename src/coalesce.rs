@@ -0,0 +1,190 @@
+// Without threads on wasm32 the flush thread and what only they use are
+// compiled but never started.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use crate::{Emitter, Encoded};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the flushing thread sleeps at most, so it notices when every
+/// coalescer is gone.
+const IDLE: Duration = Duration::from_secs(1);
+
+/// The namespace, rooms and event of coalesced emits.
+type Key = (String, BTreeSet<String>, String);
+
+struct Pending {
+    due: Instant,
+    emitter: Emitter,
+    packets: Vec<Encoded>,
+}
+
+#[derive(Default)]
+struct Shared {
+    pending: Mutex<HashMap<Key, Pending>>,
+    wake: Condvar,
+}
+
+/// Merges emits of the same event to the same rooms: the first one opens a
+/// window, later ones replace its packet, and only the latest is published
+/// when the window closes. Meant for updates such as cursor positions where
+/// intermediate values don't matter. Clones share the pending emits.
+#[derive(Clone)]
+pub struct Coalescer {
+    window: Duration,
+    events: Arc<HashSet<String>>,
+    shared: Arc<Shared>,
+}
+
+impl Coalescer {
+    /// Coalesces every event within `window`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(window: Duration) -> Coalescer {
+        let shared = Arc::new(Shared::default());
+        let worker = Arc::downgrade(&shared);
+        thread::spawn(move || flush_due(worker));
+        Coalescer {
+            window,
+            events: Default::default(),
+            shared,
+        }
+    }
+
+    /// Only coalesces `event`, and the other events given this way.
+    pub fn event(mut self, event: &str) -> Coalescer {
+        Arc::make_mut(&mut self.events).insert(event.to_string());
+        self
+    }
+
+    pub(crate) fn coalesces(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.contains(event)
+    }
+
+    /// Keeps `packets` as the latest emit of `event` to `rooms` in `nsp`,
+    /// opening a window if none is open.
+    pub(crate) fn defer(
+        &self,
+        nsp: &str,
+        rooms: &BTreeSet<String>,
+        event: &str,
+        emitter: Emitter,
+        packets: Vec<Encoded>,
+    ) {
+        let key = (nsp.to_string(), rooms.clone(), event.to_string());
+        let mut pending = self.shared.pending.lock().unwrap();
+        let due = match pending.get(&key) {
+            Some(open) => open.due,
+            None => Instant::now() + self.window,
+        };
+        pending.insert(
+            key,
+            Pending {
+                due,
+                emitter,
+                packets,
+            },
+        );
+        self.shared.wake.notify_one();
+    }
+
+    /// Publishes every pending emit now, e.g. before shutting down.
+    pub fn flush(&self) {
+        let pending: Vec<_> = self.shared.pending.lock().unwrap().drain().collect();
+        publish(pending.into_iter().map(|(_, pending)| pending));
+    }
+}
+
+/// Publishes pending emits as their windows close, until every coalescer
+/// sharing them is dropped.
+fn flush_due(shared: Weak<Shared>) {
+    while let Some(shared) = shared.upgrade() {
+        let mut pending = shared.pending.lock().unwrap();
+        let now = Instant::now();
+        let next = pending.values().map(|p| p.due).min();
+        let wait = match next {
+            Some(due) if due <= now => Duration::ZERO,
+            Some(due) => (due - now).min(IDLE),
+            None => IDLE,
+        };
+        if !wait.is_zero() {
+            drop(shared.wake.wait_timeout(pending, wait).unwrap());
+            continue;
+        }
+        let due: Vec<Key> = pending
+            .iter()
+            .filter(|(_, p)| p.due <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        let due: Vec<Pending> = due.iter().filter_map(|key| pending.remove(key)).collect();
+        drop(pending);
+        publish(due.into_iter());
+    }
+}
+
+fn publish<I: Iterator<Item = Pending>>(pending: I) {
+    for Pending {
+        emitter, packets, ..
+    } in pending
+    {
+        for _result in emitter.publish_packets(&packets) {
+            #[cfg(feature = "logging")]
+            if let Err(e) = _result {
+                log::warn!("dropped coalesced emit: {}", e);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Coalescer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Coalescer")
+            .field("window", &self.window)
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coalescer;
+    use crate::{Emitter, InMemoryBroker};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn publishes_latest_in_window() {
+        let broker = InMemoryBroker::new();
+        let coalescer = Coalescer::new(Duration::from_millis(50)).event("cursor");
+        let io = Emitter::with_broker(broker.clone()).coalesce(coalescer.clone());
+        for x in &["1", "2", "3"] {
+            io.clone().to("a").emit(vec!["cursor", x]);
+        }
+        io.clone().to("b").emit(vec!["cursor", "9"]);
+        io.clone().to("a").emit(vec!["message"]);
+        assert_eq!(vec!["message"], broker.events());
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while broker.published().len() < 3 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let mut cursors: Vec<_> = broker.published()[1..]
+            .iter()
+            .map(|p| (p.opts.rooms()[0].clone(), p.packet.data()[1].clone()))
+            .collect();
+        cursors.sort();
+        assert_eq!(
+            vec![
+                ("a".to_string(), "3".to_string()),
+                ("b".to_string(), "9".to_string())
+            ],
+            cursors
+        );
+
+        io.to("a").emit(vec!["cursor", "4"]);
+        coalescer.flush();
+        assert_eq!(4, broker.published().len());
+    }
+}
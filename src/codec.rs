@@ -0,0 +1,384 @@
+use crate::{Opts, Packet};
+use rmp_serde::{Deserializer, Serializer};
+use serde::{Deserialize, Serialize};
+
+/// Encodes `(uid, packet, opts)` into the exact bytes the emitter publishes.
+pub fn encode(uid: &str, packet: &Packet, opts: &Opts) -> Vec<u8> {
+    let mut payload = Vec::new();
+    encode_into(&mut payload, uid, packet, opts);
+    payload
+}
+
+/// Encodes into `payload`, from `Packet` and `Opts` or their borrowed views.
+pub(crate) fn encode_into<W: std::io::Write, P: Serialize, O: Serialize>(
+    payload: &mut W,
+    uid: &str,
+    packet: &P,
+    opts: &O,
+) {
+    (uid, packet, opts)
+        .serialize(&mut Serializer::new(payload).with_struct_map())
+        .unwrap();
+}
+
+/// Decodes a published payload back into its `(uid, packet, opts)` parts.
+pub fn decode(payload: &[u8]) -> Result<(String, Packet, Opts), rmp_serde::decode::Error> {
+    let mut de = Deserializer::new(payload);
+    Deserialize::deserialize(&mut de)
+}
+
+/// How deeply arrays and maps may nest in a payload given to `pretty`.
+const MAX_DEPTH: usize = 128;
+
+/// Renders any msgpack payload, e.g. one read off a Redis channel, as
+/// JSON-like text. Returns `None` if the payload is malformed or nests
+/// deeper than 128 levels.
+pub fn pretty(payload: &[u8]) -> Option<String> {
+    let mut out = String::new();
+    let mut reader = Reader {
+        buf: payload,
+        depth: 0,
+    };
+    reader.value(&mut out)?;
+    if !reader.buf.is_empty() {
+        return None;
+    }
+    Some(out)
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Some(head)
+    }
+
+    fn uint(&mut self, n: usize) -> Option<u64> {
+        Some(
+            self.take(n)?
+                .iter()
+                .fold(0, |acc, b| acc << 8 | u64::from(*b)),
+        )
+    }
+
+    fn value(&mut self, out: &mut String) -> Option<()> {
+        let marker = self.take(1)?[0];
+        match marker {
+            0x00..=0x7f => out.push_str(&marker.to_string()),
+            0x80..=0x8f => self.map(out, usize::from(marker & 0x0f))?,
+            0x90..=0x9f => self.array(out, usize::from(marker & 0x0f))?,
+            0xa0..=0xbf => self.str(out, usize::from(marker & 0x1f))?,
+            0xc0 => out.push_str("null"),
+            0xc2 => out.push_str("false"),
+            0xc3 => out.push_str("true"),
+            0xc4..=0xc6 => {
+                let len = self.uint(1 << (marker - 0xc4))? as usize;
+                self.take(len)?;
+                out.push_str(&format!("<{} bytes>", len));
+            }
+            0xc7..=0xc9 => {
+                let len = self.uint(1 << (marker - 0xc7))? as usize;
+                self.ext(out, len)?
+            }
+            0xca => {
+                let bits = self.uint(4)? as u32;
+                out.push_str(&f32::from_bits(bits).to_string())
+            }
+            0xcb => out.push_str(&f64::from_bits(self.uint(8)?).to_string()),
+            0xcc..=0xcf => out.push_str(&self.uint(1 << (marker - 0xcc))?.to_string()),
+            0xd0..=0xd3 => {
+                let n = 1 << (marker - 0xd0);
+                let value = self.uint(n)?;
+                let shift = 64 - 8 * n as u32;
+                out.push_str(&(((value << shift) as i64) >> shift).to_string())
+            }
+            0xd4..=0xd8 => self.ext(out, 1 << (marker - 0xd4))?,
+            0xd9..=0xdb => {
+                let len = self.uint(1 << (marker - 0xd9))? as usize;
+                self.str(out, len)?
+            }
+            0xdc | 0xdd => {
+                let len = self.uint(2 << (marker - 0xdc))? as usize;
+                self.array(out, len)?
+            }
+            0xde | 0xdf => {
+                let len = self.uint(2 << (marker - 0xde))? as usize;
+                self.map(out, len)?
+            }
+            0xe0..=0xff => out.push_str(&(marker as i8).to_string()),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn str(&mut self, out: &mut String, len: usize) -> Option<()> {
+        let s = std::str::from_utf8(self.take(len)?).ok()?;
+        out.push_str(&format!("{:?}", s));
+        Some(())
+    }
+
+    fn ext(&mut self, out: &mut String, len: usize) -> Option<()> {
+        let ext_type = self.take(1)?[0] as i8;
+        self.take(len)?;
+        out.push_str(&format!("<ext {}, {} bytes>", ext_type, len));
+        Some(())
+    }
+
+    fn array(&mut self, out: &mut String, len: usize) -> Option<()> {
+        self.nested(|reader| {
+            out.push('[');
+            for i in 0..len {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                reader.value(out)?;
+            }
+            out.push(']');
+            Some(())
+        })
+    }
+
+    fn map(&mut self, out: &mut String, len: usize) -> Option<()> {
+        self.nested(|reader| {
+            out.push('{');
+            for i in 0..len {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                reader.value(out)?;
+                out.push_str(": ");
+                reader.value(out)?;
+            }
+            out.push('}');
+            Some(())
+        })
+    }
+
+    /// Reads the contents of an array or map one level deeper.
+    fn nested(&mut self, read: impl FnOnce(&mut Self) -> Option<()>) -> Option<()> {
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+}
+
+/// Returns a context with the remote span the packet was emitted in as its
+/// parent, if the emitter propagated one.
+#[cfg(feature = "opentelemetry")]
+pub fn extract_context(opts: &Opts) -> Option<opentelemetry::Context> {
+    use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId};
+
+    let mut parts = opts.traceparent()?.split('-');
+    let (version, trace_id, span_id, flags) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    if version != "00" || parts.next().is_some() {
+        return None;
+    }
+    let span_context = SpanContext::new(
+        TraceId::from_hex(trace_id).ok()?,
+        SpanId::from_hex(span_id).ok()?,
+        TraceFlags::new(u8::from_str_radix(flags, 16).ok()?),
+        true,
+        Default::default(),
+    );
+    if !span_context.is_valid() {
+        return None;
+    }
+    Some(opentelemetry::Context::new().with_remote_span_context(span_context))
+}
+
+/// The `traceparent` of the current OpenTelemetry span, if there is one.
+#[cfg(feature = "opentelemetry")]
+pub(crate) fn current_traceparent() -> Option<String> {
+    use opentelemetry::trace::TraceContextExt;
+
+    let context = opentelemetry::Context::current();
+    let span = context.span();
+    let span_context = span.span_context();
+    if !span_context.is_valid() {
+        return None;
+    }
+    Some(format!(
+        "00-{:032x}-{:016x}-{:02x}",
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags().to_u8()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, encode_into, pretty};
+    use crate::{Broker, Emitter, InMemoryBroker, Opts, Packet};
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn metadata_round_trip() {
+        #[derive(Serialize)]
+        struct Hint {
+            shard: u32,
+        }
+
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .metadata("routing", &Hint { shard: 3 })
+            .metadata("priority", "high")
+            .emit(vec!["test"])
+            .emit(vec!["plain"]);
+
+        let published = broker.published();
+        let metadata = published[0].opts.metadata();
+        assert_eq!(Some("high"), metadata["priority"].as_str());
+        assert_eq!(
+            Some(&rmpv::Value::from(3)),
+            metadata["routing"].as_map().map(|m| &m[0].1)
+        );
+        assert!(published[1].opts.metadata().is_empty());
+
+        let mut opts = BTreeMap::new();
+        opts.insert("rooms", rmpv::Value::Array(vec![]));
+        opts.insert("flags", rmpv::Value::Map(vec![]));
+        opts.insert("future", rmpv::Value::from("kept"));
+        let packet = Packet {
+            _type: 2,
+            data: vec![],
+            nsp: "/".to_string(),
+        };
+        // Opts with a field this version doesn't know.
+        let mut payload = Vec::new();
+        encode_into(&mut payload, "emitter", &packet, &opts);
+        let (_, _, decoded) = decode(&payload).unwrap();
+        assert_eq!(Some("kept"), decoded.metadata()["future"].as_str());
+
+        let reencoded = encode("emitter", &packet, &decoded);
+        assert_eq!(decoded, decode(&reencoded).unwrap().2);
+        assert!(pretty(&reencoded).unwrap().contains(r#""future": "kept""#));
+    }
+
+    #[test]
+    fn pretty_payload() {
+        let packet = Packet {
+            _type: 2,
+            data: vec!["test".to_string(), "say \"hi\"".to_string()],
+            nsp: "/".to_string(),
+        };
+        let opts = Opts {
+            rooms: vec!["room".to_string()],
+            ..Default::default()
+        };
+        let payload = encode("emitter", &packet, &opts);
+
+        assert_eq!(
+            Some(r#"["emitter", {"type": 2, "data": ["test", "say \"hi\""], "nsp": "/"}, {"rooms": ["room"], "flags": {}}]"#.to_string()),
+            pretty(&payload)
+        );
+        assert_eq!(
+            r#"{"type": 2, "data": ["test", "say \"hi\""], "nsp": "/"}"#,
+            packet.to_pretty_string()
+        );
+        assert_eq!(
+            Some("[-1, -200, 300, 1.5, null, true, <3 bytes>]".to_string()),
+            pretty(&[
+                0x97, 0xff, 0xd1, 0xff, 0x38, 0xcd, 0x01, 0x2c, 0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0,
+                0xc0, 0xc3, 0xc4, 3, 1, 2, 3
+            ])
+        );
+        assert_eq!(None, pretty(&payload[..payload.len() - 1]));
+
+        let mut nested = vec![0x91; 128];
+        nested.push(0xc0);
+        let expected = format!("{}null{}", "[".repeat(128), "]".repeat(128));
+        assert_eq!(Some(expected), pretty(&nested));
+        let mut nested = vec![0x91; 100_000];
+        nested.push(0xc0);
+        assert_eq!(None, pretty(&nested));
+    }
+
+    struct Payloads(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl Broker for Payloads {
+        fn publish(&self, _channel: &str, payload: &[u8]) -> redis::RedisResult<()> {
+            self.0.lock().unwrap().push(payload.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn encode_published_bytes() {
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let io = Emitter::with_broker(Payloads(published.clone())).uid("svc");
+        io.clone().to("room").emit(vec!["test", "hi"]);
+
+        let packet = Packet::new("/", vec!["test", "hi"]);
+        let mut opts = Opts::default();
+        opts.rooms_mut().push("room".to_string());
+        let payload = encode("svc", &packet, &opts);
+        assert_eq!(vec![payload.clone()], *published.lock().unwrap());
+        assert_eq!(("svc".to_string(), packet, opts), decode(&payload).unwrap());
+
+        published.lock().unwrap().clear();
+        io.volatile()
+            .metadata("priority", "high")
+            .correlation_id(|| Some("req-1".to_string()))
+            .to(vec!["a", "b"])
+            .emit(vec!["test"]);
+        let (uid, packet, opts) = decode(&published.lock().unwrap()[0]).unwrap();
+        assert_eq!(Some("req-1"), opts.correlation_id());
+        assert_eq!(published.lock().unwrap()[0], encode(&uid, &packet, &opts));
+    }
+}
+
+#[cfg(all(test, feature = "opentelemetry"))]
+mod otel_tests {
+    use super::extract_context;
+    use crate::{decode, encode, Emitter, InMemoryBroker};
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::Context;
+
+    #[test]
+    fn propagates_trace_context() {
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::NONE,
+        );
+        let broker = InMemoryBroker::new();
+        {
+            let _guard = Context::new()
+                .with_remote_span_context(span_context.clone())
+                .attach();
+            Emitter::with_broker(broker.clone()).emit(vec!["test"]);
+        }
+        Emitter::with_broker(broker.clone()).emit(vec!["test"]);
+
+        let published = broker.published();
+        assert_eq!(
+            Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            published[0].opts.traceparent()
+        );
+        let context = extract_context(&published[0].opts).unwrap();
+        assert_eq!(&span_context, context.span().span_context());
+        assert_eq!(None, published[1].opts.traceparent());
+
+        let payload = encode("emitter", &published[1].packet, &published[1].opts);
+        assert!(decode(&payload).is_ok());
+    }
+}
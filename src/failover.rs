@@ -0,0 +1,173 @@
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// An ordered list of Redis endpoints. Publishes go to the first healthy
+/// endpoint; once an endpoint is failed over, the higher-priority ones are
+/// probed again every `probe_interval` so the emitter can fail back.
+#[derive(Debug, Clone)]
+pub struct Failover {
+    pub(crate) endpoints: Vec<redis::Client>,
+    retries: u32,
+    backoff: Duration,
+    probe_interval: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    active: usize,
+    last_probe: Instant,
+}
+
+impl Failover {
+    pub fn new(urls: &[&str]) -> RedisResult<Failover> {
+        let endpoints = urls
+            .iter()
+            .map(|url| redis::Client::open(*url))
+            .collect::<RedisResult<Vec<_>>>()?;
+        Failover::from_clients(endpoints)
+    }
+
+    pub fn from_clients(endpoints: Vec<redis::Client>) -> RedisResult<Failover> {
+        if endpoints.is_empty() {
+            return Err(RedisError::from((
+                ErrorKind::InvalidClientConfig,
+                "failover requires at least one endpoint",
+            )));
+        }
+        Ok(Failover {
+            endpoints,
+            retries: 0,
+            backoff: Duration::from_millis(50),
+            probe_interval: Duration::from_secs(30),
+            state: Arc::new(Mutex::new(State {
+                active: 0,
+                last_probe: Instant::now(),
+            })),
+        })
+    }
+
+    /// Number of additional attempts against an endpoint before moving on to
+    /// the next one.
+    pub fn retries(mut self, retries: u32) -> Failover {
+        self.retries = retries;
+        self
+    }
+
+    /// The wait before the first retry against an endpoint, doubled for each
+    /// further retry. 50ms by default.
+    pub fn backoff(mut self, backoff: Duration) -> Failover {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn probe_interval(mut self, interval: Duration) -> Failover {
+        self.probe_interval = interval;
+        self
+    }
+
+    pub fn active(&self) -> usize {
+        self.state.lock().unwrap().active
+    }
+
+    /// Runs `attempt` against the active endpoint, then against the others
+    /// in order, wrapping around, until one succeeds. The state is only
+    /// locked to pick and switch endpoints, never across an attempt.
+    pub(crate) fn run<T, F>(&self, attempt: F) -> RedisResult<T>
+    where
+        F: Fn(&redis::Client) -> RedisResult<T>,
+    {
+        let start = {
+            let mut state = self.state.lock().unwrap();
+            if state.active > 0 && state.last_probe.elapsed() >= self.probe_interval {
+                state.last_probe = Instant::now();
+                0
+            } else {
+                state.active
+            }
+        };
+
+        let mut last_err = None;
+        let count = self.endpoints.len();
+        for i in (start..count).chain(0..start) {
+            for retry in 0..=self.retries {
+                if retry > 0 {
+                    thread::sleep(self.backoff * 2u32.saturating_pow(retry - 1));
+                }
+                match attempt(&self.endpoints[i]) {
+                    Ok(value) => {
+                        self.switch_to(i);
+                        return Ok(value);
+                    }
+                    Err(e) => {
+                        #[cfg(feature = "logging")]
+                        log::warn!("attempt against endpoint {} failed: {}", i, e);
+                        last_err = Some(e)
+                    }
+                }
+            }
+        }
+        Err(last_err.unwrap())
+    }
+
+    fn switch_to(&self, endpoint: usize) {
+        let mut state = self.state.lock().unwrap();
+        if state.active != endpoint {
+            #[cfg(feature = "tracing")]
+            tracing::info!(endpoint, "switched active endpoint");
+            #[cfg(feature = "logging")]
+            log::info!("switched active endpoint to {}", endpoint);
+            state.active = endpoint;
+            state.last_probe = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Failover;
+    use redis::{ErrorKind, RedisError};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn requires_an_endpoint() {
+        assert!(Failover::new(&[]).is_err());
+        assert!(Failover::from_clients(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn wraps_around_endpoints() {
+        let failover = Failover::new(&["redis://127.0.0.1:1", "redis://127.0.0.1:2"])
+            .unwrap()
+            .retries(2)
+            .backoff(Duration::from_millis(10));
+        let addr = |client: &redis::Client| client.get_connection_info().addr.to_string();
+        let first_down = AtomicBool::new(true);
+        let attempt = |client: &redis::Client| {
+            // The state must not be locked while attempting.
+            failover.active();
+            match (
+                addr(client).ends_with(":1"),
+                first_down.load(Ordering::SeqCst),
+            ) {
+                (true, true) | (false, false) => {
+                    Err(RedisError::from((ErrorKind::IoError, "down")))
+                }
+                _ => Ok(addr(client)),
+            }
+        };
+
+        let started = Instant::now();
+        assert_eq!("127.0.0.1:2", failover.run(attempt).unwrap());
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        assert_eq!(1, failover.active());
+
+        // Endpoint 0 recovered and 1 went down before the next probe.
+        first_down.store(false, Ordering::SeqCst);
+        assert_eq!("127.0.0.1:1", failover.run(attempt).unwrap());
+        assert_eq!(0, failover.active());
+    }
+}
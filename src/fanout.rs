@@ -0,0 +1,144 @@
+use crate::{Broker, Subscription};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+use std::sync::Arc;
+
+/// What a failed publish to one of the `FanOut` backends does to the emit.
+#[derive(Clone)]
+pub enum ErrorPolicy {
+    /// The error is returned after the remaining backends have been tried.
+    Fail,
+    /// The error is dropped.
+    Ignore,
+    /// The error is passed to the handler and otherwise dropped.
+    Report(Arc<dyn Fn(&RedisError) + Send + Sync>),
+}
+
+impl ErrorPolicy {
+    pub fn report<F>(handler: F) -> ErrorPolicy
+    where
+        F: Fn(&RedisError) + Send + Sync + 'static,
+    {
+        ErrorPolicy::Report(Arc::new(handler))
+    }
+}
+
+impl fmt::Debug for ErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorPolicy::Fail => f.write_str("Fail"),
+            ErrorPolicy::Ignore => f.write_str("Ignore"),
+            ErrorPolicy::Report(_) => f.write_str("Report"),
+        }
+    }
+}
+
+/// Publishes every packet to all of its backends in order, e.g. Redis for
+/// delivery plus a Kafka topic as an audit archive.
+#[derive(Clone, Default)]
+pub struct FanOut {
+    backends: Vec<(Arc<dyn Broker>, ErrorPolicy)>,
+}
+
+impl FanOut {
+    pub fn new() -> FanOut {
+        FanOut::default()
+    }
+
+    /// Adds a backend whose errors fail the emit.
+    pub fn backend<B: Broker + 'static>(self, broker: B) -> FanOut {
+        self.backend_with(broker, ErrorPolicy::Fail)
+    }
+
+    pub fn backend_with<B: Broker + 'static>(mut self, broker: B, policy: ErrorPolicy) -> FanOut {
+        self.backends.push((Arc::new(broker), policy));
+        self
+    }
+}
+
+impl Broker for FanOut {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let mut result = Ok(());
+        for (broker, policy) in &self.backends {
+            if let Err(e) = broker.publish(channel, payload) {
+                match policy {
+                    ErrorPolicy::Fail if result.is_ok() => result = Err(e),
+                    ErrorPolicy::Fail => {}
+                    ErrorPolicy::Ignore => {
+                        #[cfg(feature = "logging")]
+                        log::debug!("dropped packet for {}: {}", channel, e);
+                    }
+                    ErrorPolicy::Report(handler) => handler(&e),
+                }
+            }
+        }
+        result
+    }
+
+    /// Subscribes through the first backend.
+    fn subscribe(&self, channels: &[String]) -> RedisResult<Box<dyn Subscription>> {
+        match self.backends.first() {
+            Some((broker, _)) => broker.subscribe(channels),
+            None => Err(RedisError::from((
+                ErrorKind::ClientError,
+                "fan-out has no backends",
+            ))),
+        }
+    }
+}
+
+impl fmt::Debug for FanOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let policies: Vec<_> = self.backends.iter().map(|(_, policy)| policy).collect();
+        f.debug_struct("FanOut")
+            .field("backends", &policies)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorPolicy, FanOut};
+    use crate::{encode, Broker, Emitter, InMemoryBroker};
+    use redis::{ErrorKind, RedisError, RedisResult};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Failing;
+
+    impl Broker for Failing {
+        fn publish(&self, _: &str, _: &[u8]) -> RedisResult<()> {
+            Err(RedisError::from((ErrorKind::IoError, "down")))
+        }
+    }
+
+    #[test]
+    fn error_policies() {
+        let memory = InMemoryBroker::new();
+        let reported = Arc::new(AtomicUsize::new(0));
+        let counter = reported.clone();
+        let fanout = FanOut::new()
+            .backend_with(Failing, ErrorPolicy::Ignore)
+            .backend_with(
+                Failing,
+                ErrorPolicy::report(move |_| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }),
+            )
+            .backend(memory.clone());
+        Emitter::with_broker(fanout.clone()).emit(vec!["test"]);
+        assert_eq!(1, reported.load(Ordering::SeqCst));
+        assert_eq!(1, memory.published().len());
+
+        let published = memory.published()[0].clone();
+        let channel = published.channel.clone();
+        let fanout = fanout.backend(Failing).backend(memory.clone());
+        assert!(fanout
+            .publish(
+                &channel,
+                &encode(&published.uid, &published.packet, &published.opts)
+            )
+            .is_err());
+        assert_eq!(3, memory.published().len());
+    }
+}
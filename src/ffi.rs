@@ -0,0 +1,276 @@
+//! A C interface, see `include/socketio_emitter.h`. Functions returning
+//! `int` return 0 on success and -1 on failure, with the reason available
+//! from `sio_last_error`. Panics are caught and reported as failures
+//! instead of unwinding into C. Build the shared library with
+//! `cargo rustc --release --features ffi --crate-type cdylib`.
+
+use crate::Emitter;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An emitter and the rooms selected for the next emits.
+pub struct SioEmitter {
+    emitter: Emitter,
+    rooms: Vec<String>,
+}
+
+fn set_error<E: ToString>(e: E) {
+    let message = CString::new(e.to_string().replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+unsafe fn string<'a>(s: *const c_char, name: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{} is null", name));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| format!("{} is not valid UTF-8", name))
+}
+
+fn status(result: Result<(), String>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Runs `f`, returning `failed` with the panic message as the last error if
+/// it panics.
+fn guard<T, F: FnOnce() -> T>(failed: T, f: F) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        set_error(format!("panicked: {}", message));
+        failed
+    })
+}
+
+/// Creates an emitter publishing through the Redis server at `url`, e.g.
+/// `redis://127.0.0.1/`, or returns null on failure.
+///
+/// # Safety
+///
+/// `url` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sio_emitter_new(url: *const c_char) -> *mut SioEmitter {
+    guard(ptr::null_mut(), || {
+        let emitter = string(url, "url").and_then(|url| {
+            Emitter::builder()
+                .endpoint(url)
+                .build()
+                .map_err(|e| e.to_string())
+        });
+        match emitter {
+            Ok(emitter) => Box::into_raw(Box::new(SioEmitter {
+                emitter,
+                rooms: Vec::new(),
+            })),
+            Err(e) => {
+                set_error(e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Frees an emitter created by `sio_emitter_new`.
+///
+/// # Safety
+///
+/// `emitter` must be null or returned by `sio_emitter_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn sio_emitter_free(emitter: *mut SioEmitter) {
+    if !emitter.is_null() {
+        guard((), || drop(Box::from_raw(emitter)));
+    }
+}
+
+/// Emits to `nsp` from now on, clearing the selected rooms.
+///
+/// # Safety
+///
+/// `emitter` must be null or a live emitter, and `nsp` null or a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sio_emitter_set_namespace(
+    emitter: *mut SioEmitter,
+    nsp: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        status(with(emitter, |handle| {
+            let nsp = string(nsp, "nsp")?;
+            handle.emitter = handle.emitter.clone().of(nsp);
+            handle.rooms.clear();
+            Ok(())
+        }))
+    })
+}
+
+/// Adds `room` to the rooms the next emits go to.
+///
+/// # Safety
+///
+/// As for `sio_emitter_set_namespace`.
+#[no_mangle]
+pub unsafe extern "C" fn sio_emitter_add_room(
+    emitter: *mut SioEmitter,
+    room: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        status(with(emitter, |handle| {
+            let room = string(room, "room")?;
+            handle.rooms.push(room.to_string());
+            Ok(())
+        }))
+    })
+}
+
+/// Goes back to emitting to every socket in the namespace.
+///
+/// # Safety
+///
+/// `emitter` must be null or a live emitter.
+#[no_mangle]
+pub unsafe extern "C" fn sio_emitter_clear_rooms(emitter: *mut SioEmitter) -> c_int {
+    guard(-1, || {
+        status(with(emitter, |handle| {
+            handle.rooms.clear();
+            Ok(())
+        }))
+    })
+}
+
+/// Emits `event` with `json` as its argument to the selected rooms; `json`
+/// may be null for an event without arguments.
+///
+/// # Safety
+///
+/// `emitter` must be null or a live emitter, and `event` and `json` null or
+/// NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn sio_emitter_emit_json(
+    emitter: *mut SioEmitter,
+    event: *const c_char,
+    json: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        status(with(emitter, |handle| {
+            let event = string(event, "event")?;
+            let args = if json.is_null() {
+                vec![]
+            } else {
+                vec![string(json, "json")?]
+            };
+            let rooms: Vec<&str> = handle.rooms.iter().map(String::as_str).collect();
+            handle
+                .emitter
+                .emit_batch(vec![(event, args, rooms)])
+                .into_iter()
+                .collect::<Result<(), _>>()
+                .map_err(|e| e.to_string())
+        }))
+    })
+}
+
+/// The reason the last failing call on this thread failed, or null. The
+/// string stays valid until the next failing call on the thread.
+#[no_mangle]
+pub extern "C" fn sio_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|last| {
+            last.borrow()
+                .as_ref()
+                .map_or(ptr::null(), |message| message.as_ptr())
+        })
+    })
+}
+
+unsafe fn with<F>(emitter: *mut SioEmitter, f: F) -> Result<(), String>
+where
+    F: FnOnce(&mut SioEmitter) -> Result<(), String>,
+{
+    match emitter.as_mut() {
+        Some(handle) => f(handle),
+        None => Err("emitter is null".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Broker, InMemoryBroker};
+
+    #[test]
+    fn c_interface() {
+        unsafe {
+            assert!(sio_emitter_new(ptr::null()).is_null());
+            assert_eq!(
+                "url is null",
+                CStr::from_ptr(sio_last_error()).to_str().unwrap()
+            );
+            let url = CString::new("redis://127.0.0.1:1/").unwrap();
+            let emitter = sio_emitter_new(url.as_ptr());
+            assert!(!emitter.is_null());
+
+            let broker = InMemoryBroker::new();
+            (*emitter).emitter = Emitter::with_broker(broker.clone());
+            let nsp = CString::new("/game").unwrap();
+            let room = CString::new("match:1").unwrap();
+            let event = CString::new("score").unwrap();
+            let json = CString::new(r#"{"home":2}"#).unwrap();
+            assert_eq!(0, sio_emitter_set_namespace(emitter, nsp.as_ptr()));
+            assert_eq!(0, sio_emitter_add_room(emitter, room.as_ptr()));
+            assert_eq!(
+                0,
+                sio_emitter_emit_json(emitter, event.as_ptr(), json.as_ptr())
+            );
+            assert_eq!(0, sio_emitter_clear_rooms(emitter));
+            assert_eq!(
+                0,
+                sio_emitter_emit_json(emitter, event.as_ptr(), ptr::null())
+            );
+            assert_eq!(-1, sio_emitter_emit_json(emitter, ptr::null(), ptr::null()));
+            assert_eq!(-1, sio_emitter_add_room(ptr::null_mut(), room.as_ptr()));
+            sio_emitter_free(emitter);
+
+            let published = broker.published();
+            assert_eq!("socket.io#/game#match:1#", published[0].channel);
+            assert_eq!(&["score", r#"{"home":2}"#], published[0].packet.data());
+            assert_eq!("socket.io#/game#", published[1].channel);
+
+            let emitter = sio_emitter_new(url.as_ptr());
+            (*emitter).emitter = Emitter::with_broker(Panicking);
+            assert_eq!(
+                -1,
+                sio_emitter_emit_json(emitter, event.as_ptr(), ptr::null())
+            );
+            assert_eq!(
+                "panicked: broker exploded",
+                CStr::from_ptr(sio_last_error()).to_str().unwrap()
+            );
+            sio_emitter_free(emitter);
+        }
+    }
+
+    struct Panicking;
+
+    impl Broker for Panicking {
+        fn publish(&self, _channel: &str, _payload: &[u8]) -> redis::RedisResult<()> {
+            panic!("broker exploded")
+        }
+    }
+}
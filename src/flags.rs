@@ -0,0 +1,76 @@
+use bitflags::bitflags;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::HashMap;
+
+bitflags! {
+    /// The flags an emitter sets on its packets, put on the wire as a map of
+    /// the set flags to `true`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub(crate) struct Flags: u8 {
+        const JSON = 1;
+        const VOLATILE = 1 << 1;
+        const BROADCAST = 1 << 2;
+    }
+}
+
+impl Flags {
+    const NAMES: [(Flags, &'static str); 3] = [
+        (Flags::JSON, "json"),
+        (Flags::VOLATILE, "volatile"),
+        (Flags::BROADCAST, "broadcast"),
+    ];
+
+    fn entries(self) -> impl Iterator<Item = &'static str> {
+        Flags::NAMES
+            .iter()
+            .filter(move |(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+    }
+
+    /// The flags as they are decoded into `Opts`.
+    pub(crate) fn to_map(self) -> HashMap<String, bool> {
+        self.entries()
+            .map(|name| (name.to_string(), true))
+            .collect()
+    }
+}
+
+impl Serialize for Flags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.bits().count_ones() as usize))?;
+        for name in self.entries() {
+            map.serialize_entry(name, &true)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Flags;
+    use crate::{Emitter, InMemoryBroker};
+    use rmp_serde::Serializer;
+    use serde::Serialize;
+    use std::collections::HashMap;
+
+    #[test]
+    fn same_wire_map() {
+        for flags in [
+            Flags::empty(),
+            Flags::VOLATILE,
+            Flags::JSON | Flags::BROADCAST,
+        ] {
+            let mut payload = Vec::new();
+            flags.serialize(&mut Serializer::new(&mut payload)).unwrap();
+            let decoded: HashMap<String, bool> = rmp_serde::from_slice(&payload).unwrap();
+            assert_eq!(flags.to_map(), decoded);
+        }
+
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .volatile()
+            .emit(vec!["test"]);
+        let published = broker.published();
+        assert_eq!(Flags::VOLATILE.to_map(), *published[0].opts.flags());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::Broker;
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+use std::future::Future;
+use std::sync::{mpsc, Arc};
+use tokio::runtime::Runtime;
+use tonic::codec::ProstCodec;
+use tonic::transport::{Channel, Endpoint};
+
+const PUBLISH_PATH: &str = "/socketio.emitter.v1.EmitterGateway/Publish";
+
+/// `PublishRequest` from `proto/emitter.proto`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PublishRequest {
+    #[prost(string, tag = "1")]
+    pub channel: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub payload: Vec<u8>,
+}
+
+/// `PublishResponse` from `proto/emitter.proto`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PublishResponse {}
+
+/// Sends packets to an `EmitterGateway` gRPC service (see
+/// `proto/emitter.proto`), which performs the actual broadcast. Requests
+/// run on a worker thread of the broker's own runtime, so the broker works
+/// both in plain threads and inside another Tokio runtime.
+#[derive(Clone)]
+pub struct GrpcBroker {
+    channel: Channel,
+    runtime: Arc<Background>,
+}
+
+/// A runtime that can be dropped from async code, which `Runtime` itself
+/// panics on.
+struct Background(Option<Runtime>);
+
+impl Background {
+    /// Runs `future` on the runtime, blocking the calling thread until it
+    /// completes.
+    fn run<F, T>(&self, future: F) -> RedisResult<T>
+    where
+        F: Future<Output = RedisResult<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (done, result) = mpsc::channel();
+        self.0.as_ref().unwrap().spawn(async move {
+            let _ = done.send(future.await);
+        });
+        result.recv().map_err(grpc_error)?
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl GrpcBroker {
+    pub fn connect(url: &str) -> RedisResult<GrpcBroker> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("grpc-broker")
+            .enable_all()
+            .build()?;
+        let runtime = Background(Some(runtime));
+        let endpoint = Endpoint::from_shared(url.to_string()).map_err(grpc_error)?;
+        let channel = runtime.run(async move { endpoint.connect().await.map_err(grpc_error) })?;
+        Ok(GrpcBroker {
+            channel,
+            runtime: Arc::new(runtime),
+        })
+    }
+}
+
+impl Broker for GrpcBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let request = PublishRequest {
+            channel: channel.to_string(),
+            payload: payload.to_vec(),
+        };
+        let mut grpc = tonic::client::Grpc::new(self.channel.clone());
+        self.runtime
+            .run(async move {
+                grpc.ready().await.map_err(grpc_error)?;
+                grpc.unary::<_, PublishResponse, _>(
+                    tonic::Request::new(request),
+                    http::uri::PathAndQuery::from_static(PUBLISH_PATH),
+                    ProstCodec::default(),
+                )
+                .await
+                .map_err(grpc_error)
+            })
+            .map(|_| ())
+    }
+}
+
+impl fmt::Debug for GrpcBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GrpcBroker").finish()
+    }
+}
+
+fn grpc_error<E: fmt::Display>(e: E) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "gRPC error", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PublishRequest, PublishResponse};
+    use crate::{Emitter, GrpcBroker};
+    use std::convert::Infallible;
+    use std::future::{ready, Future, Ready};
+    use std::pin::Pin;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::task::{Context, Poll};
+    use tonic::body::BoxBody;
+    use tonic::codec::ProstCodec;
+
+    type Published = Arc<Mutex<Vec<PublishRequest>>>;
+
+    /// The `EmitterGateway` service, recording what it was sent.
+    #[derive(Clone)]
+    struct Gateway(Published);
+
+    impl tonic::server::NamedService for Gateway {
+        const NAME: &'static str = "socketio.emitter.v1.EmitterGateway";
+    }
+
+    impl tonic::server::UnaryService<PublishRequest> for Gateway {
+        type Response = PublishResponse;
+        type Future = Ready<Result<tonic::Response<PublishResponse>, tonic::Status>>;
+
+        fn call(&mut self, request: tonic::Request<PublishRequest>) -> Self::Future {
+            self.0.lock().unwrap().push(request.into_inner());
+            ready(Ok(tonic::Response::new(PublishResponse {})))
+        }
+    }
+
+    impl tower::Service<http::Request<BoxBody>> for Gateway {
+        type Response = http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+            let gateway = self.clone();
+            Box::pin(async move {
+                let mut grpc = tonic::server::Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(gateway, request).await)
+            })
+        }
+    }
+
+    /// Serves the gateway on its own thread and runtime, returning its URL.
+    fn serve(published: Published) -> String {
+        let (bound, addr) = mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                bound.send(listener.local_addr().unwrap()).unwrap();
+                let incoming =
+                    tonic::transport::server::TcpIncoming::from_listener(listener, true, None)
+                        .unwrap();
+                tonic::transport::Server::builder()
+                    .add_service(Gateway(published))
+                    .serve_with_incoming(incoming)
+                    .await
+                    .unwrap();
+            });
+        });
+        format!("http://{}", addr.recv().unwrap())
+    }
+
+    #[tokio::test]
+    async fn emits_inside_runtime() {
+        let published = Published::default();
+        let url = serve(published.clone());
+
+        let broker = GrpcBroker::connect(&url).unwrap();
+        Emitter::with_broker(broker).to("room").emit(vec!["test"]);
+
+        let published = published.lock().unwrap();
+        assert_eq!(1, published.len());
+        assert_eq!("socket.io#/#room#", published[0].channel);
+    }
+}
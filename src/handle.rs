@@ -0,0 +1,152 @@
+use redis::RedisResult;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+
+#[derive(Default)]
+struct State {
+    result: Option<RedisResult<()>>,
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    done: Condvar,
+}
+
+/// Resolves once an emit was published or failed, returned by
+/// `Emitter::emit_handle`. Emits through a `PublishQueue` resolve when its
+/// thread publishes them, others before `emit_handle` returns. Await it, or
+/// `wait` for it outside async code; dropping it leaves the emit as fire and
+/// forget.
+#[must_use = "dropping the handle doesn't cancel the emit, only its confirmation"]
+pub struct EmitHandle {
+    shared: Arc<Shared>,
+}
+
+impl EmitHandle {
+    pub(crate) fn new() -> EmitHandle {
+        EmitHandle {
+            shared: Default::default(),
+        }
+    }
+
+    /// The side that completes the handle.
+    pub(crate) fn completer(&self) -> EmitHandle {
+        EmitHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    pub(crate) fn complete(&self, result: RedisResult<()>) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.shared.done.notify_all();
+    }
+
+    /// Whether the emit was published or failed yet.
+    pub fn is_done(&self) -> bool {
+        self.shared.state.lock().unwrap().result.is_some()
+    }
+
+    /// Blocks until the emit was published or failed.
+    pub fn wait(self) -> RedisResult<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            state = self.shared.done.wait(state).unwrap();
+        }
+    }
+}
+
+impl Future for EmitHandle {
+    type Output = RedisResult<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<RedisResult<()>> {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl fmt::Debug for EmitHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EmitHandle")
+            .field("done", &self.is_done())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Broker, Emitter, InMemoryBroker, PublishQueue};
+    use redis::{ErrorKind, RedisError, RedisResult};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::mpsc::{self, Receiver};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Wake};
+    use std::time::Duration;
+
+    /// Holds publishes until the test lets them through, failing on `false`.
+    struct Gate(Mutex<Receiver<bool>>);
+
+    impl Broker for Gate {
+        fn publish(&self, _channel: &str, _payload: &[u8]) -> RedisResult<()> {
+            match self.0.lock().unwrap().recv().unwrap() {
+                true => Ok(()),
+                false => Err(RedisError::from((ErrorKind::IoError, "refused"))),
+            }
+        }
+    }
+
+    struct Woken(Mutex<usize>);
+
+    impl Wake for Woken {
+        fn wake(self: Arc<Self>) {
+            *self.0.lock().unwrap() += 1;
+        }
+    }
+
+    #[test]
+    fn resolves_once_published() {
+        let io = Emitter::with_broker(InMemoryBroker::new());
+        assert!(io.clone().emit_handle(vec!["now"]).wait().is_ok());
+
+        let (open, gate) = mpsc::channel();
+        let io = Emitter::with_broker(Gate(Mutex::new(gate))).publish_queue(PublishQueue::new());
+        let mut handle = io.clone().emit_handle(vec!["queued"]);
+        let woken = Arc::new(Woken(Mutex::new(0)));
+        let waker = woken.clone().into();
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut handle).poll(&mut cx).is_pending());
+        assert!(!handle.is_done());
+
+        open.send(true).unwrap();
+        while !handle.is_done() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(1, *woken.0.lock().unwrap());
+        assert!(matches!(
+            Pin::new(&mut handle).poll(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+
+        let failed = io.emit_handle(vec!["refused"]);
+        open.send(false).unwrap();
+        assert!(failed.wait().is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{BridgeHandle, Emitter};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the probing thread sleeps at most, so it notices when it is
+/// stopped.
+const NAP: Duration = Duration::from_millis(100);
+
+type StaleCallback = Arc<dyn Fn(Duration) + Send + Sync>;
+
+/// Probes the adapter every `interval` with `Emitter::is_cluster_reachable`
+/// and calls back once no socket.io server has listened for `stale_after`,
+/// i.e. emits are going nowhere. The callback gets how long the cluster has
+/// been unreachable, and is called again after the cluster came back and
+/// went away once more.
+#[derive(Clone)]
+pub struct Heartbeat {
+    interval: Duration,
+    stale_after: Duration,
+    on_stale: StaleCallback,
+}
+
+impl Heartbeat {
+    /// Probes every second.
+    pub fn new(stale_after: Duration) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(1),
+            stale_after,
+            on_stale: Arc::new(|_| ()),
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Heartbeat {
+        self.interval = interval;
+        self
+    }
+
+    pub fn on_stale<F>(mut self, on_stale: F) -> Heartbeat
+    where
+        F: Fn(Duration) + Send + Sync + 'static,
+    {
+        self.on_stale = Arc::new(on_stale);
+        self
+    }
+
+    fn watch(&self, emitter: &Emitter, stop: &AtomicBool) {
+        let mut last_seen = Instant::now();
+        let mut stale = false;
+        while !stop.load(Ordering::SeqCst) {
+            if emitter.is_cluster_reachable() {
+                last_seen = Instant::now();
+                stale = false;
+            } else if !stale && last_seen.elapsed() >= self.stale_after {
+                stale = true;
+                #[cfg(feature = "logging")]
+                log::warn!(
+                    "no socket.io server reachable for {:?}",
+                    last_seen.elapsed()
+                );
+                (self.on_stale)(last_seen.elapsed());
+            }
+            let next = Instant::now() + self.interval;
+            while !stop.load(Ordering::SeqCst) && Instant::now() < next {
+                thread::sleep(next.saturating_duration_since(Instant::now()).min(NAP));
+            }
+        }
+    }
+
+    /// Probes through `emitter` on a background thread until the returned
+    /// handle is stopped.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spawn(self, emitter: Emitter) -> BridgeHandle {
+        BridgeHandle::spawn(move |stop| self.watch(&emitter, stop))
+    }
+}
+
+impl fmt::Debug for Heartbeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heartbeat")
+            .field("interval", &self.interval)
+            .field("stale_after", &self.stale_after)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Broker;
+    use redis::RedisResult;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    /// A broker whose request channel has `listening` subscribers.
+    #[derive(Clone, Default)]
+    struct Listeners(Arc<AtomicUsize>);
+
+    impl Broker for Listeners {
+        fn publish(&self, _channel: &str, _payload: &[u8]) -> RedisResult<()> {
+            Ok(())
+        }
+
+        fn subscribers(&self, channel: &str) -> RedisResult<Option<usize>> {
+            assert_eq!("socket.io-request#/#", channel);
+            Ok(Some(self.0.load(Ordering::SeqCst)))
+        }
+    }
+
+    #[test]
+    fn reports_stale_cluster() {
+        let listening = Listeners::default();
+        let io = Emitter::with_broker(listening.clone());
+        assert!(!io.is_cluster_reachable());
+        listening.0.store(2, Ordering::SeqCst);
+        assert!(io.is_cluster_reachable());
+
+        let stale = Arc::new(Mutex::new(Vec::new()));
+        let calls = stale.clone();
+        let handle = Heartbeat::new(Duration::from_millis(30))
+            .interval(Duration::from_millis(5))
+            .on_stale(move |since| calls.lock().unwrap().push(since))
+            .spawn(io);
+        thread::sleep(Duration::from_millis(60));
+        assert!(stale.lock().unwrap().is_empty());
+
+        listening.0.store(0, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(100));
+        handle.stop();
+        let stale = stale.lock().unwrap();
+        assert_eq!(1, stale.len());
+        assert!(stale[0] >= Duration::from_millis(30));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{AuditSink, Middleware, PacketTransformer};
+use redis::RedisError;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(crate) type CorrelationIdHook = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+pub(crate) type SlowPublishHook = Arc<dyn Fn(&str, Duration) + Send + Sync>;
+pub(crate) type PublishHook = Arc<dyn Fn(Result<&PublishReceipt, &RedisError>) + Send + Sync>;
+
+/// Describes a packet the broker accepted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublishReceipt {
+    pub channel: String,
+    /// The event name, if the packet has one.
+    pub event: Option<String>,
+    /// Payload size in bytes.
+    pub size: usize,
+    pub elapsed: Duration,
+}
+
+/// Callbacks run by the emitter around every emit.
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub(crate) correlation_id: Option<CorrelationIdHook>,
+    pub(crate) slow_publish: Option<(Duration, SlowPublishHook)>,
+    pub(crate) audit: Option<Arc<dyn AuditSink>>,
+    pub(crate) middleware: Vec<Arc<dyn Middleware>>,
+    pub(crate) transformers: Vec<Arc<dyn PacketTransformer>>,
+    pub(crate) on_publish: Vec<PublishHook>,
+}
+
+impl Hooks {
+    /// Reports a publish attempt to the `on_publish` hooks.
+    pub(crate) fn attempted(
+        &self,
+        receipt: impl FnOnce() -> PublishReceipt,
+        result: &redis::RedisResult<()>,
+    ) {
+        if self.on_publish.is_empty() {
+            return;
+        }
+        match result {
+            Ok(()) => {
+                let receipt = receipt();
+                for hook in &self.on_publish {
+                    hook(Ok(&receipt));
+                }
+            }
+            Err(e) => {
+                for hook in &self.on_publish {
+                    hook(Err(e));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn published(&self, channel: &str, elapsed: Duration) {
+        if let Some((threshold, hook)) = &self.slow_publish {
+            if elapsed >= *threshold {
+                hook(channel, elapsed);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("correlation_id", &self.correlation_id.is_some())
+            .field("slow_publish", &self.slow_publish.as_ref().map(|(t, _)| t))
+            .field("audit", &self.audit.is_some())
+            .field("middleware", &self.middleware.len())
+            .field("transformers", &self.transformers.len())
+            .field("on_publish", &self.on_publish.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Broker, Emitter, InMemoryBroker, PublishReceipt};
+    use redis::{ErrorKind, RedisError, RedisResult};
+    use std::sync::{Arc, Mutex};
+
+    struct Unavailable;
+
+    impl Broker for Unavailable {
+        fn publish(&self, _: &str, _: &[u8]) -> RedisResult<()> {
+            Err(RedisError::from((ErrorKind::IoError, "unavailable")))
+        }
+    }
+
+    #[test]
+    fn on_publish_outcomes() {
+        let receipts = Arc::new(Mutex::new(Vec::new()));
+        let failures = Arc::new(Mutex::new(0));
+        let (r, f) = (receipts.clone(), failures.clone());
+        let hook = move |outcome: Result<&PublishReceipt, &RedisError>| match outcome {
+            Ok(receipt) => r.lock().unwrap().push(receipt.clone()),
+            Err(_) => *f.lock().unwrap() += 1,
+        };
+
+        let results = Emitter::with_broker(InMemoryBroker::new())
+            .on_publish(hook.clone())
+            .to("room")
+            .emit(vec!["joined", "1"])
+            .emit_batch(vec![("left", vec![], "room")]);
+        assert!(results[0].is_ok());
+        let results = Emitter::with_broker(Unavailable)
+            .on_publish(hook)
+            .emit_batch(vec![("left", vec![], "room")]);
+        assert!(results[0].is_err());
+
+        let receipts = receipts.lock().unwrap();
+        assert_eq!(2, receipts.len());
+        assert_eq!("socket.io#/#room#", receipts[0].channel);
+        assert_eq!(Some("joined"), receipts[0].event.as_deref());
+        assert!(receipts[0].size > 0);
+        assert_eq!(Some("left"), receipts[1].event.as_deref());
+        assert_eq!(1, *failures.lock().unwrap());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::Broker;
+use redis::RedisResult;
+use std::sync::{Arc, Mutex};
+
+/// A request to the Upstash Redis REST API, to be sent with whatever HTTP
+/// client the platform has, e.g. `fetch` in a Cloudflare Worker.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HttpRequest {
+    pub method: &'static str,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// The Upstash REST call publishing `payload` on `channel`. The payload
+    /// goes in the body, so binary packets don't need to survive a JSON round
+    /// trip.
+    pub(crate) fn upstash_publish(
+        url: &str,
+        token: &str,
+        sharded: bool,
+        channel: &str,
+        payload: &[u8],
+    ) -> HttpRequest {
+        let command = if sharded { "spublish" } else { "publish" };
+        HttpRequest {
+            method: "POST",
+            url: format!("{}/{}/{}", url, command, encode_path(channel)),
+            headers: vec![("Authorization".to_string(), format!("Bearer {}", token))],
+            body: payload.to_vec(),
+        }
+    }
+}
+
+/// Turns every publish into an `HttpRequest` instead of sending it, for
+/// targets without TCP sockets or blocking I/O such as wasm32. Clones share
+/// the queue, so keep one to `drain` after emitting:
+///
+/// ```
+/// use socketio_rust_emitter::{Emitter, HttpRelay};
+///
+/// let relay = HttpRelay::upstash("https://eu1-example.upstash.io", "token");
+/// Emitter::with_broker(relay.clone()).to("room").emit(vec!["news"]);
+/// for request in relay.drain() {
+///     // send `request` with the platform's HTTP client
+/// #   assert_eq!("POST", request.method);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpRelay {
+    url: String,
+    token: String,
+    sharded: bool,
+    queue: Arc<Mutex<Vec<HttpRequest>>>,
+}
+
+impl HttpRelay {
+    /// `url` is the database's REST URL (`UPSTASH_REDIS_REST_URL`) and `token`
+    /// its REST token.
+    pub fn upstash(url: &str, token: &str) -> HttpRelay {
+        HttpRelay {
+            url: url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            sharded: false,
+            queue: Default::default(),
+        }
+    }
+
+    /// Publishes with `SPUBLISH` for adapters using sharded pub/sub.
+    pub fn sharded(mut self) -> HttpRelay {
+        self.sharded = true;
+        self
+    }
+
+    /// Takes the requests queued so far, oldest first.
+    pub fn drain(&self) -> Vec<HttpRequest> {
+        std::mem::take(&mut *self.queue.lock().unwrap())
+    }
+}
+
+impl Broker for HttpRelay {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let request =
+            HttpRequest::upstash_publish(&self.url, &self.token, self.sharded, channel, payload);
+        self.queue.lock().unwrap().push(request);
+        Ok(())
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986, as
+/// URL path segments and SigV4 query strings need.
+pub(crate) fn encode_path(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpRelay;
+    use crate::{decode, Emitter};
+
+    #[test]
+    fn queue_requests() {
+        let relay = HttpRelay::upstash("https://db.upstash.io/", "secret");
+        let io = Emitter::with_broker(relay.clone());
+        io.clone().to("room 1").emit(vec!["news"]);
+        io.emit(vec!["all"]);
+
+        let requests = relay.drain();
+        assert_eq!(2, requests.len());
+        assert_eq!(
+            "https://db.upstash.io/publish/socket.io%23%2F%23room%201%23",
+            requests[0].url
+        );
+        assert_eq!(
+            ("Authorization".to_string(), "Bearer secret".to_string()),
+            requests[0].headers[0]
+        );
+        assert_eq!(&["news"], decode(&requests[0].body).unwrap().1.data());
+        assert!(relay.drain().is_empty());
+    }
+}
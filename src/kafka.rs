@@ -0,0 +1,166 @@
+use crate::{Broker, Source, SourceMessage};
+use kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
+use kafka::producer::{Producer, Record, RequiredAcks};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub type TopicMapper = Arc<dyn Fn(&str) -> (String, String) + Send + Sync>;
+
+/// Produces packets to Kafka. By default every packet goes to one topic keyed
+/// by its socket.io channel, so packets for the same room stay ordered on a
+/// single partition.
+#[derive(Clone)]
+pub struct KafkaBroker {
+    producer: Arc<Mutex<Producer>>,
+    route: TopicMapper,
+}
+
+impl KafkaBroker {
+    pub fn new(producer: Producer, topic: &str) -> KafkaBroker {
+        KafkaBroker {
+            producer: Arc::new(Mutex::new(producer)),
+            route: by_channel(topic),
+        }
+    }
+
+    pub fn connect(hosts: Vec<String>, topic: &str) -> RedisResult<KafkaBroker> {
+        let producer = Producer::from_hosts(hosts)
+            .with_ack_timeout(Duration::from_secs(5))
+            .with_required_acks(RequiredAcks::One)
+            .create()
+            .map_err(kafka_error)?;
+        Ok(KafkaBroker::new(producer, topic))
+    }
+
+    /// Overrides how a channel name maps to a `(topic, key)` pair.
+    pub fn router<F>(mut self, route: F) -> KafkaBroker
+    where
+        F: Fn(&str) -> (String, String) + Send + Sync + 'static,
+    {
+        self.route = Arc::new(route);
+        self
+    }
+}
+
+impl Broker for KafkaBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let (topic, key) = (self.route)(channel);
+        self.producer
+            .lock()
+            .unwrap()
+            .send(&record(&topic, &key, payload))
+            .map_err(kafka_error)
+    }
+}
+
+/// Sends every channel to `topic`, keyed by the channel.
+fn by_channel(topic: &str) -> TopicMapper {
+    let topic = topic.to_string();
+    Arc::new(move |channel| (topic.clone(), channel.to_string()))
+}
+
+fn record<'a>(topic: &'a str, key: &'a str, payload: &'a [u8]) -> Record<'a, &'a [u8], &'a [u8]> {
+    Record::from_key_value(topic, key.as_bytes(), payload)
+}
+
+impl fmt::Debug for KafkaBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KafkaBroker").finish()
+    }
+}
+
+/// Consumes Kafka topics as a bridge `Source`, committing the consumed
+/// offsets for the consumer group. The poll timeout and batch size are
+/// taken from the consumer's fetch settings.
+pub struct KafkaSource {
+    consumer: Consumer,
+    consumed: Vec<(String, i32, i64)>,
+}
+
+impl KafkaSource {
+    pub fn new(consumer: Consumer) -> KafkaSource {
+        KafkaSource {
+            consumer,
+            consumed: Vec::new(),
+        }
+    }
+
+    /// Consumes `topic` in `group`, starting from the earliest offset when
+    /// the group has none committed.
+    pub fn connect(hosts: Vec<String>, topic: &str, group: &str) -> RedisResult<KafkaSource> {
+        let consumer = Consumer::from_hosts(hosts)
+            .with_topic(topic.to_string())
+            .with_group(group.to_string())
+            .with_fallback_offset(FetchOffset::Earliest)
+            .with_offset_storage(Some(GroupOffsetStorage::Kafka))
+            .create()
+            .map_err(kafka_error)?;
+        Ok(KafkaSource::new(consumer))
+    }
+}
+
+impl Source for KafkaSource {
+    fn poll(&mut self, _max: usize, _timeout: Duration) -> RedisResult<Vec<SourceMessage>> {
+        let sets = self.consumer.poll().map_err(kafka_error)?;
+        let mut messages = Vec::new();
+        for set in sets.iter() {
+            for msg in set.messages() {
+                messages.push(SourceMessage {
+                    topic: set.topic().to_string(),
+                    key: if msg.key.is_empty() {
+                        None
+                    } else {
+                        Some(String::from_utf8_lossy(msg.key).into_owned())
+                    },
+                    payload: msg.value.to_vec(),
+                });
+            }
+            if let Some(last) = set.messages().last() {
+                self.consumed
+                    .push((set.topic().to_string(), set.partition(), last.offset));
+            }
+        }
+        Ok(messages)
+    }
+
+    fn commit(&mut self) -> RedisResult<()> {
+        for (topic, partition, offset) in self.consumed.drain(..) {
+            self.consumer
+                .consume_message(&topic, partition, offset)
+                .map_err(kafka_error)?;
+        }
+        self.consumer.commit_consumed().map_err(kafka_error)
+    }
+}
+
+impl fmt::Debug for KafkaSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KafkaSource").finish()
+    }
+}
+
+fn kafka_error(e: kafka::Error) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "Kafka error", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{by_channel, record};
+    use crate::{encode, Opts, Packet};
+
+    #[test]
+    fn records() {
+        let (topic, key) = by_channel("socket.io")("socket.io#/#room#");
+        assert_eq!("socket.io", topic);
+        assert_eq!("socket.io#/#room#", key);
+
+        let payload = encode("emitter", &Packet::new("/", vec!["news"]), &Opts::default());
+        let record = record(&topic, &key, &payload);
+        assert_eq!("socket.io", record.topic);
+        assert_eq!(b"socket.io#/#room#", record.key);
+        assert_eq!(&payload[..], record.value);
+        assert_eq!(-1, record.partition);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{bridge, BridgeHandle, Emitter};
+use redis::RedisResult;
+use std::fmt;
+use std::sync::Arc;
+
+/// A change to a key, reported by a Redis keyspace notification.
+#[derive(Debug, PartialEq, Clone)]
+pub struct KeyEvent {
+    pub key: String,
+    /// The command or event, e.g. `set`, `del` or `expired`.
+    pub op: String,
+    /// The key's string value after the change, when values are fetched.
+    pub value: Option<Vec<u8>>,
+}
+
+type RoomMapper = Arc<dyn Fn(&KeyEvent) -> Vec<String> + Send + Sync>;
+
+/// Subscribes to keyspace notifications for key patterns and turns each
+/// into an emit of `[event, key, op]`, followed by the value when values are
+/// fetched, so cache updates can drive UI refreshes directly.
+///
+/// Redis only sends the notifications once `notify-keyspace-events` is
+/// configured, see `enable_notifications`.
+#[derive(Clone)]
+pub struct KeyspaceListener {
+    client: redis::Client,
+    patterns: Vec<String>,
+    event: String,
+    fetch_values: bool,
+    rooms: RoomMapper,
+}
+
+impl KeyspaceListener {
+    pub fn new(client: redis::Client) -> KeyspaceListener {
+        KeyspaceListener {
+            client,
+            patterns: Vec::new(),
+            event: "keyspace".to_string(),
+            fetch_values: false,
+            rooms: Arc::new(|_| Vec::new()),
+        }
+    }
+
+    /// Listens to keys matching the glob-style `pattern`, e.g. `user:*`.
+    pub fn pattern(mut self, pattern: &str) -> KeyspaceListener {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// The event name emitted, `keyspace` by default.
+    pub fn event(mut self, event: &str) -> KeyspaceListener {
+        self.event = event.to_string();
+        self
+    }
+
+    /// Fetches the value of string keys with `GET` and appends it to the
+    /// emit. Deleted and expired keys have none.
+    pub fn fetch_values(mut self) -> KeyspaceListener {
+        self.fetch_values = true;
+        self
+    }
+
+    /// Chooses the rooms an event goes to in addition to the emitter's.
+    pub fn rooms<F>(mut self, rooms: F) -> KeyspaceListener
+    where
+        F: Fn(&KeyEvent) -> Vec<String> + Send + Sync + 'static,
+    {
+        self.rooms = Arc::new(rooms);
+        self
+    }
+
+    /// Turns on keyspace notifications for all commands on the server, adding
+    /// `KA` to the `notify-keyspace-events` flags already configured.
+    /// Managed Redis services may require configuring this elsewhere.
+    pub fn enable_notifications(&self) -> RedisResult<()> {
+        let mut con = self.client.get_connection()?;
+        let current: Vec<String> = redis::cmd("CONFIG")
+            .arg("GET")
+            .arg("notify-keyspace-events")
+            .query(&mut con)?;
+        let current = current.get(1).map_or("", String::as_str);
+        let flags = with_keyspace_flags(current);
+        if flags == current {
+            return Ok(());
+        }
+        redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("notify-keyspace-events")
+            .arg(flags)
+            .query(&mut con)
+    }
+
+    fn channels(&self) -> Vec<String> {
+        let db = self.client.get_connection_info().redis.db;
+        self.patterns
+            .iter()
+            .map(|pattern| format!("__keyspace@{}__:{}", db, pattern))
+            .collect()
+    }
+
+    /// Parses a keyspace notification, sent on `__keyspace@<db>__:<key>`
+    /// with the op as payload.
+    fn parse(channel: &str, op: &str) -> Option<KeyEvent> {
+        let (_, key) = channel.strip_prefix("__keyspace@")?.split_once("__:")?;
+        Some(KeyEvent {
+            key: key.to_string(),
+            op: op.to_string(),
+            value: None,
+        })
+    }
+
+    fn message(&self, event: &KeyEvent) -> Vec<String> {
+        let mut message = vec![self.event.clone(), event.key.clone(), event.op.clone()];
+        if let Some(value) = &event.value {
+            message.push(String::from_utf8_lossy(value).into_owned());
+        }
+        message
+    }
+
+    /// Emits the notification in `msg`, fetching the key's value on the
+    /// `values` connection when values are fetched.
+    fn notify(
+        &self,
+        emitter: &Emitter,
+        values: &mut Option<redis::Connection>,
+        msg: &redis::Msg,
+    ) -> RedisResult<()> {
+        let op: String = msg.get_payload()?;
+        let mut event = match KeyspaceListener::parse(msg.get_channel_name(), &op) {
+            Some(event) => event,
+            None => return Ok(()),
+        };
+        if self.fetch_values {
+            if values.is_none() {
+                *values = Some(self.client.get_connection()?);
+            }
+            let con = values.as_mut().unwrap();
+            // Keys that are gone or not strings have no value to send.
+            event.value = match redis::cmd("GET").arg(&event.key).query(con) {
+                Ok(value) => value,
+                Err(e) => {
+                    if e.is_connection_dropped() || e.is_io_error() {
+                        *values = None;
+                    }
+                    None
+                }
+            };
+        }
+        let mut rooms = emitter.rooms.clone();
+        rooms.extend((self.rooms)(&event));
+        let message = self.message(&event);
+        let message: Vec<&str> = message.iter().map(String::as_str).collect();
+        if let Err(_e) = emitter.send(&rooms, &message) {
+            #[cfg(feature = "logging")]
+            log::warn!("dropped keyspace emit for {}: {}", event.key, _e);
+        }
+        Ok(())
+    }
+
+    /// Listens on a background thread, emitting through `emitter` until the
+    /// returned handle is stopped. Lost connections are reopened.
+    pub fn spawn(self, emitter: Emitter) -> BridgeHandle {
+        let client = self.client.clone();
+        let channels = self.channels();
+        let mut values = None;
+        bridge::psubscribe(client, channels, "keyspace listener", move |msg| {
+            self.notify(&emitter, &mut values, msg)
+        })
+    }
+}
+
+/// `flags` with keyspace notifications (`K`) for all commands (`A`) added.
+/// The classes `A` stands for are dropped as redundant, the way Redis
+/// reports them.
+fn with_keyspace_flags(flags: &str) -> String {
+    const ALL: &str = "g$lshzxetd";
+    let mut merged = String::new();
+    for flag in flags.chars() {
+        if !ALL.contains(flag) && !merged.contains(flag) {
+            merged.push(flag);
+        }
+    }
+    for flag in ['K', 'A'].iter() {
+        if !merged.contains(*flag) {
+            merged.push(*flag);
+        }
+    }
+    merged
+}
+
+impl fmt::Debug for KeyspaceListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyspaceListener")
+            .field("client", &self.client)
+            .field("patterns", &self.patterns)
+            .field("event", &self.event)
+            .field("fetch_values", &self.fetch_values)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications() {
+        let client = redis::Client::open("redis://127.0.0.1/2").unwrap();
+        let listener = KeyspaceListener::new(client)
+            .pattern("user:*")
+            .pattern("cart:*")
+            .event("cache");
+        assert_eq!(
+            vec!["__keyspace@2__:user:*", "__keyspace@2__:cart:*"],
+            listener.channels()
+        );
+
+        let mut event = KeyspaceListener::parse("__keyspace@2__:user:42", "set").unwrap();
+        assert_eq!("user:42", event.key);
+        assert_eq!(vec!["cache", "user:42", "set"], listener.message(&event));
+        event.value = Some(b"Ada".to_vec());
+        assert_eq!(
+            vec!["cache", "user:42", "set", "Ada"],
+            listener.message(&event)
+        );
+        assert_eq!(None, KeyspaceListener::parse("other", "set"));
+    }
+
+    #[test]
+    fn merges_notification_flags() {
+        assert_eq!("KA", with_keyspace_flags(""));
+        assert_eq!("EKA", with_keyspace_flags("Ex"));
+        assert_eq!("AK", with_keyspace_flags("AK"));
+        assert_eq!("KEmA", with_keyspace_flags("KEg$m"));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::pool::ConnectionPool;
+use crate::{decode, Packet};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::time::Duration;
+
+/// Keeps the most recent packet per namespace, room and event in Redis for
+/// `ttl`, so clients that connect later can read the current state with
+/// `Emitter::last_event`. Packets without rooms are not kept. Connections
+/// are kept open between emits and shared by clones.
+#[derive(Debug, Clone)]
+pub struct LastEventCache {
+    connections: ConnectionPool,
+    ttl: Duration,
+}
+
+impl LastEventCache {
+    pub fn new(client: redis::Client, ttl: Duration) -> LastEventCache {
+        LastEventCache {
+            connections: ConnectionPool::new(client),
+            ttl,
+        }
+    }
+
+    /// Stores `payload` as the last `event` for each of `rooms`.
+    pub(crate) fn store(
+        &self,
+        prefix: &str,
+        nsp: &str,
+        rooms: &[String],
+        event: &str,
+        payload: &[u8],
+    ) -> RedisResult<()> {
+        if rooms.is_empty() {
+            return Ok(());
+        }
+        let mut pipe = redis::pipe();
+        for room in rooms {
+            pipe.cmd("SET")
+                .arg(key(prefix, nsp, room, event))
+                .arg(payload)
+                .arg("PX")
+                .arg(self.ttl.as_millis().max(1) as u64)
+                .ignore();
+        }
+        self.connections.run(|con| pipe.query(con))
+    }
+
+    pub fn get(
+        &self,
+        prefix: &str,
+        nsp: &str,
+        room: &str,
+        event: &str,
+    ) -> RedisResult<Option<Packet>> {
+        let key = key(prefix, nsp, room, event);
+        let payload: Option<Vec<u8>> = self
+            .connections
+            .run(|con| redis::cmd("GET").arg(&key).query(con))?;
+        payload
+            .map(|payload| {
+                decode(&payload).map(|(_, packet, _)| packet).map_err(|e| {
+                    RedisError::from((ErrorKind::TypeError, "invalid cached packet", e.to_string()))
+                })
+            })
+            .transpose()
+    }
+}
+
+fn key(prefix: &str, nsp: &str, room: &str, event: &str) -> String {
+    format!("{}-last#{}#{}#{}", prefix, nsp, room, event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::key;
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            "socket.io-last#/chat#r1#state",
+            key("socket.io", "/chat", "r1", "state")
+        );
+    }
+}
@@ -1,29 +1,348 @@
 #[macro_use]
 extern crate serde_derive;
 
-use redis::Commands;
 use rmp_serde::Serializer;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::marker::PhantomData;
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+#[cfg(feature = "actix")]
+mod actix;
+#[cfg(feature = "amqp")]
+mod amqp;
+#[cfg(feature = "testing")]
+mod arbitrary;
+mod audit;
+mod auth;
+#[cfg(feature = "axum")]
+mod axum;
+#[cfg(feature = "bevy")]
+mod bevy;
+mod bridge;
+mod broker;
+mod builder;
+mod channel;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod coalesce;
+mod codec;
+mod failover;
+mod fanout;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod flags;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod handle;
+#[cfg(feature = "remote")]
+mod heartbeat;
+mod hooks;
+mod http;
+#[cfg(feature = "elasticache")]
+mod iam;
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(not(target_arch = "wasm32"))]
+mod keyspace;
+mod lastevent;
+#[cfg(feature = "socketioxide")]
+mod local;
+mod memory;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod middleware;
+mod mirror;
+mod mock;
+#[cfg(feature = "mongodb")]
+mod mongo;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod namespace;
+#[cfg(feature = "nats")]
+mod nats;
+#[cfg(not(target_arch = "wasm32"))]
+mod oneshot;
+#[cfg(feature = "outbox")]
+mod outbox;
+mod pool;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "socks5")]
+mod proxy;
+#[cfg(feature = "python")]
+mod python;
+mod queue;
+mod ratelimit;
+mod raw;
+mod regions;
+#[cfg(not(target_arch = "wasm32"))]
+mod relay;
+#[cfg(feature = "remote")]
+mod remote;
+mod replay;
+#[cfg(feature = "rocket")]
+mod rocket;
+mod rooms;
+mod sampling;
+mod schedule;
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(not(target_arch = "wasm32"))]
+mod sharded;
+#[cfg(feature = "srv")]
+mod srv;
+#[cfg(feature = "streams")]
+mod streams;
+mod tenant;
+#[cfg(feature = "test-util")]
+pub mod test;
+mod transform;
+#[cfg(feature = "upstash")]
+mod upstash;
+#[cfg(feature = "warp")]
+pub mod warp;
+#[cfg(feature = "webhook")]
+mod webhook;
+#[cfg(feature = "websocket")]
+mod websocket;
+#[cfg(feature = "zeromq")]
+mod zeromq;
+
+#[cfg(feature = "actix")]
+pub use actix::EmitterData;
+#[cfg(feature = "amqp")]
+pub use amqp::AmqpBroker;
+pub use audit::{AuditOutcome, AuditRecord, AuditSink};
+pub use auth::{Token, TokenProvider};
+#[cfg(feature = "axum")]
+pub use axum::{EmitterExtension, RequestEmitter};
+#[cfg(feature = "bevy")]
+pub use bevy::{SocketIoEmit, SocketIoPlugin};
+pub use bridge::{Bridge, BridgeHandle, BridgedEmit, RedisStreamSource, Source, SourceMessage};
+pub use broker::{Broker, BrokerMessage, RedisBroker, Subscription};
+pub use builder::{BuildError, EmitterBuilder, Protocol};
+pub use channel::{ChannelFormat, ChannelLayout, ChannelScheme};
+#[cfg(feature = "chaos")]
+pub use chaos::Chaos;
+pub use coalesce::Coalescer;
+#[cfg(feature = "opentelemetry")]
+pub use codec::extract_context;
+pub use codec::{decode, encode, pretty};
+pub use failover::Failover;
+pub use fanout::{ErrorPolicy, FanOut};
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcBroker;
+pub use handle::EmitHandle;
+#[cfg(feature = "remote")]
+pub use heartbeat::Heartbeat;
+pub use hooks::PublishReceipt;
+pub use http::{HttpRelay, HttpRequest};
+#[cfg(feature = "elasticache")]
+pub use iam::{ElastiCacheIam, IamAuthRequest, IamSigner, SigV4Signer};
+#[cfg(feature = "kafka")]
+pub use kafka::{KafkaBroker, KafkaSource};
+#[cfg(not(target_arch = "wasm32"))]
+pub use keyspace::{KeyEvent, KeyspaceListener};
+pub use lastevent::LastEventCache;
+#[cfg(feature = "socketioxide")]
+pub use local::SocketioxideBroker;
+pub use memory::{InMemoryBroker, Published};
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+pub use middleware::{Middleware, Rejection};
+pub use mirror::Mirror;
+pub use mock::{Emit, EmitCall, MockEmitter};
+#[cfg(feature = "mongodb")]
+pub use mongo::MongoBroker;
+#[cfg(feature = "mqtt")]
+pub use mqtt::MqttBroker;
+pub use namespace::{Namespace, NamespaceEmitter};
+#[cfg(feature = "nats")]
+pub use nats::{NatsBroker, NatsSource};
+#[cfg(not(target_arch = "wasm32"))]
+pub use oneshot::OneShot;
+#[cfg(feature = "outbox")]
+pub use outbox::Outbox;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresBroker;
+#[cfg(feature = "socks5")]
+pub use proxy::Socks5Proxy;
+pub use queue::{Priority, PublishQueue};
+pub use ratelimit::{LimitAction, RateLimiter};
+pub use regions::Regions;
+#[cfg(not(target_arch = "wasm32"))]
+pub use relay::{Relay, Relayed};
+#[cfg(feature = "remote")]
+pub use remote::{Handshake, RemoteSocket};
+pub use replay::{Recorded, Recorder, Replay};
+#[cfg(feature = "rocket")]
+pub use rocket::{EmitterFairing, SocketIo};
+pub use rooms::{IntoRoom, IntoRooms, Room, SocketId};
+pub use sampling::Sampler;
+pub use schedule::{RedisSchedule, ScheduleHandle};
+#[cfg(feature = "tower")]
+pub use service::{EmitRequest, EmitService};
+#[cfg(not(target_arch = "wasm32"))]
+pub use sharded::{ShardBy, ShardedEmitter};
+#[cfg(feature = "srv")]
+pub use srv::SrvDiscovery;
+#[cfg(feature = "streams")]
+pub use streams::RedisStreams;
+pub use tenant::{TenantEmitter, TenantScope};
+pub use transform::PacketTransformer;
+#[cfg(feature = "upstash")]
+pub use upstash::Upstash;
+#[cfg(feature = "webhook")]
+pub use webhook::{WebhookBroker, WebhookFormat};
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketBroker;
+#[cfg(feature = "zeromq")]
+pub use zeromq::ZmqBroker;
+
+use broker::Transport;
+
+/// Publishes taking at least this long are logged as slow.
+#[cfg(feature = "logging")]
+const SLOW_PUBLISH: Duration = Duration::from_millis(500);
+
+/// Publishes socket.io packets. `N` is `String` unless the emitter is made
+/// `typed`, limiting `of` to the namespaces of a `Namespace` type.
 #[derive(Debug, Clone)]
-pub struct Emitter {
-    redis: redis::Client,
-    prefix: String,
+pub struct Emitter<N = String> {
+    transport: Transport,
+    prefix: Arc<str>,
+    nsp: Arc<str>,
+    scheme: channel::Scheme,
+    rooms: BTreeSet<String>,
+    flags: flags::Flags,
+    metadata: BTreeMap<String, rmpv::Value>,
+    uid: Arc<str>,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+    hooks: Arc<hooks::Hooks>,
+    dry_run: Option<InMemoryBroker>,
+    sampler: Option<Sampler>,
+    coalescer: Option<Coalescer>,
+    queue: Option<PublishQueue>,
+    priority: Priority,
+    rate_limiter: Option<RateLimiter>,
+    namespaces: namespace::Namespaces,
+    schedule_store: Option<Arc<RedisSchedule>>,
+    last_events: Option<Arc<LastEventCache>>,
+    per_room: bool,
+    buffers: Option<pool::BufferPool>,
+    #[cfg(feature = "remote")]
+    request_timeout: Duration,
+    namespace: PhantomData<fn() -> N>,
+}
+
+/// `Packet` borrowed from an emit, serialized to the same bytes.
+#[derive(Serialize)]
+struct PacketRef<'a> {
+    #[serde(rename = "type")]
+    _type: i32,
+    data: &'a [&'a str],
+    nsp: &'a str,
+}
+
+/// `Opts` borrowed from an emitter, serialized to the same bytes.
+struct OptsRef<'a> {
+    rooms: &'a BTreeSet<String>,
+    flags: flags::Flags,
+    traceparent: Option<String>,
+    correlation_id: Option<String>,
+    metadata: &'a BTreeMap<String, rmpv::Value>,
+}
+
+// Written by hand rather than with `#[serde(flatten)]`, which makes the
+// serializer buffer the entries to count them.
+impl Serialize for OptsRef<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let optional = [self.traceparent.is_some(), self.correlation_id.is_some()];
+        let len = 2 + optional.iter().filter(|set| **set).count() + self.metadata.len();
+        let mut map = serializer.serialize_map(Some(len))?;
+        map.serialize_entry("rooms", self.rooms)?;
+        map.serialize_entry("flags", &self.flags)?;
+        if let Some(traceparent) = &self.traceparent {
+            map.serialize_entry("traceparent", traceparent)?;
+        }
+        if let Some(correlation_id) = &self.correlation_id {
+            map.serialize_entry("correlationId", correlation_id)?;
+        }
+        for (key, value) in self.metadata {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// A serialized packet ready to be published.
+struct Encoded {
+    channel: Arc<str>,
+    payload: pool::Payload,
+    event: Option<String>,
     nsp: String,
-    channel: String,
     rooms: Vec<String>,
-    flags: HashMap<String, bool>,
-    uid: String,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct Opts {
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Opts {
     rooms: Vec<String>,
     flags: HashMap<String, bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    traceparent: Option<String>,
+    #[serde(
+        rename = "correlationId",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    correlation_id: Option<String>,
+    /// Any other fields, set with `Emitter::metadata` or kept from a decoded
+    /// packet.
+    #[serde(flatten)]
+    metadata: BTreeMap<String, rmpv::Value>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+impl Opts {
+    pub fn rooms(&self) -> &[String] {
+        &self.rooms
+    }
+    pub fn flags(&self) -> &HashMap<String, bool> {
+        &self.flags
+    }
+    /// The W3C `traceparent` of the span the packet was emitted in.
+    pub fn traceparent(&self) -> Option<&str> {
+        self.traceparent.as_deref()
+    }
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_deref()
+    }
+    pub fn metadata(&self) -> &BTreeMap<String, rmpv::Value> {
+        &self.metadata
+    }
+    /// When the packet goes stale, as set with `Emitter::ttl`.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        let millis = self.metadata.get("expiresAt")?.as_u64()?;
+        Some(UNIX_EPOCH + Duration::from_millis(millis))
+    }
+    pub fn metadata_mut(&mut self) -> &mut BTreeMap<String, rmpv::Value> {
+        &mut self.metadata
+    }
+    pub fn rooms_mut(&mut self) -> &mut Vec<String> {
+        &mut self.rooms
+    }
+    pub fn flags_mut(&mut self) -> &mut HashMap<String, bool> {
+        &mut self.flags
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Packet {
     #[serde(rename = "type")]
     _type: i32,
@@ -31,52 +350,173 @@ pub struct Packet {
     nsp: String,
 }
 
+impl Packet {
+    /// An event packet for `nsp`, `data` being the event name followed by
+    /// its arguments.
+    pub fn new(nsp: &str, data: Vec<&str>) -> Packet {
+        Packet {
+            _type: 2,
+            data: data.iter().map(|s| s.to_string()).collect(),
+            nsp: nsp.to_string(),
+        }
+    }
+    pub fn packet_type(&self) -> i32 {
+        self._type
+    }
+    pub fn data(&self) -> &[String] {
+        &self.data
+    }
+    pub fn nsp(&self) -> &str {
+        &self.nsp
+    }
+    /// The event name followed by its arguments.
+    pub fn data_mut(&mut self) -> &mut Vec<String> {
+        &mut self.data
+    }
+    pub fn set_nsp(&mut self, nsp: &str) {
+        self.nsp = nsp.to_string();
+    }
+    /// Renders the packet as it is put on the wire, in JSON-like text.
+    pub fn to_pretty_string(&self) -> String {
+        let mut msg = Vec::new();
+        self.serialize(&mut Serializer::new(&mut msg).with_struct_map())
+            .unwrap();
+        codec::pretty(&msg).unwrap()
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct EmitterOpts<'a> {
     pub host: String,
     pub port: i32,
     pub socket: Option<String>,
     pub key: Option<&'a str>,
+    /// Identifies the producing service in published packets, e.g.
+    /// `"billing-3"`. Defaults to `"emitter"`.
+    pub uid: Option<&'a str>,
+    /// Read/write timeout applied to every command sent to Redis.
+    pub command_timeout: Option<Duration>,
+    /// Route the Redis connection through a SOCKS5 proxy.
+    #[cfg(feature = "socks5")]
+    pub proxy: Option<Socks5Proxy>,
+}
+
+/// An event for `Emitter::emit_stream`: the message, starting with the event
+/// name, and the rooms it goes to in addition to the emitter's.
+#[cfg(feature = "stream")]
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct OutgoingEvent {
+    pub rooms: Vec<String>,
+    pub message: Vec<String>,
+}
+
+#[cfg(feature = "stream")]
+impl OutgoingEvent {
+    pub fn new(message: Vec<&str>) -> OutgoingEvent {
+        OutgoingEvent {
+            rooms: Vec::new(),
+            message: message.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    pub fn to<R: IntoRooms>(mut self, rooms: R) -> OutgoingEvent {
+        self.rooms.extend(rooms.into_rooms());
+        self
+    }
 }
 
+/// Sources an emitter can be created from. The TCP Redis sources are not
+/// available on wasm32, where `HttpRelay` or a custom broker is used instead.
 pub trait IntoEmitter {
     fn into_emitter(self) -> Emitter;
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl IntoEmitter for redis::Client {
     fn into_emitter(self) -> Emitter {
-        create_emitter(self, "socket.io", "/")
+        RedisBroker::new(self).into_emitter()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl IntoEmitter for RedisBroker {
+    fn into_emitter(self) -> Emitter {
+        create_emitter(Transport::Redis(Arc::new(self)), "socket.io", "/")
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl<'a> IntoEmitter for EmitterOpts<'a> {
     fn into_emitter(self) -> Emitter {
         let addr = format!("redis://{}:{}", self.host, self.port);
         let prefix = self.key.unwrap_or("socket.io");
 
-        create_emitter(redis::Client::open(addr.as_str()).unwrap(), prefix, "/")
+        let mut redis = RedisBroker::new(redis::Client::open(addr.as_str()).unwrap());
+        redis.command_timeout = self.command_timeout;
+        #[cfg(feature = "socks5")]
+        {
+            redis.proxy = self.proxy;
+        }
+        let mut emitter = create_emitter(Transport::Redis(Arc::new(redis)), prefix, "/");
+        if let Some(uid) = self.uid {
+            emitter.uid = uid.into();
+        }
+        emitter
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl IntoEmitter for &str {
     fn into_emitter(self) -> Emitter {
-        create_emitter(
-            redis::Client::open(format!("redis://{}", self).as_str()).unwrap(),
-            "socket.io",
-            "/",
-        )
+        redis::Client::open(format!("redis://{}", self).as_str())
+            .unwrap()
+            .into_emitter()
     }
 }
 
-fn create_emitter(redis: redis::Client, prefix: &str, nsp: &str) -> Emitter {
+#[cfg(not(target_arch = "wasm32"))]
+impl IntoEmitter for Failover {
+    fn into_emitter(self) -> Emitter {
+        let mut redis = RedisBroker::new(self.endpoints[0].clone());
+        redis.failover = Some(self);
+        redis.into_emitter()
+    }
+}
+
+#[cfg(feature = "upstash")]
+impl IntoEmitter for Upstash {
+    fn into_emitter(self) -> Emitter {
+        Emitter::with_broker(self)
+    }
+}
+
+fn create_emitter(transport: Transport, prefix: &str, nsp: &str) -> Emitter {
     Emitter {
-        redis,
-        prefix: prefix.to_string(),
-        nsp: nsp.to_string(),
-        channel: format!("{}#{}#", prefix, nsp),
-        rooms: Vec::new(),
-        flags: HashMap::new(),
-        uid: "emitter".to_string(),
+        transport,
+        prefix: prefix.into(),
+        nsp: nsp.into(),
+        scheme: Default::default(),
+        rooms: BTreeSet::new(),
+        flags: flags::Flags::empty(),
+        metadata: BTreeMap::new(),
+        uid: "emitter".into(),
+        #[cfg(feature = "metrics")]
+        metrics: Metrics::global(),
+        hooks: Default::default(),
+        dry_run: None,
+        sampler: None,
+        coalescer: None,
+        queue: None,
+        priority: Priority::Normal,
+        rate_limiter: None,
+        namespaces: Default::default(),
+        schedule_store: None,
+        last_events: None,
+        per_room: false,
+        buffers: None,
+        #[cfg(feature = "remote")]
+        request_timeout: remote::REQUEST_TIMEOUT,
+        namespace: PhantomData,
     }
 }
 
@@ -85,68 +525,981 @@ impl Emitter {
         data.into_emitter()
     }
 
-    pub fn to(mut self, room: &str) -> Emitter {
-        self.rooms.push(room.to_string());
-        self
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn builder() -> EmitterBuilder {
+        EmitterBuilder::default()
     }
+
+    /// Publishes through `broker` instead of Redis. The Redis-specific
+    /// setters on `Emitter` leave such emitters unchanged; configure a
+    /// `RedisBroker` with its own setters instead.
+    pub fn with_broker<B: Broker + 'static>(broker: B) -> Emitter {
+        create_emitter(Transport::Custom(Arc::new(broker)), "socket.io", "/")
+    }
+
     pub fn of(self, nsp: &str) -> Emitter {
-        create_emitter(self.redis, self.prefix.as_str(), nsp)
+        Emitter {
+            nsp: nsp.into(),
+            rooms: BTreeSet::new(),
+            flags: flags::Flags::empty(),
+            ..self
+        }
+    }
+    /// An emitter scoped to `tenant` by key prefix.
+    pub fn tenant(&self, tenant: &str) -> TenantEmitter {
+        self.tenant_with(tenant, TenantScope::KeyPrefix)
+    }
+    pub fn tenant_with(&self, tenant: &str, scope: TenantScope) -> TenantEmitter {
+        TenantEmitter::new(self, tenant, scope)
+    }
+    /// Returns the handle for `nsp`, created from this emitter's configuration
+    /// on first use and shared by all clones of the emitter afterwards.
+    pub fn nsp(&self, nsp: &str) -> NamespaceEmitter {
+        self.namespaces.get(self, nsp)
+    }
+    /// Limits `of` to the namespaces of `N`, so a misspelled namespace does
+    /// not compile. The current namespace is kept.
+    pub fn typed<N: Namespace>(self) -> Emitter<N> {
+        self.retype()
+    }
+}
+
+impl<N: Namespace> Emitter<N> {
+    pub fn of(self, nsp: N) -> Emitter<N> {
+        Emitter {
+            nsp: nsp.name().into(),
+            rooms: BTreeSet::new(),
+            flags: flags::Flags::empty(),
+            ..self
+        }
+    }
+}
+
+impl<N: Clone> Emitter<N> {
+    /// Targets one or more rooms, each at most once and published in sorted
+    /// order, e.g. `to("room")`, `to(["a", "b"])` or
+    /// `to(ids.iter().map(|id| format!("user:{}", id)))`.
+    pub fn to<R: IntoRooms>(mut self, rooms: R) -> Emitter<N> {
+        self.rooms.extend(rooms.into_rooms());
+        self
+    }
+    /// Alias of `to`, matching the JS emitter's `in()`.
+    pub fn in_<R: IntoRooms>(self, rooms: R) -> Emitter<N> {
+        self.to(rooms)
+    }
+    /// Keeps the last packet per room and event in `cache`.
+    pub fn last_event_cache(mut self, cache: LastEventCache) -> Emitter<N> {
+        self.last_events = Some(Arc::new(cache));
+        self
+    }
+    /// The most recent `event` packet emitted to `room` in the current
+    /// namespace, if the last-event cache still has it.
+    pub fn last_event(&self, room: &str, event: &str) -> redis::RedisResult<Option<Packet>> {
+        match &self.last_events {
+            Some(cache) => cache.get(&self.prefix, &self.nsp, room, event),
+            None => Err(redis::RedisError::from((
+                redis::ErrorKind::ClientError,
+                "no last-event cache configured",
+            ))),
+        }
+    }
+    /// Builds channel names with `scheme` instead of `prefix#nsp#room#`.
+    pub fn channel_scheme<S: ChannelScheme + 'static>(mut self, scheme: S) -> Emitter<N> {
+        self.scheme = channel::Scheme::new(Arc::new(scheme));
+        self
+    }
+    pub fn channel_layout(self, layout: ChannelLayout) -> Emitter<N> {
+        self.channel_scheme(layout)
+    }
+    /// Sets the uid published with every packet, identifying this producer.
+    pub fn uid(mut self, uid: &str) -> Emitter<N> {
+        self.uid = uid.into();
+        self
+    }
+    /// Changes a setting of the Redis broker. Emitters with a custom broker
+    /// have none and are returned unchanged.
+    fn configure<F: FnOnce(RedisBroker) -> RedisBroker>(mut self, configure: F) -> Emitter<N> {
+        if let Some(redis) = self.transport.redis_mut() {
+            *redis = configure(redis.clone());
+        } else {
+            #[cfg(feature = "logging")]
+            log::warn!("ignoring a Redis setting on an emitter with a custom broker");
+        }
+        self
+    }
+    /// Sets the read/write timeout for commands, so a stalled Redis fails the
+    /// publish instead of blocking the caller indefinitely.
+    pub fn command_timeout(self, timeout: Duration) -> Emitter<N> {
+        self.configure(|redis| redis.command_timeout(timeout))
     }
-    pub fn json(mut self) -> Emitter {
-        let mut flags = HashMap::new();
-        flags.insert("json".to_string(), true);
-        self.flags = flags;
+    #[cfg(feature = "socks5")]
+    pub fn proxy(self, proxy: Socks5Proxy) -> Emitter<N> {
+        self.configure(|redis| redis.proxy(proxy))
+    }
+    /// Resolves the Redis endpoint through DNS SRV instead of the configured
+    /// host; credentials are still taken from the original connection info.
+    #[cfg(feature = "srv")]
+    pub fn discover(self, srv: SrvDiscovery) -> Emitter<N> {
+        self.configure(|redis| redis.discover(srv))
+    }
+    /// Publishes a copy of every packet to `mirror` as well, e.g. while
+    /// migrating between Redis deployments.
+    pub fn mirror(self, mirror: Mirror) -> Emitter<N> {
+        self.configure(|redis| redis.mirror(mirror))
+    }
+    /// Authenticates with credentials from `provider`, fetching a new token
+    /// shortly before the current one expires.
+    pub fn token_provider<P: TokenProvider + 'static>(self, provider: P) -> Emitter<N> {
+        self.configure(|redis| redis.token_provider(provider))
+    }
+    /// Authenticates with ElastiCache IAM tokens, refreshed before they expire.
+    #[cfg(feature = "elasticache")]
+    pub fn elasticache_iam(self, iam: ElastiCacheIam) -> Emitter<N> {
+        self.token_provider(iam)
+    }
+    /// Publishes with `SPUBLISH` for adapters using Redis 7 sharded pub/sub.
+    pub fn sharded(self) -> Emitter<N> {
+        self.configure(RedisBroker::sharded)
+    }
+    /// Reuses serialization buffers across emits, from a small pool kept per
+    /// thread. Buffers that grew beyond `max_size` bytes are not kept.
+    pub fn pool_buffers(mut self, max_size: usize) -> Emitter<N> {
+        self.buffers = Some(pool::BufferPool::new(max_size));
+        self
+    }
+    /// Fans emits to several rooms out into a packet per room, published on
+    /// the room's own channel, for adapters that only subscribe to the
+    /// channels of their rooms. The packets go out in one batch, which the
+    /// Redis broker sends as a single pipeline.
+    pub fn per_room_fanout(mut self) -> Emitter<N> {
+        self.per_room = true;
+        self
+    }
+    /// Speaks RESP3 (`HELLO 3`) on the connection; push messages the server
+    /// interleaves with replies are skipped.
+    pub fn resp3(self) -> Emitter<N> {
+        self.configure(RedisBroker::resp3)
+    }
+    /// Appends packets to a Redis stream for @socket.io/redis-streams-adapter
+    /// instead of publishing them.
+    #[cfg(feature = "streams")]
+    pub fn streams(self, streams: RedisStreams) -> Emitter<N> {
+        self.configure(|redis| redis.streams(streams))
+    }
+    /// Records into `metrics` instead of the default registry's.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: Metrics) -> Emitter<N> {
+        self.metrics = metrics;
         self
     }
-    pub fn volatile(mut self) -> Emitter {
-        let mut flags = HashMap::new();
-        flags.insert("volatile".to_string(), true);
-        self.flags = flags;
+    /// Stamps every packet with the id returned by `hook`, typically the id
+    /// of the request being handled, read from a thread-local or task-local.
+    pub fn correlation_id<F>(mut self, hook: F) -> Emitter<N>
+    where
+        F: Fn() -> Option<String> + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.hooks).correlation_id = Some(Arc::new(hook));
         self
     }
-    pub fn broadcast(mut self) -> Emitter {
-        let mut flags = HashMap::new();
-        flags.insert("broadcast".to_string(), true);
-        self.flags = flags;
+    /// Calls `hook` with the channel and the elapsed time whenever a publish
+    /// takes at least `threshold`; a zero threshold reports every publish.
+    pub fn on_slow_publish<F>(mut self, threshold: Duration, hook: F) -> Emitter<N>
+    where
+        F: Fn(&str, Duration) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.hooks).slow_publish = Some((threshold, Arc::new(hook)));
+        self
+    }
+    /// Runs `middleware` on every packet before it is serialized, after the
+    /// middleware registered before it.
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Emitter<N> {
+        Arc::make_mut(&mut self.hooks)
+            .middleware
+            .push(Arc::new(middleware));
+        self
+    }
+    /// Passes every packet through `transformer` after the transformers
+    /// registered before it, publishing whatever packets it returns.
+    pub fn transformer<T: PacketTransformer + 'static>(mut self, transformer: T) -> Emitter<N> {
+        Arc::make_mut(&mut self.hooks)
+            .transformers
+            .push(Arc::new(transformer));
+        self
+    }
+    /// Calls `hook` after every publish attempt with a receipt or the error.
+    pub fn on_publish<F>(mut self, hook: F) -> Emitter<N>
+    where
+        F: Fn(Result<&PublishReceipt, &redis::RedisError>) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.hooks)
+            .on_publish
+            .push(Arc::new(hook));
+        self
+    }
+    /// Drops a share of the packets for the events `sampler` has rates for.
+    pub fn sampler(mut self, sampler: Sampler) -> Emitter<N> {
+        self.sampler = Some(sampler);
+        self
+    }
+    /// Publishes only the latest of the emits `coalescer` merges, once their
+    /// window closes.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn coalesce(mut self, coalescer: Coalescer) -> Emitter<N> {
+        self.coalescer = Some(coalescer);
+        self
+    }
+    /// Hands packets to `queue` for its thread to publish, so emits return
+    /// without waiting for the broker.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn publish_queue(mut self, queue: PublishQueue) -> Emitter<N> {
+        self.queue = Some(queue);
+        self
+    }
+    /// The priority of the next packet in the publish queue, `Normal` by
+    /// default.
+    pub fn priority(mut self, priority: Priority) -> Emitter<N> {
+        self.priority = priority;
+        self
+    }
+    /// Applies `limiter` to the packets this emitter publishes.
+    pub fn rate_limit(mut self, limiter: RateLimiter) -> Emitter<N> {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+    /// Reports every emitted packet to `sink`, including failed publishes.
+    pub fn audit<S: AuditSink + 'static>(mut self, sink: S) -> Emitter<N> {
+        Arc::make_mut(&mut self.hooks).audit = Some(Arc::new(sink));
+        self
+    }
+    /// Builds and serializes packets as usual but hands them to `collector`
+    /// instead of the configured transport.
+    pub fn dry_run(mut self, collector: InMemoryBroker) -> Emitter<N> {
+        self.dry_run = Some(collector);
+        self
+    }
+    /// Adds `key` with `value` to the opts of the next packet, next to rooms
+    /// and flags, e.g. for routing hints read by a custom adapter.
+    pub fn metadata<V: Serialize + ?Sized>(mut self, key: &str, value: &V) -> Emitter<N> {
+        // Encoded like the rest of the packet, so structs become maps.
+        let mut buf = Vec::new();
+        value
+            .serialize(&mut Serializer::new(&mut buf).with_struct_map())
+            .expect("metadata must serialize to msgpack");
+        let value = rmpv::decode::read_value(&mut buf.as_slice()).unwrap();
+        self.metadata.insert(key.to_string(), value);
+        self
+    }
+    /// Marks the next packet stale `ttl` from now, adding `expiresAt` in
+    /// milliseconds since the Unix epoch to its opts, for adapters that drop
+    /// late packets instead of delivering them after a backlog. Delayed
+    /// emits count the TTL from this call too.
+    pub fn ttl(self, ttl: Duration) -> Emitter<N> {
+        let expires_at = (SystemTime::now() + ttl)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.metadata("expiresAt", &(expires_at.as_millis() as u64))
+    }
+    pub fn json(mut self) -> Emitter<N> {
+        self.flags = flags::Flags::JSON;
+        self
+    }
+    pub fn volatile(mut self) -> Emitter<N> {
+        self.flags = flags::Flags::VOLATILE;
+        self
+    }
+    pub fn broadcast(mut self) -> Emitter<N> {
+        self.flags = flags::Flags::BROADCAST;
+        self
+    }
+    /// Emits `message` to the selected rooms.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the packet is rejected or can't be published; `try_emit`
+    /// returns the error instead.
+    pub fn emit(self, message: Vec<&str>) -> Emitter<N> {
+        self.try_emit(message).unwrap()
+    }
+    /// Emits `message` to the selected rooms, failing if a middleware
+    /// rejects the packet, the rate limit refuses it or the publish fails.
+    pub fn try_emit(self, message: Vec<&str>) -> redis::RedisResult<Emitter<N>> {
+        self.send(&self.rooms, &message)?;
+        Ok(self.reset())
+    }
+    /// Emits `message` to the selected rooms, returning a handle that
+    /// resolves once it was published or failed instead of panicking.
+    pub fn emit_handle(self, message: Vec<&str>) -> EmitHandle {
+        let handle = EmitHandle::new();
+        let mut done = Some(handle.completer());
+        let result = self.send_with(&self.rooms, &message, &mut done);
+        if let Some(done) = done {
+            done.complete(result);
+        }
+        handle
+    }
+    /// Emits `message` to the selected rooms at `at`, from a background
+    /// scheduler. With a schedule store the packet is serialized now and kept
+    /// in Redis until due, skipping sampling and rate limits.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn emit_at(self, at: Instant, message: Vec<&str>) -> Emitter<N> {
+        match &self.schedule_store {
+            Some(store) => {
+                let due = SystemTime::now() + at.saturating_duration_since(Instant::now());
+                let stored = self.serialize(&self.rooms, &message).and_then(|packets| {
+                    packets
+                        .iter()
+                        .try_for_each(|packet| store.add(due, &packet.channel, &packet.payload))
+                });
+                if let Err(_e) = stored {
+                    #[cfg(feature = "logging")]
+                    log::error!("dropped scheduled packet: {}", _e);
+                }
+            }
+            None => {
+                schedule::schedule(
+                    at,
+                    self.clone().retype(),
+                    self.rooms.clone(),
+                    message.iter().map(|s| s.to_string()).collect(),
+                    None,
+                );
+            }
+        }
+        self.reset()
+    }
+    /// Emits `message` to the selected rooms once `delay` has passed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn emit_after(self, delay: Duration, message: Vec<&str>) -> Emitter<N> {
+        self.emit_at(Instant::now() + delay, message)
+    }
+    /// Emits `message` to the selected rooms every `interval`, starting one
+    /// interval from now, until the returned handle is cancelled. The room
+    /// and flag selection is kept.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn emit_every(&self, interval: Duration, message: Vec<&str>) -> ScheduleHandle {
+        schedule::schedule(
+            Instant::now() + interval,
+            self.clone().retype(),
+            self.rooms.clone(),
+            message.iter().map(|s| s.to_string()).collect(),
+            Some(schedule::Repeat::Interval(interval)),
+        )
+    }
+    /// Emits `message` to the selected rooms on the UTC times matched by the
+    /// cron `expression` (with a seconds field, e.g. `0 30 9 * * Mon-Fri`)
+    /// until the returned handle is cancelled.
+    #[cfg(feature = "cron")]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn emit_cron(
+        &self,
+        expression: &str,
+        message: Vec<&str>,
+    ) -> redis::RedisResult<ScheduleHandle> {
+        let schedule: cron::Schedule = expression.parse().map_err(|e: cron::error::Error| {
+            redis::RedisError::from((
+                redis::ErrorKind::InvalidClientConfig,
+                "invalid cron expression",
+                e.to_string(),
+            ))
+        })?;
+        let repeat = schedule::Repeat::Cron(Box::new(schedule));
+        let first = repeat.next(Instant::now());
+        let first = first.ok_or_else(|| {
+            redis::RedisError::from((
+                redis::ErrorKind::InvalidClientConfig,
+                "cron expression never matches",
+            ))
+        })?;
+        Ok(schedule::schedule(
+            first,
+            self.clone().retype(),
+            self.rooms.clone(),
+            message.iter().map(|s| s.to_string()).collect(),
+            Some(repeat),
+        ))
+    }
+    /// Keeps packets scheduled with `emit_at` in `store` so they survive
+    /// restarts, and starts polling it for due packets unless the store or a
+    /// clone of it is polled already. `RedisSchedule::stop_polling` stops it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn schedule_store(mut self, store: RedisSchedule) -> Emitter<N> {
+        store.spawn_poller(self.clone().retype());
+        self.schedule_store = Some(Arc::new(store));
         self
     }
-    pub fn emit(mut self, message: Vec<&str>) -> Emitter {
+
+    /// Runs the whole emit pipeline for a packet to `rooms`. Packets dropped
+    /// by sampling or rate limiting, or held back for coalescing, count as
+    /// sent.
+    fn send(&self, rooms: &BTreeSet<String>, message: &[&str]) -> redis::RedisResult<()> {
+        self.send_with(rooms, message, &mut None)
+    }
+
+    /// Sends like `send`, handing `done` to the publish queue if the packets
+    /// are queued, for it to complete once they are published.
+    fn send_with(
+        &self,
+        rooms: &BTreeSet<String>,
+        message: &[&str],
+        done: &mut Option<EmitHandle>,
+    ) -> redis::RedisResult<()> {
+        if let Some(sampler) = &self.sampler {
+            if !sampler.sample(message.first().copied()) {
+                return Ok(());
+            }
+        }
+        let limited = match &self.rate_limiter {
+            Some(limiter) => limiter.acquire(rooms),
+            None => Ok(true),
+        };
+        if let Ok(false) = limited {
+            return Ok(());
+        }
+        let packets = match self.serialize(rooms, message) {
+            Ok(packets) => packets,
+            Err(e) => {
+                #[cfg(feature = "logging")]
+                log::warn!("{}", e);
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %e, "packet rejected");
+                let result = Err(e);
+                self.record_audit(
+                    &self.channel_for(rooms),
+                    message.first().copied(),
+                    0,
+                    &result,
+                );
+                return result;
+            }
+        };
+        if let (Some(coalescer), Ok(true), Some(event)) =
+            (&self.coalescer, &limited, message.first())
+        {
+            if coalescer.coalesces(event) {
+                coalescer.defer(&self.nsp, rooms, event, self.clone().retype(), packets);
+                return Ok(());
+            }
+        }
+        if let (Some(queue), Ok(true)) = (&self.queue, &limited) {
+            queue.push(self.priority, self.clone().retype(), packets, done.take());
+            return Ok(());
+        }
+        if self.per_room && packets.len() > 1 && limited.is_ok() {
+            let result = self.publish_packets(&packets).into_iter().collect();
+            self.recycle(packets);
+            return result;
+        }
+        let mut result = Ok(());
+        for packet in &packets {
+            let published = self.publish_one(packet, limited.as_ref().err());
+            if result.is_ok() {
+                result = published;
+            }
+        }
+        self.recycle(packets);
+        limited.and(result)
+    }
+
+    /// Publishes a packet serialized earlier, e.g. a scheduled one, and
+    /// reports it like the emitter's own packets.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn publish_serialized(&self, channel: &str, payload: Vec<u8>) -> redis::RedisResult<()> {
+        let (event, nsp, rooms) = match codec::decode(&payload) {
+            Ok((_, packet, opts)) => (packet.data.into_iter().next(), packet.nsp, opts.rooms),
+            Err(_) => (None, String::new(), Vec::new()),
+        };
+        let packet = Encoded {
+            channel: channel.into(),
+            payload: pool::Payload::Heap(payload),
+            event,
+            nsp,
+            rooms,
+        };
+        self.publish_one(&packet, None)
+    }
+
+    /// Returns the buffers of published packets to the pool, if there is one.
+    fn recycle(&self, packets: Vec<Encoded>) {
+        if let Some(pool) = &self.buffers {
+            for packet in packets {
+                if let Some(buffer) = packet.payload.into_heap() {
+                    pool.give(buffer);
+                }
+            }
+        }
+    }
+
+    /// Publishes one serialized packet and reports it. A `refused` packet is
+    /// only reported.
+    fn publish_one(
+        &self,
+        packet: &Encoded,
+        refused: Option<&redis::RedisError>,
+    ) -> redis::RedisResult<()> {
+        let Encoded {
+            channel,
+            payload: msg,
+            ..
+        } = packet;
+        #[cfg(feature = "tracing")]
+        let _span = self.emit_span(packet).entered();
+
+        let started = std::time::Instant::now();
+        let result = match (refused, &self.dry_run) {
+            (Some(e), _) => Err(redis::RedisError::from((
+                e.kind(),
+                "packet refused",
+                e.to_string(),
+            ))),
+            (None, Some(collector)) => {
+                #[cfg(feature = "logging")]
+                log::info!("dry run, not publishing {} bytes to {}", msg.len(), channel);
+                collector.publish(channel, msg)
+            }
+            (None, None) => self.transport.broker().publish(channel, msg),
+        };
+        self.report(packet, started.elapsed(), &result);
+        result
+    }
+
+    #[cfg(feature = "tracing")]
+    fn emit_span(&self, packet: &Encoded) -> tracing::Span {
+        tracing::debug_span!(
+            "emit",
+            channel = %packet.channel,
+            uid = %self.uid,
+            rooms = packet.rooms.len(),
+            size = packet.payload.len(),
+            outcome = tracing::field::Empty,
+        )
+    }
+
+    /// Reports a publish attempt to the hooks, audit sink, log, metrics and
+    /// the current `emit` span.
+    fn report(&self, packet: &Encoded, elapsed: Duration, result: &redis::RedisResult<()>) {
+        let Encoded {
+            channel,
+            payload: msg,
+            event,
+            ..
+        } = packet;
+        if result.is_ok() {
+            self.hooks.published(channel, elapsed);
+            self.remember(packet);
+        }
+        self.hooks.attempted(
+            || PublishReceipt {
+                channel: channel.to_string(),
+                event: event.clone(),
+                size: msg.len(),
+                elapsed,
+            },
+            result,
+        );
+        self.record_audit(channel, event.as_deref(), msg.len(), result);
+        #[cfg(feature = "logging")]
+        match result {
+            Ok(()) if elapsed >= SLOW_PUBLISH => {
+                log::warn!("slow publish to {}: {:?}", channel, elapsed)
+            }
+            Ok(()) => {}
+            Err(e) => log::error!("dropped packet for {}: {}", channel, e),
+        }
+        #[cfg(feature = "metrics")]
+        self.metrics.record(msg.len(), elapsed, result.is_ok());
+        #[cfg(feature = "tracing")]
+        match result {
+            Ok(()) => {
+                tracing::Span::current().record("outcome", "ok");
+            }
+            Err(e) => {
+                tracing::Span::current().record("outcome", "error");
+                tracing::error!(error = %e, "publish failed");
+            }
+        }
+    }
+
+    /// Emits every event of `events`, publishing up to `concurrency` at a
+    /// time on the blocking thread pool, and yields the results in completion
+    /// order. Events are only pulled from `events` as the returned stream is
+    /// polled, which must happen inside a Tokio runtime. Iterators can be
+    /// passed through `futures_util::stream::iter`.
+    #[cfg(feature = "stream")]
+    pub fn emit_stream<S>(
+        &self,
+        events: S,
+        concurrency: usize,
+    ) -> impl futures_util::Stream<Item = redis::RedisResult<()>>
+    where
+        S: futures_util::Stream<Item = OutgoingEvent>,
+    {
+        use futures_util::StreamExt;
+
+        let emitter: Emitter = self.clone().retype();
+        events
+            .map(move |event| {
+                let emitter = emitter.clone();
+                async move {
+                    tokio::task::spawn_blocking(move || {
+                        let mut rooms = emitter.rooms.clone();
+                        rooms.extend(event.rooms);
+                        let message: Vec<&str> = event.message.iter().map(String::as_str).collect();
+                        emitter.send(&rooms, &message)
+                    })
+                    .await
+                    .unwrap_or_else(|e| {
+                        Err(redis::RedisError::from((
+                            redis::ErrorKind::ClientError,
+                            "emit task failed",
+                            e.to_string(),
+                        )))
+                    })
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+    }
+
+    /// Publishes several packets at once, each `(event, args, rooms)` going
+    /// to the current namespace and room selection plus its own rooms, and
+    /// returns a result per item. The Redis broker sends them in a single
+    /// pipeline, also when proxied or on RESP3; other brokers publish them
+    /// one at a time unless they override `Broker::publish_batch`. Sampling
+    /// and rate limits are not applied.
+    pub fn emit_batch<R: IntoRooms>(
+        &self,
+        items: Vec<(&str, Vec<&str>, R)>,
+    ) -> Vec<redis::RedisResult<()>> {
+        let items: Vec<_> = items
+            .into_iter()
+            .map(|(event, args, rooms)| {
+                let mut targets = self.rooms.clone();
+                targets.extend(rooms.into_rooms());
+                let mut message = vec![event];
+                message.extend(args);
+                let serialized = self.serialize(&targets, &message);
+                if let Err(e) = &serialized {
+                    let result = Err(redis::RedisError::from((
+                        e.kind(),
+                        "packet rejected",
+                        e.to_string(),
+                    )));
+                    self.record_audit(&self.channel_for(&targets), Some(event), 0, &result);
+                }
+                serialized
+            })
+            .collect();
+        let mut results = self
+            .publish_packets(
+                items
+                    .iter()
+                    .flat_map(|serialized| serialized.iter().flatten()),
+            )
+            .into_iter();
+        items
+            .into_iter()
+            .map(|serialized| {
+                let mut item = Ok(());
+                let packets = serialized?;
+                for _ in &packets {
+                    let result = results.next().unwrap();
+                    if item.is_ok() {
+                        item = result;
+                    }
+                }
+                self.recycle(packets);
+                item
+            })
+            .collect()
+    }
+
+    /// Publishes packets in a single batch and reports each, returning a
+    /// result per packet.
+    fn publish_packets<'a, I>(&self, packets: I) -> Vec<redis::RedisResult<()>>
+    where
+        I: IntoIterator<Item = &'a Encoded>,
+    {
+        let packets: Vec<&Encoded> = packets.into_iter().collect();
+        let batch: Vec<(&str, &[u8])> = packets
+            .iter()
+            .map(|packet| (&*packet.channel, &*packet.payload))
+            .collect();
+        let started = std::time::Instant::now();
+        let results = match &self.dry_run {
+            Some(collector) => collector.publish_batch(&batch),
+            None => self.transport.broker().publish_batch(&batch),
+        };
+        let elapsed = started.elapsed();
+        packets
+            .into_iter()
+            .zip(results)
+            .map(|(packet, result)| {
+                #[cfg(feature = "tracing")]
+                let _span = self.emit_span(packet).entered();
+                self.report(packet, elapsed, &result);
+                result
+            })
+            .collect()
+    }
+
+    /// Keeps a published packet in the last-event cache, if there is one.
+    fn remember(&self, packet: &Encoded) {
+        let (cache, event) = match (&self.last_events, &packet.event, &self.dry_run) {
+            (Some(cache), Some(event), None) => (cache, event),
+            _ => return,
+        };
+        let stored = cache.store(
+            &self.prefix,
+            &packet.nsp,
+            &packet.rooms,
+            event,
+            &packet.payload,
+        );
+        if let Err(_e) = stored {
+            #[cfg(feature = "logging")]
+            log::warn!("could not cache last {} event: {}", event, _e);
+        }
+    }
+
+    /// Emits `event` with `args` to each socket of `ids` in the current
+    /// namespace, ignoring the room selection. Sockets are targeted through
+    /// their own rooms: in one publish, or with a sharded Redis adapter in
+    /// pipelined publishes to each socket's channel.
+    pub fn emit_to_sockets<I>(&self, ids: I, event: &str, args: Vec<&str>) -> redis::RedisResult<()>
+    where
+        I: IntoIterator,
+        I::Item: Into<SocketId>,
+    {
+        let rooms: BTreeSet<String> = ids
+            .into_iter()
+            .map(|id| id.into().into_room().into())
+            .collect();
+        if rooms.is_empty() {
+            return Ok(());
+        }
+        let emitter = self.clone().reset();
+        if matches!(&self.transport, Transport::Redis(redis) if redis.sharded) {
+            let items = rooms
+                .iter()
+                .map(|room| (event, args.clone(), room.as_str()))
+                .collect();
+            return emitter.emit_batch(items).into_iter().collect();
+        }
+        let mut message = vec![event];
+        message.extend(args);
+        emitter.send(&rooms, &message)
+    }
+
+    /// The channel for a packet to `rooms` in the current namespace.
+    fn channel_for(&self, rooms: &BTreeSet<String>) -> Arc<str> {
+        let room = match rooms.len() {
+            1 => rooms.iter().next().map(String::as_str),
+            _ => None,
+        };
+        self.scheme.channel(&self.prefix, &self.nsp, room)
+    }
+
+    fn channel_in(&self, nsp: &str, rooms: &[String]) -> Arc<str> {
+        let room = match rooms {
+            [room] => Some(room.as_str()),
+            _ => None,
+        };
+        self.scheme.channel(&self.prefix, nsp, room)
+    }
+
+    /// Builds the packet, passes it through the transformers, runs the
+    /// middleware on each resulting packet and serializes them. Without any
+    /// of those the packet is serialized straight from borrowed parts.
+    fn serialize(
+        &self,
+        rooms: &BTreeSet<String>,
+        message: &[&str],
+    ) -> redis::RedisResult<Vec<Encoded>> {
+        #[cfg(feature = "opentelemetry")]
+        let traceparent = codec::current_traceparent();
+        #[cfg(not(feature = "opentelemetry"))]
+        let traceparent = None;
+        let correlation_id = self.hooks.correlation_id.as_ref().and_then(|hook| hook());
+        let borrowed = self.hooks.transformers.is_empty()
+            && self.hooks.middleware.is_empty()
+            && !(self.per_room && rooms.len() > 1);
+        if borrowed {
+            let packet = PacketRef {
+                _type: 2,
+                data: message,
+                nsp: &self.nsp,
+            };
+            let opts = OptsRef {
+                rooms,
+                flags: self.flags,
+                traceparent,
+                correlation_id,
+                metadata: &self.metadata,
+            };
+            // The namespace and rooms are only kept for the last-event cache.
+            let cached = self.last_events.is_some();
+            return Ok(vec![Encoded {
+                channel: self.channel_for(rooms),
+                payload: self.payload(&packet, &opts),
+                event: message.first().map(|event| event.to_string()),
+                nsp: if cached {
+                    self.nsp.to_string()
+                } else {
+                    String::new()
+                },
+                rooms: if cached {
+                    rooms.iter().cloned().collect()
+                } else {
+                    Vec::new()
+                },
+            }]);
+        }
+
         let packet = Packet {
             _type: 2,
             data: message.iter().map(|s| s.to_string()).collect(),
-            nsp: self.nsp.clone(),
+            nsp: self.nsp.to_string(),
         };
         let opts = Opts {
-            rooms: self.rooms.clone(),
-            flags: self.flags.clone(),
+            rooms: rooms.iter().cloned().collect(),
+            flags: self.flags.to_map(),
+            traceparent,
+            correlation_id,
+            metadata: self.metadata.clone(),
         };
-        let mut msg = Vec::new();
-        let val = (self.uid.clone(), packet, opts);
-        val.serialize(&mut Serializer::new(&mut msg).with_struct_map())
-            .unwrap();
-
-        let channel = if self.rooms.len() == 1 {
-            format!("{}{}#", self.channel, self.rooms.join("#"))
+        let mut packets = if self.per_room && opts.rooms.len() > 1 {
+            opts.rooms
+                .iter()
+                .map(|room| {
+                    let rooms = vec![room.clone()];
+                    (
+                        packet.clone(),
+                        Opts {
+                            rooms,
+                            ..opts.clone()
+                        },
+                    )
+                })
+                .collect()
         } else {
-            self.channel.clone()
+            vec![(packet, opts)]
         };
-        let _: () = self.redis.publish(channel, msg).unwrap();
-        self.rooms = vec![];
-        self.flags = HashMap::new();
+        for transformer in &self.hooks.transformers {
+            packets = packets
+                .into_iter()
+                .flat_map(|(packet, opts)| transformer.transform(packet, opts))
+                .collect();
+        }
+        packets
+            .into_iter()
+            .map(|(mut packet, mut opts)| {
+                for middleware in &self.hooks.middleware {
+                    middleware.handle(&mut packet, &mut opts)?;
+                }
+                let channel = self.channel_in(&packet.nsp, &opts.rooms);
+                let payload = self.payload(&packet, &opts);
+                Ok(Encoded {
+                    channel,
+                    payload,
+                    event: packet.data.into_iter().next(),
+                    nsp: packet.nsp,
+                    rooms: opts.rooms,
+                })
+            })
+            .collect()
+    }
+
+    /// Serializes a packet inline when small, or into a pooled buffer if
+    /// there is a pool. Small packets serialize without allocating.
+    fn payload<P: Serialize, O: Serialize>(&self, packet: &P, opts: &O) -> pool::Payload {
+        let mut payload = match &self.buffers {
+            Some(pool) => pool::Payload::Heap(pool.take()),
+            None => pool::Payload::new(),
+        };
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("serialize").entered();
+        codec::encode_into(&mut payload, &self.uid, packet, opts);
+        payload
+    }
+
+    fn record_audit(
+        &self,
+        channel: &str,
+        event: Option<&str>,
+        size: usize,
+        result: &redis::RedisResult<()>,
+    ) {
+        if let Some(sink) = &self.hooks.audit {
+            sink.record(&AuditRecord {
+                timestamp: std::time::SystemTime::now(),
+                channel,
+                uid: &self.uid,
+                event,
+                size,
+                outcome: match (result, &self.dry_run) {
+                    (Err(e), _) => AuditOutcome::Failed(e),
+                    (Ok(()), Some(_)) => AuditOutcome::DryRun,
+                    (Ok(()), None) => AuditOutcome::Published,
+                },
+            });
+        }
+    }
+
+    /// The same emitter with another namespace type.
+    fn retype<M>(self) -> Emitter<M> {
+        Emitter {
+            transport: self.transport,
+            prefix: self.prefix,
+            nsp: self.nsp,
+            scheme: self.scheme,
+            rooms: self.rooms,
+            flags: self.flags,
+            metadata: self.metadata,
+            uid: self.uid,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+            hooks: self.hooks,
+            dry_run: self.dry_run,
+            sampler: self.sampler,
+            coalescer: self.coalescer,
+            queue: self.queue,
+            priority: self.priority,
+            rate_limiter: self.rate_limiter,
+            namespaces: self.namespaces,
+            schedule_store: self.schedule_store,
+            last_events: self.last_events,
+            per_room: self.per_room,
+            buffers: self.buffers,
+            #[cfg(feature = "remote")]
+            request_timeout: self.request_timeout,
+            namespace: PhantomData,
+        }
+    }
+
+    /// Clears the room and flag selection after an emit.
+    fn reset(mut self) -> Emitter<N> {
+        self.rooms = BTreeSet::new();
+        self.flags = flags::Flags::empty();
+        self.metadata = BTreeMap::new();
+        self.priority = Priority::Normal;
         self
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Emitter, Opts, Packet};
+    use crate::{
+        encode, AuditOutcome, AuditRecord, Broker, BrokerMessage, Emitter, EmitterOpts, Failover,
+        InMemoryBroker, LastEventCache, Mirror, Opts, Packet, RedisBroker, Transport,
+    };
     use redis::Msg;
     use rmp_serde::Deserializer;
     use serde::Deserialize;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+    use testcontainers::{clients, core::RunArgs, images, Docker};
 
     macro_rules! create_redis {
         ($redis:ident) => {
-            use testcontainers::{clients, core::RunArgs, images, Docker};
             let docker = clients::Cli::default();
             let container =
                 docker.run_with_args(images::redis::Redis::default(), RunArgs::default());
@@ -189,7 +1542,7 @@ mod tests {
         assert_eq!(
             Opts {
                 rooms: vec![],
-                flags: Default::default()
+                ..Default::default()
             },
             actual.2
         );
@@ -220,7 +1573,7 @@ mod tests {
         assert_eq!(
             Opts {
                 rooms: vec![],
-                flags: Default::default()
+                ..Default::default()
             },
             actual.2
         );
@@ -251,12 +1604,40 @@ mod tests {
         assert_eq!(
             Opts {
                 rooms: vec![],
-                flags: Default::default()
+                ..Default::default()
             },
             actual.2
         );
     }
 
+    #[test]
+    fn emit_with_last_event_cache() {
+        create_redis!(redis);
+        let cache = LastEventCache::new(redis.clone(), Duration::from_secs(60));
+        let io = Emitter::new(redis).last_event_cache(cache.clone());
+        io.clone().to("room1").emit(vec!["state", "1"]);
+        io.clone().to("room1").emit(vec!["state", "2"]);
+
+        let last = io.last_event("room1", "state").unwrap().unwrap();
+        assert_eq!(&["state", "2"], last.data());
+        assert_eq!(None, io.last_event("room2", "state").unwrap());
+
+        let packet = Packet {
+            _type: 2,
+            data: vec!["state".to_string(), "3".to_string()],
+            nsp: "/chat".to_string(),
+        };
+        let payload = encode("emitter", &packet, &Opts::default());
+        let rooms = ["room3".to_string()];
+        for _ in 0..3 {
+            cache
+                .store("socket.io", "/chat", &rooms, "state", &payload)
+                .unwrap();
+        }
+        let cached = cache.get("socket.io", "/chat", "room3", "state").unwrap();
+        assert_eq!(Some(packet), cached);
+    }
+
     #[test]
     fn emit_to_room() {
         create_redis!(redis);
@@ -282,9 +1663,444 @@ mod tests {
         assert_eq!(
             Opts {
                 rooms: vec!["room1".to_string()],
-                flags: Default::default()
+                ..Default::default()
             },
             actual.2
         );
     }
+
+    #[test]
+    fn emit_with_command_timeout() {
+        create_redis!(redis);
+        let mut con = redis.get_connection().unwrap();
+        let mut pubsub = con.as_pubsub();
+        pubsub.subscribe("socket.io#/#").unwrap();
+
+        // act
+        let io = Emitter::new(redis).command_timeout(Duration::from_secs(1));
+        io.emit(vec!["test"]);
+
+        // assert
+        let actual = decode_msg(pubsub.get_message().unwrap());
+        assert_eq!(
+            Packet {
+                _type: 2,
+                data: vec!["test".to_string()],
+                nsp: "/".to_string(),
+            },
+            actual.1
+        );
+    }
+
+    #[test]
+    fn emit_fails_over_to_next_endpoint() {
+        create_redis!(redis);
+        let mut con = redis.get_connection().unwrap();
+        let mut pubsub = con.as_pubsub();
+        pubsub.subscribe("socket.io#/#").unwrap();
+
+        // act
+        let unreachable = redis::Client::open("redis://127.0.0.1:1").unwrap();
+        let failover = Failover::from_clients(vec![unreachable, redis]).unwrap();
+        let io = Emitter::new(failover.clone());
+        io.emit(vec!["test"]);
+
+        // assert
+        let actual = decode_msg(pubsub.get_message().unwrap());
+        assert_eq!(vec!["test".to_string()], actual.1.data);
+        assert_eq!(1, failover.active());
+    }
+
+    #[test]
+    fn emit_to_mirror() {
+        create_redis!(primary);
+        create_redis!(secondary);
+        let mut primary_con = primary.get_connection().unwrap();
+        let mut primary_pubsub = primary_con.as_pubsub();
+        primary_pubsub.subscribe("socket.io#/#").unwrap();
+        let mut secondary_con = secondary.get_connection().unwrap();
+        let mut secondary_pubsub = secondary_con.as_pubsub();
+        secondary_pubsub.subscribe("socket.io#/#").unwrap();
+
+        // act
+        let io = Emitter::new(primary).mirror(Mirror::new(secondary));
+        io.emit(vec!["test"]);
+
+        // assert
+        let actual = decode_msg(primary_pubsub.get_message().unwrap());
+        assert_eq!(vec!["test".to_string()], actual.1.data);
+        let actual = decode_msg(secondary_pubsub.get_message().unwrap());
+        assert_eq!(vec!["test".to_string()], actual.1.data);
+    }
+
+    #[test]
+    fn emit_over_resp3() {
+        create_redis!(redis);
+        let mut con = redis.get_connection().unwrap();
+        let mut pubsub = con.as_pubsub();
+        pubsub.subscribe("socket.io#/#").unwrap();
+
+        // act
+        let io = Emitter::new(redis).resp3();
+        io.emit(vec!["test"]);
+
+        // assert
+        let actual = decode_msg(pubsub.get_message().unwrap());
+        assert_eq!(vec!["test".to_string()], actual.1.data);
+    }
+
+    #[derive(Default)]
+    struct RecordingBroker {
+        published: Arc<Mutex<Vec<BrokerMessage>>>,
+    }
+
+    impl Broker for RecordingBroker {
+        fn publish(&self, channel: &str, payload: &[u8]) -> redis::RedisResult<()> {
+            let mut published = self.published.lock().unwrap();
+            published.push(BrokerMessage {
+                channel: channel.to_string(),
+                payload: payload.to_vec(),
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn emit_with_custom_broker() {
+        let broker = RecordingBroker::default();
+        let published = broker.published.clone();
+
+        // act
+        let io = Emitter::with_broker(broker);
+        io.to("room1").emit(vec!["test"]);
+
+        // assert
+        let published = published.lock().unwrap();
+        assert_eq!(1, published.len());
+        assert_eq!("socket.io#/#room1#", published[0].channel);
+        let mut de = Deserializer::new(&published[0].payload[..]);
+        let actual: (String, Packet, Opts) = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(vec!["room1".to_string()], actual.2.rooms);
+    }
+
+    #[test]
+    fn custom_broker_ignores_redis_settings() {
+        let broker = InMemoryBroker::new();
+        let unreachable = redis::Client::open("redis://127.0.0.1:1").unwrap();
+        Emitter::with_broker(broker.clone())
+            .command_timeout(Duration::from_secs(1))
+            .mirror(Mirror::new(unreachable))
+            .sharded()
+            .resp3()
+            .emit(vec!["test"]);
+        assert_eq!(vec!["test"], broker.events());
+
+        let redis = RedisBroker::new(redis::Client::open("redis://127.0.0.1").unwrap())
+            .command_timeout(Duration::from_secs(1))
+            .sharded();
+        let io = Emitter::new(redis);
+        assert!(matches!(&io.transport, Transport::Redis(redis) if redis.sharded));
+    }
+
+    #[test]
+    fn clones_share_hooks_and_stores() {
+        let client = redis::Client::open("redis://127.0.0.1:1").unwrap();
+        let io = Emitter::with_broker(InMemoryBroker::new())
+            .on_publish(|_| {})
+            .last_event_cache(LastEventCache::new(client, Duration::from_secs(1)));
+        let clone = io.clone().to("room");
+        assert!(Arc::ptr_eq(&io.hooks, &clone.hooks));
+        let caches = (io.last_events.as_ref(), clone.last_events.as_ref());
+        assert!(matches!(caches, (Some(a), Some(b)) if Arc::ptr_eq(a, b)));
+
+        let extended = clone.on_publish(|_| {});
+        assert_eq!(1, io.hooks.on_publish.len());
+        assert_eq!(2, extended.hooks.on_publish.len());
+    }
+
+    #[derive(Default)]
+    struct PipeliningBroker {
+        batches: Arc<Mutex<Vec<Vec<String>>>>,
+    }
+
+    impl Broker for PipeliningBroker {
+        fn publish(&self, channel: &str, _payload: &[u8]) -> redis::RedisResult<()> {
+            self.batches.lock().unwrap().push(vec![channel.to_string()]);
+            Ok(())
+        }
+
+        fn publish_batch(&self, messages: &[(&str, &[u8])]) -> Vec<redis::RedisResult<()>> {
+            let channels = messages.iter().map(|(channel, _)| channel.to_string());
+            self.batches.lock().unwrap().push(channels.collect());
+            messages.iter().map(|_| Ok(())).collect()
+        }
+    }
+
+    #[test]
+    fn emit_per_room_in_one_pipeline() {
+        let broker = PipeliningBroker::default();
+        let batches = broker.batches.clone();
+
+        // act
+        let io = Emitter::with_broker(broker).per_room_fanout();
+        io.clone().to(vec!["a", "b", "c"]).emit(vec!["test"]);
+        io.to("a").emit(vec!["test"]);
+
+        // assert
+        let batches = batches.lock().unwrap();
+        assert_eq!(
+            vec![
+                vec!["socket.io#/#a#", "socket.io#/#b#", "socket.io#/#c#"],
+                vec!["socket.io#/#a#"],
+            ],
+            *batches
+        );
+    }
+
+    #[test]
+    fn reports_batched_packets() {
+        let slow = Arc::new(Mutex::new(Vec::new()));
+        let recorded = slow.clone();
+
+        // act
+        Emitter::with_broker(PipeliningBroker::default())
+            .per_room_fanout()
+            .on_slow_publish(Duration::from_secs(0), move |channel, _| {
+                recorded.lock().unwrap().push(channel.to_string())
+            })
+            .to(vec!["a", "b"])
+            .emit(vec!["test"]);
+
+        // assert
+        assert_eq!(
+            vec!["socket.io#/#a#", "socket.io#/#b#"],
+            *slow.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn emit_with_ttl() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+        let before = SystemTime::now();
+        io.to("presence")
+            .ttl(Duration::from_secs(5))
+            .emit(vec!["a"])
+            .emit(vec!["b"]);
+
+        let published = broker.published();
+        let expires_at = published[0].opts.expires_at().unwrap();
+        assert!(expires_at >= before + Duration::from_millis(4999));
+        assert!(expires_at <= SystemTime::now() + Duration::from_secs(5));
+        assert_eq!(None, published[1].opts.expires_at());
+    }
+
+    #[test]
+    fn emit_with_correlation_id() {
+        let broker = InMemoryBroker::new();
+        let ids = Arc::new(Mutex::new(vec!["req-2".to_string(), "req-1".to_string()]));
+        let io =
+            Emitter::with_broker(broker.clone()).correlation_id(move || ids.lock().unwrap().pop());
+        io.emit(vec!["a"]).emit(vec!["b"]).emit(vec!["c"]);
+
+        let ids: Vec<_> = broker
+            .published()
+            .iter()
+            .map(|p| p.opts.correlation_id().map(str::to_string))
+            .collect();
+        assert_eq!(
+            vec![Some("req-1".to_string()), Some("req-2".to_string()), None],
+            ids
+        );
+    }
+
+    #[test]
+    fn emit_reports_slow_publishes() {
+        struct SlowBroker;
+        impl Broker for SlowBroker {
+            fn publish(&self, _: &str, _: &[u8]) -> redis::RedisResult<()> {
+                std::thread::sleep(Duration::from_millis(20));
+                Ok(())
+            }
+        }
+
+        let slow = Arc::new(Mutex::new(Vec::new()));
+        let reported = slow.clone();
+        Emitter::with_broker(SlowBroker)
+            .on_slow_publish(Duration::from_millis(10), move |channel, elapsed| {
+                reported
+                    .lock()
+                    .unwrap()
+                    .push((channel.to_string(), elapsed));
+            })
+            .emit(vec!["test"]);
+        Emitter::with_broker(SlowBroker)
+            .on_slow_publish(Duration::from_secs(10), |_, _| panic!("not slow"))
+            .emit(vec!["test"]);
+
+        let slow = slow.lock().unwrap();
+        assert_eq!(1, slow.len());
+        assert_eq!("socket.io#/#", slow[0].0);
+        assert!(slow[0].1 >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn emit_in_dry_run() {
+        let broker = InMemoryBroker::new();
+        let collector = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .dry_run(collector.clone())
+            .of("/admin")
+            .to("room")
+            .emit(vec!["test"]);
+
+        assert!(broker.published().is_empty());
+        collector.assert_published_to("socket.io#/admin#room#");
+    }
+
+    #[test]
+    fn emit_with_audit_sink() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = records.clone();
+        let collector = InMemoryBroker::new();
+        let io = Emitter::with_broker(InMemoryBroker::new()).audit(move |r: &AuditRecord<'_>| {
+            let dry_run = matches!(r.outcome, AuditOutcome::DryRun);
+            sink.lock().unwrap().push((
+                r.channel.to_string(),
+                r.uid.to_string(),
+                r.event.map(str::to_string),
+                r.size > 0,
+                dry_run,
+            ));
+        });
+        io.clone().to("room").emit(vec!["test", "arg"]);
+        io.dry_run(collector).emit(vec![]);
+
+        assert_eq!(
+            vec![
+                (
+                    "socket.io#/#room#".to_string(),
+                    "emitter".to_string(),
+                    Some("test".to_string()),
+                    true,
+                    false
+                ),
+                (
+                    "socket.io#/#".to_string(),
+                    "emitter".to_string(),
+                    None,
+                    true,
+                    true
+                ),
+            ],
+            *records.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn emit_to_deduplicated_rooms() {
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .to("b")
+            .to("a")
+            .to(["b", "a"])
+            .emit(vec!["test"])
+            .to("a")
+            .to("a")
+            .emit(vec!["test"]);
+
+        let published = broker.published();
+        assert_eq!(&["a", "b"], published[0].opts.rooms());
+        assert_eq!("socket.io#/#a#", published[1].channel);
+    }
+
+    #[test]
+    fn emit_with_uid() {
+        let io = Emitter::new(EmitterOpts {
+            host: "127.0.0.1".to_owned(),
+            port: 6379,
+            uid: Some("billing-1"),
+            ..Default::default()
+        });
+        assert_eq!("billing-1", &*io.uid);
+
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .uid("billing-2")
+            .emit(vec!["test"]);
+        assert_eq!("billing-2", broker.published()[0].uid);
+    }
+
+    #[test]
+    fn clone_shares_internals() {
+        let io = Emitter::new("127.0.0.1").uid("svc");
+        let clone = io.clone().to("room");
+        assert!(Arc::ptr_eq(&io.prefix, &clone.prefix));
+        assert!(Arc::ptr_eq(&io.uid, &clone.uid));
+        let (Transport::Redis(a), Transport::Redis(b)) = (&io.transport, &clone.transport) else {
+            panic!("expected the Redis broker");
+        };
+        assert!(Arc::ptr_eq(a, b));
+
+        // Settings copy the broker on write instead of changing other clones.
+        let sharded = clone.sharded();
+        assert!(!matches!(&io.transport, Transport::Redis(redis) if redis.sharded));
+        assert!(matches!(&sharded.transport, Transport::Redis(redis) if redis.sharded));
+    }
+
+    #[test]
+    fn emit_batch() {
+        let broker = InMemoryBroker::new();
+        let results = Emitter::with_broker(broker.clone())
+            .of("/users")
+            .emit_batch(vec![
+                ("notify", vec!["a"], vec!["user:1"]),
+                ("notify", vec!["b"], vec!["user:2", "user:3"]),
+            ]);
+
+        assert!(results.iter().all(|r| r.is_ok()));
+        let published = broker.published();
+        assert_eq!("socket.io#/users#user:1#", published[0].channel);
+        assert_eq!(&["notify", "a"], published[0].packet.data());
+        assert_eq!("socket.io#/users#", published[1].channel);
+        assert_eq!(&["user:2", "user:3"], published[1].opts.rooms());
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn emit_stream() {
+        use crate::OutgoingEvent;
+        use futures_util::StreamExt;
+
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+        let events = futures_util::stream::iter(
+            (0..20).map(|i| OutgoingEvent::new(vec!["change"]).to(format!("doc:{}", i))),
+        );
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let results: Vec<_> = runtime.block_on(io.emit_stream(events, 4).collect());
+
+        assert_eq!(20, results.len());
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(20, broker.published().len());
+    }
+
+    #[test]
+    fn emit_with_in_memory_broker() {
+        let broker = InMemoryBroker::new();
+
+        // act
+        let io = Emitter::with_broker(broker.clone());
+        io.of("/custom").to("room1").emit(vec!["test", "payload"]);
+
+        // assert
+        let actual = broker.assert_emitted("test");
+        assert_eq!("socket.io#/custom#room1#", actual.channel);
+        assert_eq!("emitter", actual.uid);
+        assert_eq!("/custom", actual.packet.nsp());
+        assert_eq!(&["room1".to_string()], actual.opts.rooms());
+        broker.assert_not_emitted("other");
+    }
 }
@@ -0,0 +1,137 @@
+use crate::{decode, Broker};
+use redis::{ErrorKind, RedisError, RedisResult};
+use socketioxide::SocketIo;
+use std::fmt;
+use std::sync::Arc;
+
+/// Delivers packets straight to the sockets of a socketioxide server running
+/// in the same process, skipping the network round trip through Redis. The
+/// `broadcast` flag maps to the `broadcast` operator; volatile packets are
+/// dropped for sockets that can't take them instead of failing the emit.
+#[derive(Clone)]
+pub struct SocketioxideBroker {
+    io: SocketIo,
+    forward: Option<Arc<dyn Broker>>,
+}
+
+impl SocketioxideBroker {
+    pub fn new(io: SocketIo) -> SocketioxideBroker {
+        SocketioxideBroker { io, forward: None }
+    }
+
+    /// Publishes every packet through `broker` as well, so servers in other
+    /// processes still receive them.
+    pub fn forward<B: Broker + 'static>(mut self, broker: B) -> SocketioxideBroker {
+        self.forward = Some(Arc::new(broker));
+        self
+    }
+}
+
+impl Broker for SocketioxideBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let (_, packet, opts) = decode(payload).map_err(|e| {
+            RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+        })?;
+        // A namespace nobody registered has no sockets to deliver to.
+        if let (Some(operators), Some((event, args))) =
+            (self.io.of(packet.nsp()), packet.data().split_first())
+        {
+            let flag = |name: &str| opts.flags().get(name) == Some(&true);
+            let mut operators = operators.to(opts.rooms().to_vec());
+            if flag("broadcast") {
+                operators = operators.broadcast();
+            }
+            match operators.emit(event.clone(), args) {
+                Ok(()) => {}
+                // socketioxide has no volatile operator: volatile packets that
+                // a socket can't take, e.g. with a full buffer, are dropped.
+                Err(_) if flag("volatile") => {}
+                Err(e) => {
+                    return Err(RedisError::from((
+                        ErrorKind::IoError,
+                        "socketioxide error",
+                        e.to_string(),
+                    )))
+                }
+            }
+        }
+        match &self.forward {
+            Some(broker) => broker.publish(channel, payload),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for SocketioxideBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SocketioxideBroker")
+            .field("forward", &self.forward.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SocketioxideBroker;
+    use crate::{Emitter, InMemoryBroker};
+    use http_body_util::{BodyExt, Full};
+    use socketioxide::{extract::SocketRef, SocketIo};
+    use tower::ServiceExt;
+
+    #[test]
+    fn forwards_packets() {
+        let (_, io) = SocketIo::new_svc();
+        io.ns("/", |_: SocketRef| {});
+        let forward = InMemoryBroker::new();
+        let broker = SocketioxideBroker::new(io).forward(forward.clone());
+        Emitter::with_broker(broker)
+            .to("room")
+            .emit(vec!["event", "a"])
+            .of("/unknown")
+            .emit(vec!["event"]);
+        assert_eq!(forward.published().len(), 2);
+    }
+
+    /// Sends an engine.io long-polling request, returning the response body.
+    async fn poll<S, B>(svc: &S, sid: &str, body: &'static str) -> String
+    where
+        S: tower::Service<http::Request<Full<&'static [u8]>>, Response = http::Response<B>>,
+        S: Clone,
+        S::Error: std::fmt::Debug,
+        B: BodyExt,
+        B::Error: std::fmt::Debug,
+    {
+        let method = if body.is_empty() { "GET" } else { "POST" };
+        let request = http::Request::builder()
+            .method(method)
+            .uri(format!("/socket.io/?EIO=4&transport=polling&sid={}", sid))
+            .body(Full::new(body.as_bytes()))
+            .unwrap();
+        let response = svc.clone().oneshot(request).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn delivers_to_sockets() {
+        let (svc, io) = SocketIo::builder().max_buffer_size(1).build_svc();
+        io.ns("/", |socket: SocketRef| socket.join("room").unwrap());
+        let open = poll(&svc, "", "").await;
+        let sid = open.split('"').nth(3).unwrap().to_string();
+        assert_eq!("ok", poll(&svc, &sid, "40").await);
+        assert!(poll(&svc, &sid, "").await.starts_with("40{"));
+
+        let io = Emitter::with_broker(SocketioxideBroker::new(io));
+        io.clone().to("room").emit(vec!["event", "a"]);
+        assert_eq!(r#"42["event","a"]"#, poll(&svc, &sid, "").await);
+        io.clone().broadcast().emit(vec!["event", "b"]);
+        assert_eq!(r#"42["event","b"]"#, poll(&svc, &sid, "").await);
+
+        // The buffer holds one packet, so the second emit can't be delivered.
+        let emit = |io: &Emitter, arg| io.emit_batch(vec![("event", vec![arg], vec!["room"])]);
+        assert!(emit(&io, "c")[0].is_ok());
+        assert!(emit(&io, "d")[0].is_err());
+        assert!(emit(&io.clone().volatile(), "e")[0].is_ok());
+        assert_eq!(r#"42["event","c"]"#, poll(&svc, &sid, "").await);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{decode, Broker, Opts, Packet};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::sync::{Arc, Mutex};
+
+/// A packet captured by `InMemoryBroker`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Published {
+    pub channel: String,
+    pub uid: String,
+    pub packet: Packet,
+    pub opts: Opts,
+}
+
+impl Published {
+    /// The event name, i.e. the first element of the packet data.
+    pub fn event(&self) -> Option<&str> {
+        self.packet.data().first().map(String::as_str)
+    }
+}
+
+/// A broker that records every published packet instead of sending it, for
+/// testing emit logic without Redis. Clones share the same record, so keep a
+/// clone around after handing one to `Emitter::with_broker`.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryBroker {
+    published: Arc<Mutex<Vec<Published>>>,
+}
+
+impl InMemoryBroker {
+    pub fn new() -> InMemoryBroker {
+        InMemoryBroker::default()
+    }
+
+    pub fn published(&self) -> Vec<Published> {
+        self.published.lock().unwrap().clone()
+    }
+
+    /// Returns the recorded packets and clears the record.
+    pub fn take(&self) -> Vec<Published> {
+        std::mem::take(&mut *self.published.lock().unwrap())
+    }
+
+    pub fn clear(&self) {
+        self.published.lock().unwrap().clear();
+    }
+
+    pub fn events(&self) -> Vec<String> {
+        self.published()
+            .iter()
+            .filter_map(|p| p.event().map(str::to_string))
+            .collect()
+    }
+
+    /// Returns the first packet emitted as `event`, panicking if there is none.
+    pub fn assert_emitted(&self, event: &str) -> Published {
+        self.published()
+            .into_iter()
+            .find(|p| p.event() == Some(event))
+            .unwrap_or_else(|| {
+                panic!(
+                    "expected `{}` to be emitted, got {:?}",
+                    event,
+                    self.events()
+                )
+            })
+    }
+
+    pub fn assert_not_emitted(&self, event: &str) {
+        assert!(
+            !self.events().iter().any(|e| e == event),
+            "expected `{}` not to be emitted",
+            event
+        );
+    }
+
+    /// Returns the packets published on `channel`, panicking if there are none.
+    pub fn assert_published_to(&self, channel: &str) -> Vec<Published> {
+        let published: Vec<_> = self
+            .published()
+            .into_iter()
+            .filter(|p| p.channel == channel)
+            .collect();
+        assert!(
+            !published.is_empty(),
+            "expected a publish to `{}`, got {:?}",
+            channel,
+            self.published()
+                .iter()
+                .map(|p| p.channel.as_str())
+                .collect::<Vec<_>>()
+        );
+        published
+    }
+}
+
+impl Broker for InMemoryBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let (uid, packet, opts) = decode(payload).map_err(|e| {
+            RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+        })?;
+        self.published.lock().unwrap().push(Published {
+            channel: channel.to_string(),
+            uid,
+            packet,
+            opts,
+        });
+        Ok(())
+    }
+}
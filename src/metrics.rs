@@ -0,0 +1,75 @@
+use prometheus::{Histogram, HistogramOpts, IntCounter, Registry};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Prometheus metrics recorded for every emit. Emitters record into the ones
+/// registered in the default registry unless given others with
+/// `Emitter::metrics`.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    emits: IntCounter,
+    errors: IntCounter,
+    duration: Histogram,
+    bytes: Histogram,
+}
+
+impl Metrics {
+    pub fn register(registry: &Registry) -> prometheus::Result<Metrics> {
+        let metrics = Metrics {
+            emits: IntCounter::new("emits_total", "Packets emitted.")?,
+            errors: IntCounter::new("emit_errors_total", "Packets that failed to publish.")?,
+            duration: Histogram::with_opts(HistogramOpts::new(
+                "publish_duration_seconds",
+                "Time spent publishing a packet.",
+            ))?,
+            bytes: Histogram::with_opts(
+                HistogramOpts::new("payload_bytes", "Size of the encoded packets.")
+                    .buckets(prometheus::exponential_buckets(64.0, 4.0, 8)?),
+            )?,
+        };
+        registry.register(Box::new(metrics.emits.clone()))?;
+        registry.register(Box::new(metrics.errors.clone()))?;
+        registry.register(Box::new(metrics.duration.clone()))?;
+        registry.register(Box::new(metrics.bytes.clone()))?;
+        Ok(metrics)
+    }
+
+    /// The metrics registered in `prometheus::default_registry()`.
+    pub fn global() -> Metrics {
+        static GLOBAL: OnceLock<Metrics> = OnceLock::new();
+        GLOBAL
+            .get_or_init(|| Metrics::register(prometheus::default_registry()).unwrap())
+            .clone()
+    }
+
+    pub(crate) fn record(&self, size: usize, duration: Duration, ok: bool) {
+        self.emits.inc();
+        if !ok {
+            self.errors.inc();
+        }
+        self.duration.observe(duration.as_secs_f64());
+        self.bytes.observe(size as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use crate::{Emitter, InMemoryBroker};
+    use prometheus::Registry;
+
+    #[test]
+    fn records_emits() {
+        let registry = Registry::new();
+        let metrics = Metrics::register(&registry).unwrap();
+        Emitter::with_broker(InMemoryBroker::new())
+            .metrics(metrics.clone())
+            .emit(vec!["a"])
+            .emit(vec!["b"]);
+
+        assert_eq!(2, metrics.emits.get());
+        assert_eq!(0, metrics.errors.get());
+        assert_eq!(2, metrics.bytes.get_sample_count());
+        assert_eq!(4, registry.gather().len());
+    }
+}
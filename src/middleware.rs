@@ -0,0 +1,96 @@
+use crate::{Opts, Packet};
+use redis::{ErrorKind, RedisError};
+use std::error::Error;
+use std::fmt;
+
+/// Returned by a middleware to stop a packet from being published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    reason: String,
+}
+
+impl Rejection {
+    pub fn new<S: Into<String>>(reason: S) -> Rejection {
+        Rejection {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "packet rejected: {}", self.reason)
+    }
+}
+
+impl Error for Rejection {}
+
+impl From<Rejection> for RedisError {
+    fn from(rejection: Rejection) -> RedisError {
+        RedisError::from((ErrorKind::ClientError, "packet rejected", rejection.reason))
+    }
+}
+
+/// Runs on every packet before it is serialized, in the order registered
+/// with `Emitter::middleware`. It may change the packet and its opts, e.g.
+/// to scrub fields, or reject it, which fails `Emitter::try_emit`. Changed
+/// rooms also change the channel.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, packet: &mut Packet, opts: &mut Opts) -> Result<(), Rejection>;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&mut Packet, &mut Opts) -> Result<(), Rejection> + Send + Sync,
+{
+    fn handle(&self, packet: &mut Packet, opts: &mut Opts) -> Result<(), Rejection> {
+        self(packet, opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rejection;
+    use crate::{Emitter, InMemoryBroker, Opts, Packet};
+
+    #[test]
+    fn middleware_chain() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone())
+            .middleware(|packet: &mut Packet, _: &mut Opts| {
+                for arg in packet.data_mut().iter_mut().skip(1) {
+                    if arg.contains('@') {
+                        *arg = "[redacted]".to_string();
+                    }
+                }
+                Ok(())
+            })
+            .middleware(|_: &mut Packet, opts: &mut Opts| {
+                if opts.rooms().iter().any(|room| room.starts_with("other:")) {
+                    return Err(Rejection::new("room of another tenant"));
+                }
+                opts.rooms_mut().retain(|room| room != "internal");
+                Ok(())
+            });
+
+        io.clone()
+            .to(vec!["acme:room", "internal"])
+            .emit(vec!["signup", "jane@example.com"]);
+        let err = io
+            .clone()
+            .to("other:room")
+            .try_emit(vec!["signup"])
+            .unwrap_err();
+        assert!(err.to_string().contains("room of another tenant"));
+
+        let published = broker.published();
+        assert_eq!(1, published.len());
+        assert_eq!("socket.io#/#acme:room#", published[0].channel);
+        assert_eq!(&["signup", "[redacted]"], published[0].packet.data());
+        assert_eq!(&["acme:room"], published[0].opts.rooms());
+    }
+}
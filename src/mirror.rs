@@ -0,0 +1,46 @@
+use redis::RedisError;
+use std::fmt;
+use std::sync::Arc;
+
+pub type MirrorErrorHandler = Arc<dyn Fn(&redis::Client, &RedisError) + Send + Sync>;
+
+/// A secondary Redis target that receives a copy of every published packet.
+/// Failures are reported to the mirror's own handler and never affect the
+/// result of publishing to the primary.
+#[derive(Clone)]
+pub struct Mirror {
+    pub(crate) client: redis::Client,
+    pub(crate) on_error: Option<MirrorErrorHandler>,
+}
+
+impl Mirror {
+    pub fn new(client: redis::Client) -> Mirror {
+        Mirror {
+            client,
+            on_error: None,
+        }
+    }
+
+    pub fn on_error<F>(mut self, handler: F) -> Mirror
+    where
+        F: Fn(&redis::Client, &RedisError) + Send + Sync + 'static,
+    {
+        self.on_error = Some(Arc::new(handler));
+        self
+    }
+
+    pub(crate) fn report(&self, err: &RedisError) {
+        if let Some(handler) = &self.on_error {
+            handler(&self.client, err);
+        }
+    }
+}
+
+impl fmt::Debug for Mirror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mirror")
+            .field("client", &self.client)
+            .field("on_error", &self.on_error.is_some())
+            .finish()
+    }
+}
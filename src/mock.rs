@@ -0,0 +1,154 @@
+use crate::{Emitter, IntoRooms};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// The emitting surface of `Emitter`, so application code can take any
+/// `E: Emit` and be tested with `MockEmitter`.
+pub trait Emit: Sized {
+    fn to<R: IntoRooms>(self, rooms: R) -> Self;
+    fn of(self, nsp: &str) -> Self;
+    fn json(self) -> Self;
+    fn volatile(self) -> Self;
+    fn broadcast(self) -> Self;
+    fn emit(self, message: Vec<&str>) -> Self;
+}
+
+impl Emit for Emitter {
+    fn to<R: IntoRooms>(self, rooms: R) -> Emitter {
+        Emitter::to(self, rooms)
+    }
+    fn of(self, nsp: &str) -> Emitter {
+        <Emitter>::of(self, nsp)
+    }
+    fn json(self) -> Emitter {
+        Emitter::json(self)
+    }
+    fn volatile(self) -> Emitter {
+        Emitter::volatile(self)
+    }
+    fn broadcast(self) -> Emitter {
+        Emitter::broadcast(self)
+    }
+    fn emit(self, message: Vec<&str>) -> Emitter {
+        Emitter::emit(self, message)
+    }
+}
+
+/// An `emit` call captured by `MockEmitter`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EmitCall {
+    pub nsp: String,
+    pub rooms: Vec<String>,
+    pub flags: HashMap<String, bool>,
+    pub message: Vec<String>,
+}
+
+/// Records `emit` calls instead of publishing anything. Clones share the
+/// record.
+#[derive(Debug, Clone)]
+pub struct MockEmitter {
+    nsp: String,
+    rooms: BTreeSet<String>,
+    flags: HashMap<String, bool>,
+    calls: Arc<Mutex<Vec<EmitCall>>>,
+}
+
+impl Default for MockEmitter {
+    fn default() -> MockEmitter {
+        MockEmitter {
+            nsp: "/".to_string(),
+            rooms: BTreeSet::new(),
+            flags: HashMap::new(),
+            calls: Default::default(),
+        }
+    }
+}
+
+impl MockEmitter {
+    pub fn new() -> MockEmitter {
+        MockEmitter::default()
+    }
+
+    pub fn calls(&self) -> Vec<EmitCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The event names emitted so far, i.e. the first element of each message.
+    pub fn events(&self) -> Vec<String> {
+        self.calls()
+            .into_iter()
+            .filter_map(|call| call.message.into_iter().next())
+            .collect()
+    }
+
+    fn flag(mut self, flag: &str) -> MockEmitter {
+        self.flags = HashMap::new();
+        self.flags.insert(flag.to_string(), true);
+        self
+    }
+}
+
+impl Emit for MockEmitter {
+    fn to<R: IntoRooms>(mut self, rooms: R) -> MockEmitter {
+        self.rooms.extend(rooms.into_rooms());
+        self
+    }
+    fn of(self, nsp: &str) -> MockEmitter {
+        MockEmitter {
+            nsp: nsp.to_string(),
+            rooms: BTreeSet::new(),
+            flags: HashMap::new(),
+            calls: self.calls,
+        }
+    }
+    fn json(self) -> MockEmitter {
+        self.flag("json")
+    }
+    fn volatile(self) -> MockEmitter {
+        self.flag("volatile")
+    }
+    fn broadcast(self) -> MockEmitter {
+        self.flag("broadcast")
+    }
+    fn emit(mut self, message: Vec<&str>) -> MockEmitter {
+        self.calls.lock().unwrap().push(EmitCall {
+            nsp: self.nsp.clone(),
+            rooms: std::mem::take(&mut self.rooms).into_iter().collect(),
+            flags: std::mem::take(&mut self.flags),
+            message: message.iter().map(|s| s.to_string()).collect(),
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Emit, MockEmitter};
+    use crate::{Emitter, InMemoryBroker};
+
+    fn notify<E: Emit>(io: E, user: u32) {
+        io.of("/users")
+            .to(format!("user:{}", user))
+            .volatile()
+            .emit(vec!["notify", "hello"])
+            .emit(vec!["done"]);
+    }
+
+    #[test]
+    fn mock_and_real_emitters() {
+        let mock = MockEmitter::new();
+        notify(mock.clone(), 7);
+
+        let calls = mock.calls();
+        assert_eq!("/users", calls[0].nsp);
+        assert_eq!(vec!["user:7"], calls[0].rooms);
+        assert_eq!(Some(&true), calls[0].flags.get("volatile"));
+        assert_eq!(vec!["notify", "hello"], calls[0].message);
+        assert!(calls[1].rooms.is_empty());
+        assert_eq!(vec!["notify", "done"], mock.events());
+
+        let broker = InMemoryBroker::new();
+        notify(Emitter::with_broker(broker.clone()), 7);
+        assert_eq!(vec!["notify", "done"], broker.events());
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{decode, Broker};
+use mongodb::bson::{self, doc, Document};
+use mongodb::sync::Collection;
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+
+/// `EventType.BROADCAST` in @socket.io/mongo-adapter.
+const BROADCAST: i32 = 3;
+
+/// Inserts packets into the collection watched by @socket.io/mongo-adapter,
+/// using the same document layout as @socket.io/mongo-emitter. The collection
+/// should be capped (or have a TTL index on `createdAt`) like the adapter's.
+#[derive(Clone)]
+pub struct MongoBroker {
+    collection: Collection<Document>,
+}
+
+impl MongoBroker {
+    pub fn new(collection: Collection<Document>) -> MongoBroker {
+        MongoBroker { collection }
+    }
+}
+
+impl Broker for MongoBroker {
+    fn publish(&self, _channel: &str, payload: &[u8]) -> RedisResult<()> {
+        // The adapter routes by the document's `nsp` and rooms rather than by
+        // channel, so only the decoded packet matters here.
+        let document = document(payload, bson::DateTime::now())?;
+        self.collection
+            .insert_one(document, None)
+            .map(|_| ())
+            .map_err(|e| RedisError::from((ErrorKind::IoError, "MongoDB error", e.to_string())))
+    }
+}
+
+/// The broadcast document for a published payload, created at `created_at`.
+fn document(payload: &[u8], created_at: bson::DateTime) -> RedisResult<Document> {
+    let (uid, packet, opts) = decode(payload)
+        .map_err(|e| RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string())))?;
+    Ok(doc! {
+        "uid": uid,
+        "nsp": packet.nsp(),
+        "type": BROADCAST,
+        "data": {
+            "packet": bson::to_bson(&packet).map_err(bson_error)?,
+            "opts": {
+                "rooms": opts.rooms(),
+                "except": Vec::<String>::new(),
+                "flags": bson::to_bson(opts.flags()).map_err(bson_error)?,
+            },
+        },
+        "createdAt": created_at,
+    })
+}
+
+impl fmt::Debug for MongoBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MongoBroker")
+            .field("collection", &self.collection.namespace().to_string())
+            .finish()
+    }
+}
+
+fn bson_error(e: bson::ser::Error) -> RedisError {
+    RedisError::from((ErrorKind::TypeError, "BSON error", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::document;
+    use crate::{encode, Emitter, InMemoryBroker};
+    use mongodb::bson::{doc, DateTime};
+
+    #[test]
+    fn broadcast_documents() {
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .uid("emitter")
+            .of("/chat")
+            .to("room1")
+            .volatile()
+            .emit(vec!["message", "hi"]);
+        let published = &broker.published()[0];
+        let payload = encode(&published.uid, &published.packet, &published.opts);
+
+        let created_at = DateTime::from_millis(1_700_000_000_000);
+        assert_eq!(
+            doc! {
+                "uid": "emitter",
+                "nsp": "/chat",
+                "type": 3,
+                "data": {
+                    "packet": { "type": 2, "data": ["message", "hi"], "nsp": "/chat" },
+                    "opts": {
+                        "rooms": ["room1"],
+                        "except": [],
+                        "flags": { "volatile": true },
+                    },
+                },
+                "createdAt": created_at,
+            },
+            document(&payload, created_at).unwrap()
+        );
+        assert!(document(b"not msgpack", created_at).is_err());
+    }
+}
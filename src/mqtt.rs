@@ -0,0 +1,114 @@
+use crate::Broker;
+use redis::{ErrorKind, RedisError, RedisResult};
+use rumqttc::{Client, MqttOptions, QoS};
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+/// How long the event loop waits before reconnecting after its first failure.
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+/// The longest wait between reconnects; the wait doubles up to it.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Publishes packets to an MQTT broker. Channels map to topics by turning the
+/// `#` separators into `/`: `socket.io#/chat#room1#` becomes
+/// `socket.io/chat/room1`, and the root namespace is an empty level, e.g.
+/// `socket.io//room1`.
+#[derive(Clone)]
+pub struct MqttBroker {
+    client: Client,
+    qos: QoS,
+}
+
+impl MqttBroker {
+    /// Connects with `options` and drives the MQTT event loop on a background
+    /// thread for as long as the client is alive. After a connection error it
+    /// waits before reconnecting, from 100ms doubling up to 30s.
+    pub fn connect(options: MqttOptions) -> MqttBroker {
+        let (client, mut connection) = Client::new(options, 64);
+        thread::spawn(move || {
+            let mut backoff = None;
+            for event in connection.iter() {
+                match event {
+                    Ok(_) => backoff = None,
+                    // Stop only once every client handle has been dropped.
+                    Err(rumqttc::ConnectionError::RequestsDone) => break,
+                    // The client reconnects on the next iteration.
+                    Err(_e) => {
+                        let wait = next_backoff(backoff);
+                        #[cfg(feature = "logging")]
+                        log::warn!("MQTT connection failed, retrying in {:?}: {}", wait, _e);
+                        thread::sleep(wait);
+                        backoff = Some(wait);
+                    }
+                }
+            }
+        });
+        MqttBroker {
+            client,
+            qos: QoS::AtLeastOnce,
+        }
+    }
+
+    pub fn qos(mut self, qos: QoS) -> MqttBroker {
+        self.qos = qos;
+        self
+    }
+}
+
+fn next_backoff(previous: Option<Duration>) -> Duration {
+    previous.map_or(MIN_BACKOFF, |previous| (previous * 2).min(MAX_BACKOFF))
+}
+
+fn topic(channel: &str) -> String {
+    let mut segments: Vec<_> = channel.split('#').collect();
+    if segments.last() == Some(&"") {
+        segments.pop();
+    }
+    segments
+        .iter()
+        .map(|segment| {
+            segment
+                .trim_start_matches('/')
+                .replace(['+', '#', '/'], "_")
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Broker for MqttBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        self.client
+            .publish(topic(channel), self.qos, false, payload)
+            .map_err(|e| RedisError::from((ErrorKind::IoError, "MQTT error", e.to_string())))
+    }
+}
+
+impl fmt::Debug for MqttBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MqttBroker")
+            .field("qos", &self.qos)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_backoff, topic, MAX_BACKOFF, MIN_BACKOFF};
+    use std::time::Duration;
+
+    #[test]
+    fn maps_channels_to_topics() {
+        assert_eq!("socket.io/", topic("socket.io#/#"));
+        assert_eq!("socket.io//room1", topic("socket.io#/#room1#"));
+        assert_eq!("socket.io/chat/room_1", topic("socket.io#/chat#room/1#"));
+    }
+
+    #[test]
+    fn backs_off_reconnects() {
+        assert_eq!(MIN_BACKOFF, next_backoff(None));
+        assert_eq!(Duration::from_millis(200), next_backoff(Some(MIN_BACKOFF)));
+        assert_eq!(MAX_BACKOFF, next_backoff(Some(Duration::from_secs(20))));
+        assert_eq!(MAX_BACKOFF, next_backoff(Some(MAX_BACKOFF)));
+    }
+}
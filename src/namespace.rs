@@ -0,0 +1,134 @@
+use crate::{Emitter, IntoRooms};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A fixed set of namespaces, usually an enum, for `Emitter::typed`.
+///
+/// ```
+/// use socketio_rust_emitter::Namespace;
+///
+/// #[derive(Clone, Copy)]
+/// enum Nsp {
+///     Chat,
+///     Admin,
+/// }
+///
+/// impl Namespace for Nsp {
+///     fn name(&self) -> &str {
+///         match self {
+///             Nsp::Chat => "/chat",
+///             Nsp::Admin => "/admin",
+///         }
+///     }
+/// }
+/// ```
+pub trait Namespace: Clone {
+    fn name(&self) -> &str;
+}
+
+/// A long-lived handle for emitting to one namespace, returned by
+/// `Emitter::nsp`. Handles share the emitter's transport and can be kept in
+/// application state; unlike `Emitter`, emitting only needs `&self`.
+#[derive(Debug, Clone)]
+pub struct NamespaceEmitter {
+    emitter: Emitter,
+}
+
+impl NamespaceEmitter {
+    pub fn name(&self) -> &str {
+        &self.emitter.nsp
+    }
+
+    /// An emitter for this namespace, for targeting rooms or setting flags.
+    pub fn emitter(&self) -> Emitter {
+        self.emitter.clone()
+    }
+
+    pub fn to<R: IntoRooms>(&self, rooms: R) -> Emitter {
+        self.emitter().to(rooms)
+    }
+
+    pub fn emit(&self, message: Vec<&str>) {
+        self.emitter().emit(message);
+    }
+}
+
+/// Handles created so far, by namespace name. Shared between clones of an
+/// emitter.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Namespaces {
+    handles: Arc<Mutex<HashMap<String, NamespaceEmitter>>>,
+}
+
+impl Namespaces {
+    pub(crate) fn get(&self, emitter: &Emitter, nsp: &str) -> NamespaceEmitter {
+        self.handles
+            .lock()
+            .unwrap()
+            .entry(nsp.to_string())
+            .or_insert_with(|| {
+                // The handle gets a cache of its own, so it does not keep
+                // this one alive.
+                let mut emitter = emitter.clone().of(nsp);
+                emitter.namespaces = Namespaces::default();
+                NamespaceEmitter { emitter }
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Emitter, InMemoryBroker, Namespace};
+
+    #[derive(Clone, Copy)]
+    enum Nsp {
+        Chat,
+        Admin,
+    }
+
+    impl Namespace for Nsp {
+        fn name(&self) -> &str {
+            match self {
+                Nsp::Chat => "/chat",
+                Nsp::Admin => "/admin",
+            }
+        }
+    }
+
+    #[test]
+    fn namespace_handles() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+        let chat = io.nsp("/chat");
+        io.clone().to("ignored").nsp("/chat").emit(vec!["first"]);
+        chat.emit(vec!["second"]);
+        chat.to("room").emit(vec!["third"]);
+
+        assert_eq!("/chat", chat.name());
+        let channels: Vec<_> = broker.published().into_iter().map(|p| p.channel).collect();
+        assert_eq!(
+            vec![
+                "socket.io#/chat#",
+                "socket.io#/chat#",
+                "socket.io#/chat#room#"
+            ],
+            channels
+        );
+    }
+
+    #[test]
+    fn typed_namespaces() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone()).typed::<Nsp>();
+        io.clone().emit(vec!["root"]);
+        io.clone().of(Nsp::Chat).to("room").emit(vec!["chat"]);
+        io.of(Nsp::Admin).json().emit(vec!["admin"]);
+
+        let channels: Vec<_> = broker.published().into_iter().map(|p| p.channel).collect();
+        assert_eq!(
+            vec!["socket.io#/#", "socket.io#/chat#room#", "socket.io#/admin#"],
+            channels
+        );
+    }
+}
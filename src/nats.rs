@@ -0,0 +1,177 @@
+use crate::{Broker, BrokerMessage, Source, SourceMessage, Subscription};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub type SubjectMapper = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Publishes packets on NATS subjects derived from the socket.io channel names.
+#[derive(Clone)]
+pub struct NatsBroker {
+    con: nats::Connection,
+    subject: SubjectMapper,
+}
+
+impl NatsBroker {
+    pub fn new(con: nats::Connection) -> NatsBroker {
+        NatsBroker {
+            con,
+            subject: Arc::new(default_subject),
+        }
+    }
+
+    pub fn connect(url: &str) -> RedisResult<NatsBroker> {
+        Ok(NatsBroker::new(nats::connect(url).map_err(nats_error)?))
+    }
+
+    /// Overrides how channel names are turned into subjects.
+    pub fn subject_mapper<F>(mut self, mapper: F) -> NatsBroker
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.subject = Arc::new(mapper);
+        self
+    }
+}
+
+/// Maps `socket.io#/chat#room1#` to `socket_io./chat.room1`: each `#`-separated
+/// segment becomes a subject token, with characters NATS reserves in tokens
+/// replaced by `_`.
+fn default_subject(channel: &str) -> String {
+    channel
+        .split('#')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            segment
+                .chars()
+                .map(|c| match c {
+                    '.' | '*' | '>' => '_',
+                    c if c.is_whitespace() => '_',
+                    c => c,
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+impl Broker for NatsBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        self.con
+            .publish(&(self.subject)(channel), payload)
+            .map_err(nats_error)
+    }
+
+    fn subscribe(&self, channels: &[String]) -> RedisResult<Box<dyn Subscription>> {
+        let subscriptions = channels
+            .iter()
+            .map(|channel| {
+                let sub = self
+                    .con
+                    .subscribe(&(self.subject)(channel))
+                    .map_err(nats_error)?;
+                Ok((channel.clone(), sub))
+            })
+            .collect::<RedisResult<Vec<_>>>()?;
+        Ok(Box::new(NatsSubscription { subscriptions }))
+    }
+}
+
+impl fmt::Debug for NatsBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NatsBroker").finish()
+    }
+}
+
+struct NatsSubscription {
+    subscriptions: Vec<(String, nats::Subscription)>,
+}
+
+impl Subscription for NatsSubscription {
+    fn next_message(&mut self, timeout: Duration) -> RedisResult<Option<BrokerMessage>> {
+        // Subscriptions are polled in turn, splitting the timeout between them.
+        let slice = timeout / self.subscriptions.len().max(1) as u32;
+        for (channel, sub) in &self.subscriptions {
+            match sub.next_timeout(slice) {
+                Ok(msg) => {
+                    return Ok(Some(BrokerMessage {
+                        channel: channel.clone(),
+                        payload: msg.data,
+                    }))
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(nats_error(e)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Reads a NATS subject as a bridge `Source`, in a queue group so bridge
+/// instances share the messages. Core NATS has no acknowledgements, so
+/// committing does nothing and delivery is at most once.
+pub struct NatsSource {
+    sub: nats::Subscription,
+}
+
+impl NatsSource {
+    pub fn new(con: &nats::Connection, subject: &str, queue: &str) -> RedisResult<NatsSource> {
+        let sub = con.queue_subscribe(subject, queue).map_err(nats_error)?;
+        Ok(NatsSource { sub })
+    }
+}
+
+impl Source for NatsSource {
+    fn poll(&mut self, max: usize, timeout: Duration) -> RedisResult<Vec<SourceMessage>> {
+        let first = match self.sub.next_timeout(timeout) {
+            Ok(msg) => msg,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => return Ok(Vec::new()),
+            Err(e) => return Err(nats_error(e)),
+        };
+        let mut messages = vec![first];
+        while messages.len() < max {
+            match self.sub.try_next() {
+                Some(msg) => messages.push(msg),
+                None => break,
+            }
+        }
+        Ok(messages
+            .into_iter()
+            .map(|msg| SourceMessage {
+                topic: msg.subject,
+                key: None,
+                payload: msg.data,
+            })
+            .collect())
+    }
+
+    fn commit(&mut self) -> RedisResult<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for NatsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NatsSource").finish()
+    }
+}
+
+fn nats_error(e: io::Error) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "NATS error", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::default_subject;
+
+    #[test]
+    fn maps_channels_to_subjects() {
+        assert_eq!("socket_io./", default_subject("socket.io#/#"));
+        assert_eq!(
+            "socket_io./chat.room_1",
+            default_subject("socket.io#/chat#room 1#")
+        );
+    }
+}
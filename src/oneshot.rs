@@ -0,0 +1,206 @@
+use crate::broker::Transport;
+use crate::{Broker, Emitter, IntoEmitter, IntoRooms};
+use redis::RedisResult;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+/// How long connecting and each command may take when the source sets no
+/// command timeout.
+const DEFAULT_DEADLINE: Duration = Duration::from_secs(2);
+
+/// Connections kept between one-shot emits, so warm serverless invocations
+/// skip the connection setup. Keyed by address and database.
+fn connections() -> &'static Mutex<HashMap<String, redis::Connection>> {
+    static CONNECTIONS: OnceLock<Mutex<HashMap<String, redis::Connection>>> = OnceLock::new();
+    CONNECTIONS.get_or_init(Default::default)
+}
+
+/// Publishes over a connection from the process-wide cache, opening one on
+/// first use and replacing it when it went stale, e.g. while a Lambda
+/// execution environment was frozen.
+struct OneShotBroker {
+    client: redis::Client,
+    deadline: Duration,
+    command: &'static str,
+}
+
+impl OneShotBroker {
+    fn key(&self) -> String {
+        let info = self.client.get_connection_info();
+        format!("{}/{}", info.addr, info.redis.db)
+    }
+
+    fn connect(&self) -> RedisResult<redis::Connection> {
+        let con = self.client.get_connection_with_timeout(self.deadline)?;
+        con.set_read_timeout(Some(self.deadline))?;
+        con.set_write_timeout(Some(self.deadline))?;
+        Ok(con)
+    }
+
+    fn query(&self, con: &mut redis::Connection, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        redis::cmd(self.command)
+            .arg(channel)
+            .arg(payload)
+            .query(con)
+    }
+}
+
+impl Broker for OneShotBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let key = self.key();
+        let cached = connections().lock().unwrap().remove(&key);
+        // A cached connection may have been closed by the server, so it is
+        // checked first; a failed publish isn't retried, as it may have
+        // reached Redis.
+        let mut con = match cached.and_then(|mut con| ping(&mut con).map(|()| con).ok()) {
+            Some(con) => con,
+            None => self.connect()?,
+        };
+        self.query(&mut con, channel, payload)?;
+        connections().lock().unwrap().insert(key, con);
+        Ok(())
+    }
+}
+
+fn ping(con: &mut redis::Connection) -> RedisResult<()> {
+    redis::cmd("PING").query(con)
+}
+
+/// An emit for short-lived processes such as AWS Lambda functions, created
+/// with `Emitter::one_shot`. It connects on the first emit, bounds connecting
+/// and publishing by the command timeout and keeps the connection in a
+/// global handle for the next invocation of a warm instance. Only the
+/// address, credentials, database, command timeout and sharding of the source
+/// are used: failover, mirrors, proxies, token providers, RESP3 and streams
+/// are not.
+#[derive(Debug, Clone)]
+pub struct OneShot {
+    emitter: Emitter,
+}
+
+impl OneShot {
+    pub fn of(self, nsp: &str) -> OneShot {
+        OneShot {
+            emitter: self.emitter.of(nsp),
+        }
+    }
+
+    pub fn to<R: IntoRooms>(self, rooms: R) -> OneShot {
+        OneShot {
+            emitter: self.emitter.to(rooms),
+        }
+    }
+
+    /// Publishes `message` and returns the outcome instead of panicking.
+    pub fn emit(self, message: Vec<&str>) -> RedisResult<()> {
+        self.emitter.send(&self.emitter.rooms, &message)
+    }
+}
+
+impl Emitter {
+    /// Creates a `OneShot` emit from any emitter source, e.g. `EmitterOpts`.
+    pub fn one_shot<I: IntoEmitter>(data: I) -> OneShot {
+        let mut emitter = data.into_emitter();
+        if let Transport::Redis(redis) = &emitter.transport {
+            let broker = OneShotBroker {
+                client: redis.client.clone(),
+                deadline: redis.command_timeout.unwrap_or(DEFAULT_DEADLINE),
+                command: if redis.sharded { "SPUBLISH" } else { "PUBLISH" },
+            };
+            emitter.transport = Transport::Custom(Arc::new(broker));
+        }
+        OneShot { emitter }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmitterOpts;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[derive(Default)]
+    struct Counts {
+        accepted: AtomicUsize,
+        publishes: AtomicUsize,
+    }
+
+    /// Answers every command with `:0` and closes each connection after
+    /// `commands` commands, or on the first PUBLISH without answering it if
+    /// `hang_up` is set.
+    fn fake_redis(commands: usize, hang_up: bool) -> (u16, Arc<Counts>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let counts = Arc::new(Counts::default());
+        let counted = counts.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                counted.accepted.fetch_add(1, Ordering::SeqCst);
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                for _ in 0..commands {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let args: usize = line.trim()[1..].parse().unwrap();
+                    let mut name = Vec::new();
+                    for _ in 0..args {
+                        line.clear();
+                        reader.read_line(&mut line).unwrap();
+                        let mut arg = vec![0; line.trim()[1..].parse::<usize>().unwrap() + 2];
+                        reader.read_exact(&mut arg).unwrap();
+                        if name.is_empty() {
+                            name = arg;
+                        }
+                    }
+                    if name.starts_with(b"PUBLISH") {
+                        counted.publishes.fetch_add(1, Ordering::SeqCst);
+                        if hang_up {
+                            break;
+                        }
+                    }
+                    stream.write_all(b":0\r\n").unwrap();
+                }
+            }
+        });
+        (port, counts)
+    }
+
+    fn opts(port: u16) -> EmitterOpts<'static> {
+        EmitterOpts {
+            host: "127.0.0.1".to_string(),
+            port: port as i32,
+            command_timeout: Some(Duration::from_millis(500)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reuses_connections() {
+        let (port, counts) = fake_redis(3, false);
+        for _ in 0..2 {
+            Emitter::one_shot(opts(port))
+                .to("room")
+                .emit(vec!["event", "data"])
+                .unwrap();
+        }
+        assert_eq!(1, counts.accepted.load(Ordering::SeqCst));
+
+        // The server closed the connection after a PUBLISH, a PING and
+        // another PUBLISH.
+        Emitter::one_shot(opts(port)).emit(vec!["event"]).unwrap();
+        assert_eq!(2, counts.accepted.load(Ordering::SeqCst));
+        assert_eq!(3, counts.publishes.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn does_not_retry_publishes() {
+        let (port, counts) = fake_redis(2, true);
+        assert!(Emitter::one_shot(opts(port)).emit(vec!["event"]).is_err());
+        assert_eq!(1, counts.publishes.load(Ordering::SeqCst));
+        assert_eq!(1, counts.accepted.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::Emitter;
+use redis::{ErrorKind, RedisError, RedisResult};
+use sqlx::{PgConnection, PgPool, Row};
+use std::time::Duration;
+
+/// A transactional outbox in a Postgres table. `enqueue` serializes an emit
+/// into the table using the caller's transaction, and a relay publishes the
+/// committed rows in order, so rolled-back writes never notify anyone.
+///
+/// The table name is interpolated into the SQL as is.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    table: String,
+    batch_size: usize,
+}
+
+impl Outbox {
+    pub fn new(table: &str) -> Outbox {
+        Outbox {
+            table: table.to_string(),
+            batch_size: 100,
+        }
+    }
+
+    /// The most rows published per relay round, 100 by default.
+    pub fn batch_size(mut self, batch_size: usize) -> Outbox {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Creates the outbox table if it does not exist.
+    pub async fn create_table(&self, pool: &PgPool) -> RedisResult<()> {
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} (id BIGSERIAL PRIMARY KEY, channel TEXT NOT NULL, payload BYTEA NOT NULL)",
+            self.table
+        );
+        sqlx::query(&sql).execute(pool).await.map_err(sql_error)?;
+        Ok(())
+    }
+
+    /// Writes the packets `emitter` would publish for `message` to the
+    /// outbox through `con`, usually an open transaction. Sampling and rate
+    /// limits are not applied.
+    pub async fn enqueue<N: Clone>(
+        &self,
+        con: &mut PgConnection,
+        emitter: &Emitter<N>,
+        message: Vec<&str>,
+    ) -> RedisResult<()> {
+        let packets = emitter.serialize(&emitter.rooms, &message)?;
+        let sql = format!(
+            "INSERT INTO {} (channel, payload) VALUES ($1, $2)",
+            self.table
+        );
+        for packet in packets {
+            sqlx::query(&sql)
+                .bind(packet.channel.to_string())
+                .bind(packet.payload.to_vec())
+                .execute(&mut *con)
+                .await
+                .map_err(sql_error)?;
+        }
+        Ok(())
+    }
+
+    /// Publishes one batch of committed rows through `emitter`'s broker and
+    /// deletes them, returning how many were published. Rows are locked
+    /// with `SKIP LOCKED`, so several relays can share a table. A failed
+    /// publish keeps that row and the ones after it for the next round.
+    pub async fn relay(&self, pool: &PgPool, emitter: &Emitter) -> RedisResult<usize> {
+        let mut tx = pool.begin().await.map_err(sql_error)?;
+        let sql = format!(
+            "SELECT id, channel, payload FROM {} ORDER BY id LIMIT {} FOR UPDATE SKIP LOCKED",
+            self.table, self.batch_size
+        );
+        let rows = sqlx::query(&sql)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(sql_error)?;
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        let mut ids = Vec::with_capacity(rows.len());
+        let mut messages = Vec::with_capacity(rows.len());
+        for row in rows {
+            ids.push(row.try_get::<i64, _>("id").map_err(sql_error)?);
+            messages.push((
+                row.try_get::<String, _>("channel").map_err(sql_error)?,
+                row.try_get::<Vec<u8>, _>("payload").map_err(sql_error)?,
+            ));
+        }
+        let emitter = emitter.clone();
+        let results = tokio::task::spawn_blocking(move || {
+            let messages: Vec<_> = messages
+                .iter()
+                .map(|(channel, payload)| (channel.as_str(), payload.as_slice()))
+                .collect();
+            emitter.transport.broker().publish_batch(&messages)
+        })
+        .await
+        .map_err(|e| {
+            RedisError::from((ErrorKind::ClientError, "relay task failed", e.to_string()))
+        })?;
+        let published = results.iter().take_while(|result| result.is_ok()).count();
+        let sql = format!("DELETE FROM {} WHERE id = ANY($1)", self.table);
+        sqlx::query(&sql)
+            .bind(&ids[..published])
+            .execute(&mut *tx)
+            .await
+            .map_err(sql_error)?;
+        tx.commit().await.map_err(sql_error)?;
+        match results.into_iter().find_map(Result::err) {
+            Some(e) => Err(e),
+            None => Ok(published),
+        }
+    }
+
+    /// Spawns a Tokio task relaying the outbox, waiting `interval` whenever
+    /// it is drained or a round fails.
+    pub fn spawn_relay(
+        self,
+        pool: PgPool,
+        emitter: Emitter,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match self.relay(&pool, &emitter).await {
+                    Ok(published) if published == self.batch_size => continue,
+                    Ok(_) => {}
+                    Err(_e) => {
+                        #[cfg(feature = "logging")]
+                        log::warn!("relaying outbox {} failed: {}", self.table, _e);
+                    }
+                }
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+}
+
+fn sql_error(e: sqlx::Error) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "outbox error", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryBroker;
+    use testcontainers::{clients, images, Docker};
+
+    #[test]
+    fn relays_committed_emits() {
+        let docker = clients::Cli::default();
+        let container = docker.run(images::postgres::Postgres::default());
+        let url = format!(
+            "postgres://postgres@localhost:{}/postgres",
+            container.get_host_port(5432).unwrap()
+        );
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+        runtime.block_on(async {
+            let pool = PgPool::connect(&url).await.unwrap();
+            let outbox = Outbox::new("socketio_outbox").batch_size(2);
+            outbox.create_table(&pool).await.unwrap();
+
+            let mut tx = pool.begin().await.unwrap();
+            outbox
+                .enqueue(&mut tx, &io.clone().to("news"), vec!["rolled back"])
+                .await
+                .unwrap();
+            tx.rollback().await.unwrap();
+            let mut tx = pool.begin().await.unwrap();
+            for event in &["first", "second", "third"] {
+                outbox
+                    .enqueue(&mut tx, &io.clone().to("news"), vec![event])
+                    .await
+                    .unwrap();
+            }
+            tx.commit().await.unwrap();
+
+            assert_eq!(2, outbox.relay(&pool, &io).await.unwrap());
+            assert_eq!(1, outbox.relay(&pool, &io).await.unwrap());
+            assert_eq!(0, outbox.relay(&pool, &io).await.unwrap());
+        });
+        let events: Vec<_> = broker
+            .published()
+            .iter()
+            .map(|p| (p.channel.clone(), p.event().unwrap().to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("socket.io#/#news#".to_string(), "first".to_string()),
+                ("socket.io#/#news#".to_string(), "second".to_string()),
+                ("socket.io#/#news#".to_string(), "third".to_string()),
+            ],
+            events
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use redis::RedisResult;
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+/// How many buffers each thread keeps.
+const POOLED_BUFFERS: usize = 16;
+
+/// Packets up to this many bytes are serialized without a heap allocation.
+/// Only the serialization is covered: building the packet, its channel and
+/// the publish itself still allocate.
+const INLINE: usize = 512;
+
+/// A serialized packet, kept inline while it is small and moved to the heap
+/// once it outgrows the inline buffer.
+// Being large inline is the point: it keeps small packets off the heap.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Payload {
+    Inline { buf: [u8; INLINE], len: usize },
+    Heap(Vec<u8>),
+}
+
+impl Payload {
+    pub(crate) fn new() -> Payload {
+        Payload::Inline {
+            buf: [0; INLINE],
+            len: 0,
+        }
+    }
+
+    /// The heap buffer, to give back to a pool.
+    pub(crate) fn into_heap(self) -> Option<Vec<u8>> {
+        match self {
+            Payload::Inline { .. } => None,
+            Payload::Heap(buffer) => Some(buffer),
+        }
+    }
+}
+
+impl Deref for Payload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Payload::Inline { buf, len } => &buf[..*len],
+            Payload::Heap(buffer) => buffer,
+        }
+    }
+}
+
+impl io::Write for Payload {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Payload::Inline { buf, len } if *len + data.len() <= INLINE => {
+                buf[*len..*len + data.len()].copy_from_slice(data);
+                *len += data.len();
+            }
+            Payload::Inline { buf, len } => {
+                let mut buffer = Vec::with_capacity(2 * INLINE + data.len());
+                buffer.extend_from_slice(&buf[..*len]);
+                buffer.extend_from_slice(data);
+                *self = Payload::Heap(buffer);
+            }
+            Payload::Heap(buffer) => buffer.extend_from_slice(data),
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+thread_local! {
+    static BUFFERS: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Thread-local serialization buffers, reused across emits instead of
+/// allocating one per packet. Buffers grown beyond `max_size` bytes are
+/// freed rather than kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BufferPool {
+    max_size: usize,
+}
+
+impl BufferPool {
+    pub(crate) fn new(max_size: usize) -> BufferPool {
+        BufferPool { max_size }
+    }
+
+    /// An empty buffer, pooled when one is available.
+    pub(crate) fn take(&self) -> Vec<u8> {
+        BUFFERS
+            .with(|buffers| buffers.borrow_mut().pop())
+            .unwrap_or_default()
+    }
+
+    /// Hands a buffer back once its packet is published.
+    pub(crate) fn give(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() > self.max_size {
+            return;
+        }
+        buffer.clear();
+        BUFFERS.with(|buffers| {
+            let mut buffers = buffers.borrow_mut();
+            if buffers.len() < POOLED_BUFFERS {
+                buffers.push(buffer);
+            }
+        });
+    }
+}
+
+/// How many idle Redis connections a `ConnectionPool` keeps.
+const POOLED_CONNECTIONS: usize = 8;
+
+/// Redis connections kept open between commands. Each caller gets its own
+/// connection, so concurrent commands don't queue behind each other; one
+/// that failed is closed instead of being handed back.
+#[derive(Clone)]
+pub(crate) struct ConnectionPool {
+    client: redis::Client,
+    idle: Arc<Mutex<Vec<redis::Connection>>>,
+}
+
+impl ConnectionPool {
+    pub(crate) fn new(client: redis::Client) -> ConnectionPool {
+        ConnectionPool {
+            client,
+            idle: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Runs `command` on a pooled connection, opening one if none is idle.
+    pub(crate) fn run<T, F>(&self, command: F) -> RedisResult<T>
+    where
+        F: FnOnce(&mut redis::Connection) -> RedisResult<T>,
+    {
+        let pooled = self.idle.lock().unwrap().pop();
+        let mut con = match pooled {
+            Some(con) => con,
+            None => self.client.get_connection()?,
+        };
+        let result = command(&mut con);
+        if result.is_ok() {
+            let mut idle = self.idle.lock().unwrap();
+            if idle.len() < POOLED_CONNECTIONS {
+                idle.push(con);
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("client", &self.client)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_buffers() {
+        let pool = BufferPool::new(64);
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(b"packet");
+        let reused = buffer.as_ptr();
+        pool.give(buffer);
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(reused, buffer.as_ptr());
+
+        pool.give(vec![0; 128]);
+        assert_eq!(0, pool.take().capacity());
+    }
+
+    #[test]
+    fn small_payloads_stay_inline() {
+        use std::io::Write;
+
+        let mut payload = Payload::new();
+        payload.write_all(&[1; 500]).unwrap();
+        assert!(matches!(payload, Payload::Inline { len: 500, .. }));
+        payload.write_all(&[2; 100]).unwrap();
+        assert_eq!(600, payload.len());
+        assert_eq!([1, 2], [payload[499], payload[500]]);
+        assert!(payload.into_heap().is_some());
+    }
+}
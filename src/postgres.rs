@@ -0,0 +1,214 @@
+use crate::{decode, Broker, Opts, Packet};
+use postgres::{Client, NoTls};
+use redis::{ErrorKind, RedisError, RedisResult};
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// `EventType.BROADCAST` in @socket.io/postgres-adapter.
+const BROADCAST: i32 = 3;
+
+/// Publishes packets in the format of @socket.io/postgres-emitter, for
+/// servers running @socket.io/postgres-adapter.
+///
+/// Each packet is a JSON document sent with `pg_notify` on
+/// `<prefix>#<namespace>`. Documents over the payload threshold (8000 bytes
+/// by default, under NOTIFY's 8KB limit) are inserted msgpack-encoded into
+/// the adapter's attachments table instead, and the notification only
+/// carries the row id. Packets are not split into chunks: the adapter can't
+/// reassemble them, and this is how it handles large packets itself.
+/// Requests to the servers, such as `fetch_sockets`, are not supported: the
+/// adapter answers them in its own protocol.
+///
+/// A lost connection is reopened on the next publish.
+#[derive(Clone)]
+pub struct PostgresBroker {
+    client: Arc<Mutex<Client>>,
+    params: String,
+    channel_prefix: String,
+    table: String,
+    payload_threshold: usize,
+}
+
+impl PostgresBroker {
+    pub fn connect(params: &str) -> RedisResult<PostgresBroker> {
+        Ok(PostgresBroker {
+            client: Arc::new(Mutex::new(connect(params)?)),
+            params: params.to_string(),
+            channel_prefix: "socket.io".to_string(),
+            table: "socket_io_attachments".to_string(),
+            payload_threshold: 8000,
+        })
+    }
+
+    /// The prefix of the Postgres channels, `socket.io` by default like the
+    /// adapter's `channelPrefix`.
+    pub fn channel_prefix(mut self, prefix: &str) -> PostgresBroker {
+        self.channel_prefix = prefix.to_string();
+        self
+    }
+
+    /// The table large documents are stored in, `socket_io_attachments` by
+    /// default like the adapter's `tableName`. The name is quoted, so it is
+    /// case-sensitive and can't name a schema.
+    pub fn table_name(mut self, table: &str) -> PostgresBroker {
+        self.table = table.to_string();
+        self
+    }
+
+    /// Documents longer than `bytes` go through the attachments table, like
+    /// the adapter's `payloadThreshold`.
+    pub fn payload_threshold(mut self, bytes: usize) -> PostgresBroker {
+        self.payload_threshold = bytes;
+        self
+    }
+
+    /// Runs `f` on the connection, reopening it first if it was closed and
+    /// once more if it closes during `f`.
+    fn with_client<F>(&self, mut f: F) -> RedisResult<()>
+    where
+        F: FnMut(&mut Client) -> Result<(), postgres::Error>,
+    {
+        let mut client = self.client.lock().unwrap();
+        if client.is_closed() {
+            *client = connect(&self.params)?;
+        }
+        match f(&mut client) {
+            Err(_e) if client.is_closed() => {
+                #[cfg(feature = "logging")]
+                log::warn!("Postgres connection lost, reconnecting: {}", _e);
+                *client = connect(&self.params)?;
+                f(&mut client).map_err(pg_error)
+            }
+            result => result.map_err(pg_error),
+        }
+    }
+}
+
+fn connect(params: &str) -> RedisResult<Client> {
+    Client::connect(params, NoTls).map_err(pg_error)
+}
+
+/// Quotes `name` as a SQL identifier, doubling embedded quotes.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// The broadcast document the adapter expects for a packet.
+fn document(uid: &str, packet: &Packet, opts: &Opts) -> Value {
+    json!({
+        "uid": uid,
+        "type": BROADCAST,
+        "data": {
+            "packet": packet,
+            "opts": {
+                "rooms": opts.rooms(),
+                "except": [],
+                "flags": opts.flags(),
+            },
+        },
+    })
+}
+
+/// The notification pointing the adapter at a document stored as attachment
+/// `id`. The adapter reads ids as strings, as node-postgres returns them.
+fn attachment_header(uid: &str, id: i64) -> String {
+    json!({ "uid": uid, "type": BROADCAST, "attachmentId": id.to_string() }).to_string()
+}
+
+impl Broker for PostgresBroker {
+    fn publish(&self, _channel: &str, payload: &[u8]) -> RedisResult<()> {
+        // The adapter listens on one channel per namespace and filters by
+        // room itself, so the socket.io channel isn't needed.
+        let (uid, packet, opts) = decode(payload).map_err(|e| {
+            RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+        })?;
+        let channel = format!("{}#{}", self.channel_prefix, packet.nsp());
+        let document = document(&uid, &packet, &opts);
+        let notification = document.to_string();
+        if notification.len() <= self.payload_threshold {
+            return self.with_client(|client| {
+                client
+                    .execute("SELECT pg_notify($1, $2)", &[&channel, &notification])
+                    .map(|_| ())
+            });
+        }
+        let attachment = rmp_serde::to_vec_named(&document).map_err(|e| {
+            RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+        })?;
+        let insert = format!(
+            "INSERT INTO {} (payload) VALUES ($1) RETURNING id",
+            quote_identifier(&self.table)
+        );
+        self.with_client(|client| {
+            let id: i64 = client.query_one(insert.as_str(), &[&attachment])?.get(0);
+            let header = attachment_header(&uid, id);
+            client
+                .execute("SELECT pg_notify($1, $2)", &[&channel, &header])
+                .map(|_| ())
+        })
+    }
+}
+
+impl fmt::Debug for PostgresBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostgresBroker")
+            .field("channel_prefix", &self.channel_prefix)
+            .field("table", &self.table)
+            .field("payload_threshold", &self.payload_threshold)
+            .finish()
+    }
+}
+
+fn pg_error(e: postgres::Error) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "Postgres error", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{attachment_header, document, quote_identifier};
+    use crate::{Emitter, InMemoryBroker};
+    use serde_json::Value;
+
+    #[test]
+    fn adapter_documents() {
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .of("/chat")
+            .to("room1")
+            .volatile()
+            .emit(vec!["message", "hi"]);
+        let published = &broker.published()[0];
+        let document = document(&published.uid, &published.packet, &published.opts);
+
+        // As sent by @socket.io/postgres-emitter.
+        let notification: Value = serde_json::from_str(
+            r#"{"type":3,"data":{"packet":{"type":2,"data":["message","hi"],"nsp":"/chat"},"opts":{"rooms":["room1"],"flags":{"volatile":true},"except":[]}},"uid":"emitter"}"#,
+        )
+        .unwrap();
+        assert_eq!(notification, document);
+
+        // The adapter decodes attachments with notepack.io, a msgpack codec.
+        let attachment = rmp_serde::to_vec_named(&document).unwrap();
+        let decoded: Value = rmp_serde::from_slice(&attachment).unwrap();
+        assert_eq!(notification, decoded);
+
+        let header: Value = serde_json::from_str(&attachment_header("emitter", 42)).unwrap();
+        assert_eq!(
+            serde_json::json!({ "uid": "emitter", "type": 3, "attachmentId": "42" }),
+            header
+        );
+    }
+
+    #[test]
+    fn quotes_table_names() {
+        assert_eq!(
+            r#""socket_io_attachments""#,
+            quote_identifier("socket_io_attachments")
+        );
+        assert_eq!(
+            r#""a"" (payload) VALUES (1); --""#,
+            quote_identifier(r#"a" (payload) VALUES (1); --"#)
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use crate::raw;
+use redis::{ConnectionInfo, ErrorKind, RedisError, RedisResult};
+use std::io::{Read, Write};
+use std::net::IpAddr;
+use std::time::Duration;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USER_PASSWORD: u8 = 2;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Socks5Proxy {
+    /// `host:port` of the SOCKS5 proxy.
+    pub addr: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Socks5Proxy {
+    pub fn new(addr: &str) -> Socks5Proxy {
+        Socks5Proxy {
+            addr: addr.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Opens a tunnel to the Redis address of `info`, starting TLS in it for
+    /// `rediss://` addresses. `timeout` bounds connecting to the proxy and
+    /// every read and write after, including the handshakes.
+    pub(crate) fn connect(
+        &self,
+        info: &ConnectionInfo,
+        timeout: Option<Duration>,
+    ) -> RedisResult<Box<dyn raw::Stream>> {
+        let (host, port) = raw::host_port(&info.addr)?;
+        let credentials = match (&self.username, &self.password) {
+            (Some(username), Some(password)) => Some((username, password)),
+            (None, None) => None,
+            _ => {
+                return Err(RedisError::from((
+                    ErrorKind::InvalidClientConfig,
+                    "SOCKS5 proxy needs both a username and a password",
+                )))
+            }
+        };
+        let (proxy_host, proxy_port) = self
+            .addr
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+            .ok_or_else(|| {
+                RedisError::from((
+                    ErrorKind::InvalidClientConfig,
+                    "invalid SOCKS5 proxy address",
+                    self.addr.clone(),
+                ))
+            })?;
+        let mut stream = raw::tcp(proxy_host.trim_matches(['[', ']']), proxy_port, timeout)?;
+        handshake(&mut stream, credentials, host, port)?;
+        raw::secure(stream, &info.addr)
+    }
+}
+
+fn handshake<S: Read + Write>(
+    stream: &mut S,
+    credentials: Option<(&String, &String)>,
+    host: &str,
+    port: u16,
+) -> RedisResult<()> {
+    let method = if credentials.is_some() {
+        USER_PASSWORD
+    } else {
+        NO_AUTH
+    };
+    stream.write_all(&[VERSION, 1, method])?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [VERSION, method] {
+        return Err(proxy_error("proxy refused the authentication method"));
+    }
+    if let Some((username, password)) = credentials {
+        if username.len() > 255 || password.len() > 255 {
+            return Err(proxy_error("credentials longer than 255 bytes"));
+        }
+        let mut auth = vec![1, username.len() as u8];
+        auth.extend(username.as_bytes());
+        auth.push(password.len() as u8);
+        auth.extend(password.as_bytes());
+        stream.write_all(&auth)?;
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0 {
+            return Err(proxy_error("proxy rejected the credentials"));
+        }
+    }
+
+    let mut request = vec![VERSION, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend(ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend(ip.octets());
+        }
+        Err(_) if host.len() > 255 => return Err(proxy_error("host name too long")),
+        Err(_) => {
+            request.extend([3, host.len() as u8]);
+            request.extend(host.as_bytes());
+        }
+    }
+    request.extend(port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(proxy_error("proxy could not connect to Redis"));
+    }
+    // The address the proxy bound, which isn't needed.
+    let len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => return Err(proxy_error("invalid proxy reply")),
+    };
+    stream.read_exact(&mut vec![0; len + 2])?;
+    Ok(())
+}
+
+fn proxy_error(detail: &str) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "SOCKS5 error", detail.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Socks5Proxy;
+    use crate::{Broker, RedisBroker};
+    use redis::ErrorKind;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn read(con: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        con.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn tunnels_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = Socks5Proxy {
+            addr: listener.local_addr().unwrap().to_string(),
+            username: Some("user".to_string()),
+            password: Some("pass".to_string()),
+        };
+        let server = thread::spawn(move || {
+            let (mut con, _) = listener.accept().unwrap();
+            let greeting = read(&mut con, 3);
+            con.write_all(&[5, 2]).unwrap();
+            let auth = read(&mut con, 11);
+            con.write_all(&[1, 0]).unwrap();
+            let request = read(&mut con, 21);
+            con.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).unwrap();
+            let numsub = redis::cmd("PUBSUB")
+                .arg("NUMSUB")
+                .arg("c")
+                .get_packed_command();
+            assert_eq!(numsub, read(&mut con, numsub.len()));
+            con.write_all(b"*2\r\n$1\r\nc\r\n:3\r\n").unwrap();
+            (greeting, auth, request)
+        });
+
+        let client = redis::Client::open("redis://redis.internal:6379").unwrap();
+        let broker = RedisBroker::new(client).proxy(proxy.clone());
+        assert_eq!(Some(3), broker.subscribers("c").unwrap());
+        let (greeting, auth, request) = server.join().unwrap();
+        assert_eq!(b"\x05\x01\x02", &greeting[..]);
+        assert_eq!(b"\x01\x04user\x04pass", &auth[..]);
+        assert_eq!(b"\x05\x01\x00\x03\x0eredis.internal\x18\xeb", &request[..]);
+
+        let client = redis::Client::open("redis://redis.internal:6379").unwrap();
+        let password_only = Socks5Proxy {
+            username: None,
+            ..proxy
+        };
+        let broker = RedisBroker::new(client.clone()).proxy(password_only);
+        let err = broker.publish("c", b"x").unwrap_err();
+        assert_eq!(ErrorKind::InvalidClientConfig, err.kind());
+
+        // A proxy that accepts but never answers times out the handshake.
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let broker = RedisBroker::new(client)
+            .proxy(Socks5Proxy::new(&silent.local_addr().unwrap().to_string()))
+            .command_timeout(Duration::from_millis(100));
+        let started = Instant::now();
+        assert!(broker.publish("c", b"x").unwrap_err().is_timeout());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
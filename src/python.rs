@@ -0,0 +1,183 @@
+//! A Python module wrapping `Emitter`, built with maturin and the
+//! `python,pyo3/extension-module` features:
+//!
+//! ```python
+//! from socketio_rust_emitter import Emitter
+//!
+//! io = Emitter("redis://127.0.0.1/")
+//! io.of("/chat").to("room").emit("message", '{"text": "hi"}')
+//! await io.to(["a", "b"]).emit_async("message", "hi")
+//! ```
+
+use crate::Emitter;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyCFunction;
+
+create_exception!(socketio_rust_emitter, EmitError, PyException);
+
+/// Each call returns a new emitter, so selections can be built up and
+/// reused like the Rust builder methods. `emit` keeps the selection.
+#[pyclass(name = "Emitter", module = "socketio_rust_emitter")]
+#[derive(Clone)]
+struct PyEmitter {
+    emitter: Emitter,
+    rooms: Vec<String>,
+}
+
+#[derive(FromPyObject)]
+enum Rooms {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[pymethods]
+impl PyEmitter {
+    #[new]
+    #[pyo3(signature = (url, key = "socket.io", nsp = "/", uid = None))]
+    fn new(url: &str, key: &str, nsp: &str, uid: Option<&str>) -> PyResult<PyEmitter> {
+        let mut builder = Emitter::builder().endpoint(url).prefix(key).nsp(nsp);
+        if let Some(uid) = uid {
+            builder = builder.uid(uid);
+        }
+        let emitter = builder
+            .build()
+            .map_err(|e| EmitError::new_err(e.to_string()))?;
+        Ok(PyEmitter {
+            emitter,
+            rooms: Vec::new(),
+        })
+    }
+
+    fn to(&self, rooms: Rooms) -> PyEmitter {
+        let mut next = self.clone();
+        match rooms {
+            Rooms::One(room) => next.rooms.push(room),
+            Rooms::Many(rooms) => next.rooms.extend(rooms),
+        }
+        next
+    }
+
+    fn of(&self, nsp: &str) -> PyEmitter {
+        PyEmitter {
+            emitter: self.emitter.clone().of(nsp),
+            rooms: Vec::new(),
+        }
+    }
+
+    fn json(&self) -> PyEmitter {
+        self.with(Emitter::json)
+    }
+
+    fn volatile(&self) -> PyEmitter {
+        self.with(Emitter::volatile)
+    }
+
+    fn broadcast(&self) -> PyEmitter {
+        self.with(Emitter::broadcast)
+    }
+
+    /// Publishes `event` with string `args`, raising `EmitError` on failure.
+    /// The GIL is released while publishing.
+    #[pyo3(signature = (event, *args))]
+    fn emit(&self, py: Python<'_>, event: &str, args: Vec<String>) -> PyResult<()> {
+        py.allow_threads(|| self.publish(event, &args))
+    }
+
+    /// Like `emit`, but returns an awaitable publishing on the running event
+    /// loop's default executor.
+    #[pyo3(signature = (event, *args))]
+    fn emit_async(
+        slf: Bound<'_, Self>,
+        event: String,
+        args: Vec<String>,
+    ) -> PyResult<Bound<'_, PyAny>> {
+        let py = slf.py();
+        let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+        let emitter = slf.borrow().clone();
+        let task = PyCFunction::new_closure(py, None, None, move |call, _| {
+            call.py().allow_threads(|| emitter.publish(&event, &args))
+        })?;
+        event_loop.call_method1("run_in_executor", (py.None(), task))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Emitter(nsp={:?}, rooms={:?})",
+            self.emitter.nsp, self.rooms
+        )
+    }
+}
+
+impl PyEmitter {
+    fn with(&self, f: fn(Emitter) -> Emitter) -> PyEmitter {
+        PyEmitter {
+            emitter: f(self.emitter.clone()),
+            rooms: self.rooms.clone(),
+        }
+    }
+
+    fn publish(&self, event: &str, args: &[String]) -> PyResult<()> {
+        let args = args.iter().map(String::as_str).collect();
+        let rooms: Vec<&str> = self.rooms.iter().map(String::as_str).collect();
+        self.emitter
+            .emit_batch(vec![(event, args, rooms)])
+            .into_iter()
+            .collect::<Result<(), _>>()
+            .map_err(|e| EmitError::new_err(e.to_string()))
+    }
+}
+
+#[pymodule]
+fn socketio_rust_emitter(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyEmitter>()?;
+    m.add("EmitError", m.py().get_type::<EmitError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PyEmitter;
+    use crate::{Emitter, InMemoryBroker};
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+    use std::ffi::CString;
+
+    #[test]
+    fn python_module() {
+        pyo3::prepare_freethreaded_python();
+        let broker = InMemoryBroker::new();
+        Python::with_gil(|py| {
+            let io = PyEmitter {
+                emitter: Emitter::with_broker(broker.clone()),
+                rooms: Vec::new(),
+            };
+            let globals = PyDict::new(py);
+            globals.set_item("io", Py::new(py, io).unwrap()).unwrap();
+            let code = CString::new(
+                r#"
+import asyncio
+io.of("/chat").to("room").emit("message", '{"text": "hi"}')
+chat = io.of("/chat").to(["a", "b"]).json()
+
+async def main():
+    await chat.emit_async("typing")
+
+asyncio.run(main())
+"#,
+            )
+            .unwrap();
+            py.run(&code, Some(&globals), None).unwrap();
+        });
+
+        let published = broker.published();
+        assert_eq!("socket.io#/chat#room#", published[0].channel);
+        assert_eq!(
+            &["message", r#"{"text": "hi"}"#],
+            published[0].packet.data()
+        );
+        assert_eq!(&["a", "b"], published[1].opts.rooms());
+        assert_eq!(Some(&true), published[1].opts.flags().get("json"));
+    }
+}
@@ -0,0 +1,220 @@
+// Without threads on wasm32 the publishing thread and what only they use are
+// compiled but never started.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use crate::{EmitHandle, Emitter, Encoded};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::Duration;
+
+/// How long the publishing thread sleeps at most, so it notices when every
+/// queue is gone.
+const IDLE: Duration = Duration::from_secs(1);
+
+/// The priority of an emit published through a `PublishQueue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// Bulk events such as activity feeds.
+    Low,
+    #[default]
+    Normal,
+    /// Critical events such as payment confirmations.
+    High,
+}
+
+const PRIORITIES: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+struct Queued {
+    emitter: Emitter,
+    packets: Vec<Encoded>,
+    done: Option<EmitHandle>,
+}
+
+#[derive(Default)]
+struct Shared {
+    /// One lane per priority, lowest first.
+    lanes: Mutex<[VecDeque<Queued>; 3]>,
+    wake: Condvar,
+}
+
+impl Shared {
+    /// The oldest emit of the highest priority.
+    fn pop(lanes: &mut [VecDeque<Queued>; 3]) -> Option<Queued> {
+        PRIORITIES
+            .iter()
+            .find_map(|&priority| lanes[priority as usize].pop_front())
+    }
+}
+
+/// Publishes emits from a background thread instead of the caller's, in
+/// order within a priority. When the queue backs up, queued emits of a
+/// higher priority are published before any of a lower one. Clones share
+/// the queue.
+#[derive(Clone)]
+pub struct PublishQueue {
+    shared: Arc<Shared>,
+}
+
+impl PublishQueue {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new() -> PublishQueue {
+        let shared = Arc::new(Shared::default());
+        let worker = Arc::downgrade(&shared);
+        thread::spawn(move || publish_queued(worker));
+        PublishQueue { shared }
+    }
+
+    pub(crate) fn push(
+        &self,
+        priority: Priority,
+        emitter: Emitter,
+        packets: Vec<Encoded>,
+        done: Option<EmitHandle>,
+    ) {
+        let queued = Queued {
+            emitter,
+            packets,
+            done,
+        };
+        let mut lanes = self.shared.lanes.lock().unwrap();
+        lanes[priority as usize].push_back(queued);
+        self.shared.wake.notify_one();
+    }
+
+    /// How many emits are waiting to be published.
+    pub fn len(&self) -> usize {
+        self.shared
+            .lanes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(VecDeque::len)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Publishes every queued emit now on the calling thread, highest
+    /// priority first, e.g. before shutting down.
+    pub fn flush(&self) {
+        loop {
+            let queued = Shared::pop(&mut self.shared.lanes.lock().unwrap());
+            match queued {
+                Some(queued) => publish(queued),
+                None => return,
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for PublishQueue {
+    fn default() -> PublishQueue {
+        PublishQueue::new()
+    }
+}
+
+/// Publishes queued emits as they come, until every queue sharing them is
+/// dropped.
+fn publish_queued(shared: Weak<Shared>) {
+    while let Some(shared) = shared.upgrade() {
+        let mut lanes = shared.lanes.lock().unwrap();
+        match Shared::pop(&mut lanes) {
+            Some(queued) => {
+                drop(lanes);
+                publish(queued);
+            }
+            None => drop(shared.wake.wait_timeout(lanes, IDLE).unwrap()),
+        }
+    }
+}
+
+fn publish(queued: Queued) {
+    let Queued {
+        emitter,
+        packets,
+        done,
+    } = queued;
+    let mut result = Ok(());
+    for published in emitter.publish_packets(&packets) {
+        #[cfg(feature = "logging")]
+        if let Err(e) = &published {
+            log::warn!("dropped queued emit: {}", e);
+        }
+        if result.is_ok() {
+            result = published;
+        }
+    }
+    emitter.recycle(packets);
+    if let Some(done) = done {
+        done.complete(result);
+    }
+}
+
+impl std::fmt::Debug for PublishQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PublishQueue")
+            .field("queued", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Broker, InMemoryBroker};
+    use redis::RedisResult;
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::Instant;
+
+    /// Holds every publish until the test lets it through.
+    struct Gate {
+        broker: InMemoryBroker,
+        open: Mutex<Receiver<()>>,
+    }
+
+    impl Broker for Gate {
+        fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+            self.open.lock().unwrap().recv().unwrap();
+            self.broker.publish(channel, payload)
+        }
+    }
+
+    #[test]
+    fn publishes_higher_priority_first() {
+        let broker = InMemoryBroker::new();
+        let (open, gate) = mpsc::channel();
+        let queue = PublishQueue::new();
+        let io = Emitter::with_broker(Gate {
+            broker: broker.clone(),
+            open: Mutex::new(gate),
+        })
+        .publish_queue(queue.clone());
+
+        io.clone().emit(vec!["feed-1"]);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while !queue.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        io.clone().priority(Priority::Low).emit(vec!["feed-2"]);
+        io.clone().emit(vec!["message"]);
+        io.clone().priority(Priority::High).emit(vec!["payment"]);
+        assert_eq!(3, queue.len());
+
+        for _ in 0..4 {
+            open.send(()).unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while broker.published().len() < 4 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(
+            vec!["feed-1", "payment", "message", "feed-2"],
+            broker.events()
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Rooms with a full bucket are forgotten once this many are tracked.
+const MAX_ROOM_BUCKETS: usize = 10_000;
+
+/// What `emit` does when the rate limit is exceeded.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LimitAction {
+    /// Blocks until the packet fits in the limit.
+    Delay,
+    /// Silently skips the packet.
+    Drop,
+    /// Fails the publish: `Emitter::try_emit` returns the error and `emit`
+    /// panics.
+    Error,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(rate: f64, burst: u32, now: Instant) -> Bucket {
+        Bucket {
+            // Also turns NaN into a bucket that never refills.
+            rate: if rate > 0.0 { rate } else { 0.0 },
+            burst: f64::from(burst),
+            tokens: f64::from(burst),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.updated = now;
+    }
+
+    /// How long until a token is available, `None` if never.
+    fn wait(&self) -> Option<Duration> {
+        if self.tokens >= 1.0 {
+            Some(Duration::ZERO)
+        } else {
+            Duration::try_from_secs_f64((1.0 - self.tokens) / self.rate).ok()
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    global: Option<Bucket>,
+    per_room: Option<(f64, u32)>,
+    rooms: HashMap<String, Bucket>,
+}
+
+/// Token-bucket limits on the packets an emitter publishes, overall and for
+/// each room. Clones share the buckets.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    action: LimitAction,
+    state: Arc<Mutex<State>>,
+}
+
+impl RateLimiter {
+    pub fn new(action: LimitAction) -> RateLimiter {
+        RateLimiter {
+            action,
+            state: Arc::new(Mutex::new(State {
+                global: None,
+                per_room: None,
+                rooms: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Allows `rate` packets per second overall, with bursts of up to `burst`.
+    /// A `rate` of zero or less allows only the burst; once it is spent,
+    /// delayed packets fail as with `LimitAction::Error`.
+    pub fn global(self, rate: f64, burst: u32) -> RateLimiter {
+        self.state.lock().unwrap().global = Some(Bucket::new(rate, burst, Instant::now()));
+        self
+    }
+
+    /// Allows `rate` packets per second to each room, with bursts of up to
+    /// `burst`. Packets to several rooms take a token from each. Rates of
+    /// zero or less are handled as in `global`.
+    pub fn per_room(self, rate: f64, burst: u32) -> RateLimiter {
+        self.state.lock().unwrap().per_room = Some((rate, burst));
+        self
+    }
+
+    /// Returns `Ok(false)` if the packet is to be dropped.
+    pub(crate) fn acquire(&self, rooms: &BTreeSet<String>) -> RedisResult<bool> {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let State {
+                global,
+                per_room,
+                rooms: buckets,
+            } = &mut *state;
+            if let Some((rate, burst)) = *per_room {
+                if buckets.len() >= MAX_ROOM_BUCKETS {
+                    buckets.retain(|_, bucket| {
+                        bucket.refill(now);
+                        bucket.tokens < bucket.burst
+                    });
+                }
+                for room in rooms {
+                    buckets
+                        .entry(room.clone())
+                        .or_insert_with(|| Bucket::new(rate, burst, now));
+                }
+            }
+            let mut targeted: Vec<&mut Bucket> = global.iter_mut().collect();
+            if per_room.is_some() {
+                targeted.extend(
+                    buckets
+                        .iter_mut()
+                        .filter(|(room, _)| rooms.contains(*room))
+                        .map(|(_, bucket)| bucket),
+                );
+            }
+            for bucket in targeted.iter_mut() {
+                bucket.refill(now);
+            }
+            let wait = targeted
+                .iter()
+                .try_fold(Duration::ZERO, |max, bucket| Some(max.max(bucket.wait()?)));
+            match (wait, self.action) {
+                (Some(Duration::ZERO), _) | (Some(_), LimitAction::Delay) => {}
+                (_, LimitAction::Drop) => return Ok(false),
+                (_, LimitAction::Error) | (None, LimitAction::Delay) => {
+                    return Err(RedisError::from((
+                        ErrorKind::ClientError,
+                        "emit rate limit exceeded",
+                    )))
+                }
+            }
+            let wait = wait.unwrap_or_default();
+            // Delayed packets reserve their token now, so concurrent
+            // emitters queue up behind them.
+            for bucket in targeted {
+                bucket.tokens -= 1.0;
+            }
+            wait
+        };
+        if wait > Duration::ZERO {
+            thread::sleep(wait);
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LimitAction, RateLimiter};
+    use crate::{Emitter, InMemoryBroker};
+    use std::collections::BTreeSet;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn limits() {
+        let rooms = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<BTreeSet<_>>();
+
+        let limiter = RateLimiter::new(LimitAction::Drop).per_room(0.001, 2);
+        assert!(limiter.acquire(&rooms(&["a"])).unwrap());
+        assert!(limiter.acquire(&rooms(&["a", "b"])).unwrap());
+        assert!(!limiter.acquire(&rooms(&["a"])).unwrap());
+        assert!(!limiter.acquire(&rooms(&["b", "a"])).unwrap());
+        assert!(limiter.acquire(&rooms(&["b"])).unwrap());
+        assert!(limiter.acquire(&rooms(&[])).unwrap());
+
+        let limiter = RateLimiter::new(LimitAction::Error).global(0.001, 1);
+        assert!(limiter.acquire(&rooms(&[])).is_ok());
+        assert!(limiter.acquire(&rooms(&["a"])).is_err());
+
+        let broker = InMemoryBroker::new();
+        let started = Instant::now();
+        let limiter = RateLimiter::new(LimitAction::Delay).global(50.0, 1);
+        Emitter::with_broker(broker.clone())
+            .rate_limit(limiter)
+            .emit(vec!["a"])
+            .emit(vec!["b"])
+            .emit(vec!["c"]);
+        assert_eq!(vec!["a", "b", "c"], broker.events());
+        assert!(started.elapsed() >= Duration::from_millis(35));
+
+        // A bucket without a rate only allows its burst, in every mode.
+        for action in [LimitAction::Delay, LimitAction::Drop, LimitAction::Error] {
+            let limiter = RateLimiter::new(action).global(0.0, 1).per_room(-1.0, 1);
+            assert!(limiter.acquire(&rooms(&["a"])).unwrap());
+            let limited = limiter.acquire(&rooms(&["a"]));
+            assert!(!limited.unwrap_or(false));
+        }
+        let io = Emitter::with_broker(broker.clone())
+            .rate_limit(RateLimiter::new(LimitAction::Error).global(0.001, 1));
+        let io = io.try_emit(vec!["d"]).unwrap();
+        assert!(io.try_emit(vec!["e"]).is_err());
+        assert_eq!(vec!["a", "b", "c", "d"], broker.events());
+
+        let limiter = RateLimiter::new(LimitAction::Delay).per_room(f64::NAN, 1);
+        assert!(limiter.acquire(&rooms(&["a"])).unwrap());
+        assert!(limiter.acquire(&rooms(&["a"])).is_err());
+    }
+}
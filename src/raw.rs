@@ -0,0 +1,407 @@
+//! A minimal RESP client for connections the redis crate can't drive itself:
+//! proxied streams and RESP3, over plain TCP or TLS.
+
+use redis::{
+    ConnectionAddr, ConnectionInfo, ErrorKind, RedisConnectionInfo, RedisError, RedisResult, Value,
+};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// A stream a `Connection` runs over.
+pub(crate) trait Stream: Read + Write + Send {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// Opens a TCP connection to `host:port`, giving up on each resolved address
+/// after `timeout`, which also becomes the read/write timeout.
+pub(crate) fn tcp(host: &str, port: u16, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in (host, port).to_socket_addrs()? {
+        let connected = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+            None => TcpStream::connect(addr),
+        };
+        match connected {
+            Ok(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)?;
+                return Ok(stream);
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::other("host resolved to no addresses")))
+}
+
+#[cfg(feature = "tls")]
+impl Stream for native_tls::TlsStream<TcpStream> {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.get_ref().set_read_timeout(timeout)
+    }
+}
+
+/// The host and port of a TCP address, with or without TLS.
+pub(crate) fn host_port(addr: &ConnectionAddr) -> RedisResult<(&str, u16)> {
+    match addr {
+        ConnectionAddr::Tcp(host, port) | ConnectionAddr::TcpTls { host, port, .. } => {
+            Ok((host.as_str(), *port))
+        }
+        ConnectionAddr::Unix(_) => Err(RedisError::from((
+            ErrorKind::InvalidClientConfig,
+            "requires a TCP redis address",
+        ))),
+    }
+}
+
+pub(crate) fn connect(
+    info: &ConnectionInfo,
+    timeout: Option<Duration>,
+) -> RedisResult<Box<dyn Stream>> {
+    let (host, port) = host_port(&info.addr)?;
+    secure(tcp(host, port, timeout)?, &info.addr)
+}
+
+/// Starts TLS on `stream` if `addr` is a `rediss://` address. `#insecure`
+/// addresses skip verifying the server's certificate.
+pub(crate) fn secure(stream: TcpStream, addr: &ConnectionAddr) -> RedisResult<Box<dyn Stream>> {
+    match addr {
+        #[cfg(feature = "tls")]
+        ConnectionAddr::TcpTls { host, insecure, .. } => {
+            let tls_error = |e: &dyn std::fmt::Display| {
+                RedisError::from((ErrorKind::IoError, "TLS handshake failed", e.to_string()))
+            };
+            let connector = native_tls::TlsConnector::builder()
+                .danger_accept_invalid_certs(*insecure)
+                .danger_accept_invalid_hostnames(*insecure)
+                .build()
+                .map_err(|e| tls_error(&e))?;
+            let stream = connector.connect(host, stream).map_err(|e| tls_error(&e))?;
+            Ok(Box::new(stream))
+        }
+        _ => Ok(Box::new(stream)),
+    }
+}
+
+/// A connection speaking RESP2 or RESP3 over any stream.
+pub(crate) struct Connection {
+    reader: BufReader<Box<dyn Stream>>,
+    parser: redis::Parser,
+    resp3: bool,
+}
+
+impl Connection {
+    /// Authenticates on `stream` and selects the database of `info`. With
+    /// `resp3` the connection is upgraded with `HELLO 3` first.
+    pub(crate) fn open(
+        stream: Box<dyn Stream>,
+        info: &RedisConnectionInfo,
+        resp3: bool,
+    ) -> RedisResult<Connection> {
+        let mut con = Connection {
+            reader: BufReader::new(stream),
+            parser: redis::Parser::new(),
+            resp3,
+        };
+        if resp3 {
+            let mut hello = redis::cmd("HELLO");
+            hello.arg(3);
+            if let Some(password) = &info.password {
+                hello
+                    .arg("AUTH")
+                    .arg(info.username.as_deref().unwrap_or("default"))
+                    .arg(password);
+            }
+            con.query(&hello)?;
+        } else if let Some(password) = &info.password {
+            let mut auth = redis::cmd("AUTH");
+            if let Some(username) = &info.username {
+                auth.arg(username);
+            }
+            con.query(auth.arg(password))?;
+        }
+        if info.db != 0 {
+            con.query(redis::cmd("SELECT").arg(info.db))?;
+        }
+        Ok(con)
+    }
+
+    /// Runs `cmd`, returning its reply.
+    pub(crate) fn query(&mut self, cmd: &redis::Cmd) -> RedisResult<Value> {
+        self.send(cmd)?;
+        loop {
+            if let Frame::Reply(value) = self.read()? {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Runs `cmds` in one write and reads all their replies, failing with the
+    /// first error once every reply was read.
+    pub(crate) fn pipeline(&mut self, cmds: &[redis::Cmd]) -> RedisResult<()> {
+        let mut packed = Vec::new();
+        for cmd in cmds {
+            packed.extend_from_slice(&cmd.get_packed_command());
+        }
+        self.reader.get_mut().write_all(&packed)?;
+        let mut result = Ok(());
+        for _ in cmds {
+            let reply = loop {
+                match self.read() {
+                    Ok(Frame::Reply(_)) => break Ok(()),
+                    Ok(_) => {}
+                    Err(e) => break Err(e),
+                }
+            };
+            if let Err(e) = reply {
+                if !matches!(e.kind(), ErrorKind::ResponseError) {
+                    return Err(e);
+                }
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Sends `cmd` without waiting for its reply.
+    pub(crate) fn send(&mut self, cmd: &redis::Cmd) -> RedisResult<()> {
+        self.reader.get_mut().write_all(&cmd.get_packed_command())?;
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for the next reply or push message, e.g. a
+    /// message on a subscribed channel. `Ok(None)` means the timeout elapsed.
+    pub(crate) fn recv(&mut self, timeout: Duration) -> RedisResult<Option<Value>> {
+        self.reader.get_ref().set_read_timeout(Some(timeout))?;
+        loop {
+            match self.read() {
+                Ok(Frame::Reply(value)) | Ok(Frame::Push(value)) => return Ok(Some(value)),
+                Ok(_) => {}
+                Err(e) if e.is_timeout() => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read(&mut self) -> RedisResult<Frame> {
+        if self.resp3 {
+            read_frame(&mut self.reader)?.into_result()
+        } else {
+            self.parser.parse_value(&mut self.reader).map(Frame::Reply)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Frame {
+    Reply(Value),
+    Error(String),
+    /// An out-of-band push message, e.g. a message on a subscribed channel.
+    Push(Value),
+    /// Attributes a RESP3 server may put before a reply.
+    Attribute,
+}
+
+impl Frame {
+    fn into_result(self) -> RedisResult<Frame> {
+        match self {
+            Frame::Error(message) => Err(RedisError::from((
+                ErrorKind::ResponseError,
+                "An error was signalled by the server",
+                message,
+            ))),
+            frame => Ok(frame),
+        }
+    }
+}
+
+/// Reads the next reply, skipping out-of-band push messages and attributes
+/// that RESP3 servers may interleave with it.
+#[cfg(test)]
+fn read_reply<R: BufRead>(reader: &mut R) -> RedisResult<Value> {
+    loop {
+        if let Frame::Reply(value) = read_frame(reader)?.into_result()? {
+            return Ok(value);
+        }
+    }
+}
+
+/// Reads a RESP3 frame, converting it to the RESP2 values the redis crate
+/// works with: maps and sets become flat arrays, and doubles, big numbers
+/// and verbatim strings become bulk strings.
+fn read_frame<R: BufRead>(reader: &mut R) -> RedisResult<Frame> {
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line)?;
+    if line.len() < 3 || !line.ends_with(b"\r\n") {
+        return Err(parse_error("truncated frame"));
+    }
+    let body = String::from_utf8_lossy(&line[1..line.len() - 2]).into_owned();
+    let len = || {
+        body.parse::<i64>()
+            .map_err(|_| parse_error("invalid length"))
+    };
+
+    match line[0] {
+        b'+' if body == "OK" => Ok(Frame::Reply(Value::Okay)),
+        b'+' => Ok(Frame::Reply(Value::Status(body))),
+        b':' => Ok(Frame::Reply(Value::Int(len()?))),
+        b'#' => Ok(Frame::Reply(Value::Int((body == "t") as i64))),
+        b',' | b'(' => Ok(Frame::Reply(Value::Data(body.into_bytes()))),
+        b'_' => Ok(Frame::Reply(Value::Nil)),
+        b'-' => Ok(Frame::Error(body)),
+        kind @ (b'$' | b'=' | b'!') => {
+            let len = len()?;
+            if len < 0 {
+                return Ok(Frame::Reply(Value::Nil));
+            }
+            let mut data = vec![0; len as usize + 2];
+            reader.read_exact(&mut data)?;
+            data.truncate(len as usize);
+            match kind {
+                b'!' => Ok(Frame::Error(String::from_utf8_lossy(&data).into_owned())),
+                // Verbatim strings start with their format, e.g. `txt:`.
+                b'=' => Ok(Frame::Reply(Value::Data(data.split_off(4.min(data.len()))))),
+                _ => Ok(Frame::Reply(Value::Data(data))),
+            }
+        }
+        kind @ (b'*' | b'~' | b'>' | b'%' | b'|') => {
+            let len = len()?;
+            if len < 0 {
+                return Ok(Frame::Reply(Value::Nil));
+            }
+            let items = if kind == b'%' || kind == b'|' {
+                len * 2
+            } else {
+                len
+            };
+            let mut values = Vec::new();
+            for _ in 0..items {
+                match read_frame(reader)? {
+                    Frame::Reply(value) | Frame::Push(value) => values.push(value),
+                    Frame::Error(message) => values.push(Value::Status(message)),
+                    Frame::Attribute => {}
+                }
+            }
+            Ok(match kind {
+                b'>' => Frame::Push(Value::Bulk(values)),
+                b'|' => Frame::Attribute,
+                _ => Frame::Reply(Value::Bulk(values)),
+            })
+        }
+        _ => Err(parse_error("unknown frame type")),
+    }
+}
+
+fn parse_error(detail: &str) -> RedisError {
+    RedisError::from((ErrorKind::TypeError, "parse error", detail.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_reply, Connection, Stream};
+    use redis::{RedisConnectionInfo, Value};
+    use std::io::{self, Cursor, Read, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Replays scripted server output and records what the client wrote.
+    struct Scripted(Cursor<Vec<u8>>, Arc<Mutex<Vec<u8>>>);
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.read(buf)? {
+                0 => Err(io::ErrorKind::WouldBlock.into()),
+                n => Ok(n),
+            }
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Stream for Scripted {
+        fn set_read_timeout(&self, _: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn selects_database_and_receives_pushes() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let replies = "%1\r\n+proto\r\n:3\r\n+OK\r\n%1\r\n$7\r\nchannel\r\n:2\r\n\
+                       >3\r\n$7\r\nmessage\r\n$1\r\nc\r\n$1\r\nx\r\n";
+        let stream = Scripted(Cursor::new(replies.into()), written.clone());
+        let info = RedisConnectionInfo {
+            db: 2,
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let mut con = Connection::open(Box::new(stream), &info, true).unwrap();
+        let reply = con.query(redis::cmd("PUBSUB").arg("NUMSUB").arg("channel"));
+        assert_eq!(
+            Value::Bulk(vec![Value::Data(b"channel".to_vec()), Value::Int(2)]),
+            reply.unwrap()
+        );
+        let message = con.recv(Duration::from_secs(1)).unwrap().unwrap();
+        assert_eq!(
+            "x",
+            redis::Msg::from_value(&message)
+                .unwrap()
+                .get_payload::<String>()
+                .unwrap()
+        );
+        assert_eq!(None, con.recv(Duration::from_secs(1)).unwrap());
+
+        let written = String::from_utf8(written.lock().unwrap().clone()).unwrap();
+        assert!(written.starts_with("*5\r\n$5\r\nHELLO\r\n$1\r\n3\r\n$4\r\nAUTH\r\n"));
+        assert!(written.contains("*2\r\n$6\r\nSELECT\r\n$1\r\n2\r\n"));
+    }
+
+    #[test]
+    fn pipelines() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let replies = ":1\r\n>3\r\n$7\r\nmessage\r\n$1\r\nc\r\n$1\r\nx\r\n-ERR oops\r\n:0\r\n";
+        let stream = Scripted(Cursor::new(replies.into()), written.clone());
+        let mut con = Connection {
+            reader: std::io::BufReader::new(Box::new(stream)),
+            parser: redis::Parser::new(),
+            resp3: true,
+        };
+        let publish = |channel: &str| redis::cmd("PUBLISH").arg(channel).arg("x").clone();
+        let result = con.pipeline(&[publish("a"), publish("b"), publish("c")]);
+        assert!(result.unwrap_err().to_string().contains("oops"));
+        // Every reply was read, so the connection is still in sync.
+        assert_eq!(None, con.recv(Duration::from_millis(10)).unwrap());
+        let written = String::from_utf8(written.lock().unwrap().clone()).unwrap();
+        assert_eq!(3, written.matches("PUBLISH").count());
+    }
+
+    #[test]
+    fn skips_push_frames() {
+        let mut input = &b">3\r\n$7\r\nmessage\r\n$1\r\nc\r\n$1\r\nx\r\n:1\r\n"[..];
+        assert!(read_reply(&mut input).is_ok());
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn returns_errors() {
+        let mut input = &b"-ERR unknown command\r\n"[..];
+        assert!(read_reply(&mut input).is_err());
+        let mut input = &b"!9\r\nERR oops!\r\n"[..];
+        assert!(read_reply(&mut input).is_err());
+    }
+}
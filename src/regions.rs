@@ -0,0 +1,156 @@
+use crate::{codec, Broker};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+
+type Router = Arc<dyn Fn(&str, &str) -> Option<String> + Send + Sync>;
+
+/// Publishes each packet to the Redis clusters of the regions its rooms
+/// belong to, e.g. rooms prefixed `eu:` to the EU cluster, so one emitter
+/// serves a multi-region deployment. The router maps a namespace and room
+/// to a region; rooms it maps to none go to the default region. Packets to
+/// a whole namespace go to every region.
+#[derive(Clone)]
+pub struct Regions {
+    regions: BTreeMap<String, Arc<dyn Broker>>,
+    default: Option<String>,
+    route: Router,
+}
+
+impl Regions {
+    pub fn new<F>(route: F) -> Regions
+    where
+        F: Fn(&str, &str) -> Option<String> + Send + Sync + 'static,
+    {
+        Regions {
+            regions: BTreeMap::new(),
+            default: None,
+            route: Arc::new(route),
+        }
+    }
+
+    /// Routes rooms prefixed `<region>:` to that region, e.g. `eu:lobby` to
+    /// `eu`.
+    pub fn by_room_prefix() -> Regions {
+        Regions::new(|_, room| room.split_once(':').map(|(region, _)| region.to_string()))
+    }
+
+    /// Publishes packets routed to region `name` with `broker`.
+    pub fn region<B: Broker + 'static>(mut self, name: &str, broker: B) -> Regions {
+        self.regions.insert(name.to_string(), Arc::new(broker));
+        self
+    }
+
+    /// The region of rooms the router maps to none or to an unknown region.
+    /// Without one, packets to such rooms fail.
+    pub fn default_region(mut self, name: &str) -> Regions {
+        self.default = Some(name.to_string());
+        self
+    }
+
+    /// The regions a packet goes to, from the namespace and rooms in it.
+    fn targets(&self, payload: &[u8]) -> RedisResult<BTreeSet<&str>> {
+        let (_, packet, opts) = codec::decode(payload).map_err(|e| {
+            RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+        })?;
+        if opts.rooms().is_empty() {
+            return Ok(self.regions.keys().map(String::as_str).collect());
+        }
+        opts.rooms()
+            .iter()
+            .map(|room| {
+                let region = (self.route)(packet.nsp(), room)
+                    .and_then(|region| self.regions.get_key_value(&region))
+                    .or_else(|| self.regions.get_key_value(self.default.as_ref()?));
+                match region {
+                    Some((name, _)) => Ok(name.as_str()),
+                    None => Err(RedisError::from((
+                        ErrorKind::ClientError,
+                        "no region for room",
+                        room.clone(),
+                    ))),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Broker for Regions {
+    /// Publishes to every region of the packet, returning the first error
+    /// once all of them were tried.
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let mut result = Ok(());
+        for region in self.targets(payload)? {
+            let published = self.regions[region].publish(channel, payload);
+            if result.is_ok() {
+                result = published;
+            }
+        }
+        result
+    }
+
+    /// Hands each region one batch of its packets, in their original order.
+    fn publish_batch(&self, messages: &[(&str, &[u8])]) -> Vec<RedisResult<()>> {
+        let mut results: Vec<RedisResult<()>> = messages.iter().map(|_| Ok(())).collect();
+        let mut batches: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (i, (_, payload)) in messages.iter().enumerate() {
+            match self.targets(payload) {
+                Ok(regions) => regions
+                    .into_iter()
+                    .for_each(|region| batches.entry(region).or_default().push(i)),
+                Err(e) => results[i] = Err(e),
+            }
+        }
+        for (region, batch) in batches {
+            let messages: Vec<_> = batch.iter().map(|&i| messages[i]).collect();
+            let published = self.regions[region].publish_batch(&messages);
+            for (i, result) in batch.into_iter().zip(published) {
+                if results[i].is_ok() {
+                    results[i] = result;
+                }
+            }
+        }
+        results
+    }
+}
+
+impl fmt::Debug for Regions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Regions")
+            .field("regions", &self.regions.keys())
+            .field("default", &self.default)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Emitter, InMemoryBroker};
+
+    #[test]
+    fn routes_rooms_to_regions() {
+        let eu = InMemoryBroker::new();
+        let us = InMemoryBroker::new();
+        let regions = Regions::by_room_prefix()
+            .region("eu", eu.clone())
+            .region("us", us.clone())
+            .default_region("us");
+        let io = Emitter::with_broker(regions);
+        io.clone().to("eu:lobby").emit(vec!["a"]);
+        io.clone().to("lobby").emit(vec!["b"]);
+        io.clone().to(["eu:x", "us:y"]).emit(vec!["c"]);
+        io.clone().emit(vec!["d"]);
+        assert_eq!(vec!["a", "c", "d"], eu.events());
+        assert_eq!(vec!["b", "c", "d"], us.events());
+
+        let results = io.emit_batch(vec![("e", vec![], "eu:a"), ("f", vec![], "ap:b")]);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(vec!["a", "c", "d", "e"], eu.events());
+        assert_eq!(vec!["b", "c", "d", "f"], us.events());
+
+        let io = Emitter::with_broker(Regions::by_room_prefix().region("eu", eu)).to("ap:lobby");
+        assert!(io.send(&io.rooms, &["g"]).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{bridge, codec, BridgeHandle, Emitter, Encoded, Opts, Packet};
+use redis::RedisResult;
+use std::fmt;
+use std::sync::Arc;
+
+/// A packet read by a `Relay`, which the rewriter may change before it is
+/// republished.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Relayed {
+    /// The channel it was read from.
+    pub channel: String,
+    pub uid: String,
+    pub packet: Packet,
+    pub opts: Opts,
+}
+
+type Rewriter = Arc<dyn Fn(&mut Relayed) -> bool + Send + Sync>;
+
+/// Subscribes to emitter traffic on one Redis and republishes it through
+/// another emitter, e.g. to fan out broadcasts across regions. Packets are
+/// decoded, passed to the rewriter, which may change them or drop them by
+/// returning `false`, and published on the target's channel for their
+/// namespace and rooms.
+///
+/// Packets are republished with the relay's uid, `relay` by default, and
+/// packets carrying it are skipped, so relays in both directions between
+/// two clusters don't loop.
+#[derive(Clone)]
+pub struct Relay {
+    client: redis::Client,
+    patterns: Vec<String>,
+    target: Emitter,
+    rewrite: Rewriter,
+}
+
+impl Relay {
+    /// Relays every packet published on `client` under the `socket.io`
+    /// prefix.
+    pub fn new(client: redis::Client, target: Emitter) -> Relay {
+        Relay {
+            client,
+            patterns: Vec::new(),
+            target: target.uid("relay"),
+            rewrite: Arc::new(|_| true),
+        }
+    }
+
+    /// Relays channels matching the glob-style `pattern` instead, e.g.
+    /// `socket.io#/chat#*`, and the other patterns given this way.
+    pub fn pattern(mut self, pattern: &str) -> Relay {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Sets the uid republished packets carry.
+    pub fn uid(mut self, uid: &str) -> Relay {
+        self.target = self.target.uid(uid);
+        self
+    }
+
+    pub fn rewrite<F>(mut self, rewrite: F) -> Relay
+    where
+        F: Fn(&mut Relayed) -> bool + Send + Sync + 'static,
+    {
+        self.rewrite = Arc::new(rewrite);
+        self
+    }
+
+    fn patterns(&self) -> Vec<String> {
+        if self.patterns.is_empty() {
+            vec![format!("{}#*", self.target.prefix)]
+        } else {
+            self.patterns.clone()
+        }
+    }
+
+    /// Republishes one message read off `channel`. Returns whether it was
+    /// relayed; payloads that aren't packets are skipped.
+    fn relay(&self, channel: &str, payload: &[u8]) -> RedisResult<bool> {
+        let (uid, packet, opts) = match codec::decode(payload) {
+            Ok(decoded) => decoded,
+            Err(_) => return Ok(false),
+        };
+        if uid == *self.target.uid {
+            return Ok(false);
+        }
+        let mut relayed = Relayed {
+            channel: channel.to_string(),
+            uid,
+            packet,
+            opts,
+        };
+        if !(self.rewrite)(&mut relayed) {
+            return Ok(false);
+        }
+        let Relayed { packet, opts, .. } = relayed;
+        let encoded = Encoded {
+            channel: self.target.channel_in(&packet.nsp, &opts.rooms),
+            payload: self.target.payload(&packet, &opts),
+            event: packet.data.into_iter().next(),
+            nsp: packet.nsp,
+            rooms: opts.rooms,
+        };
+        let packets = vec![encoded];
+        let result = self.target.publish_packets(&packets).remove(0);
+        self.target.recycle(packets);
+        result.map(|()| true)
+    }
+
+    /// Relays on a background thread until the returned handle is stopped.
+    /// Lost connections are reopened.
+    pub fn spawn(self) -> BridgeHandle {
+        let client = self.client.clone();
+        let patterns = self.patterns();
+        bridge::psubscribe(client, patterns, "relay", move |msg| {
+            if let Err(_e) = self.relay(msg.get_channel_name(), msg.get_payload_bytes()) {
+                #[cfg(feature = "logging")]
+                log::warn!(
+                    "dropped relayed packet from {}: {}",
+                    msg.get_channel_name(),
+                    _e
+                );
+            }
+            Ok(())
+        })
+    }
+}
+
+impl fmt::Debug for Relay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Relay")
+            .field("client", &self.client)
+            .field("patterns", &self.patterns())
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryBroker;
+
+    #[test]
+    fn rewrites_and_republishes() {
+        let source = InMemoryBroker::new();
+        let io = Emitter::with_broker(source.clone());
+        io.clone().to("eu:lobby").emit(vec!["joined", "ada"]);
+        io.clone().to("us:lobby").emit(vec!["joined", "bob"]);
+        io.clone().of("/admin").emit(vec!["stats"]);
+
+        let target = InMemoryBroker::new();
+        let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+        let relay = Relay::new(client, Emitter::with_broker(target.clone())).rewrite(|relayed| {
+            let rooms = relayed.opts.rooms_mut();
+            rooms.retain(|room| !room.starts_with("us:"));
+            for room in rooms.iter_mut() {
+                *room = room.replacen("eu:", "", 1);
+            }
+            relayed.packet.nsp() == "/admin" || !relayed.opts.rooms().is_empty()
+        });
+        assert_eq!(vec!["socket.io#*"], relay.patterns());
+        for published in source.published() {
+            let payload = codec::encode(&published.uid, &published.packet, &published.opts);
+            relay.relay(&published.channel, &payload).unwrap();
+        }
+        assert!(!relay.relay("socket.io#/#", b"not a packet").unwrap());
+
+        let relayed = target.published();
+        let channels: Vec<_> = relayed.iter().map(|p| p.channel.as_str()).collect();
+        assert_eq!(vec!["socket.io#/#lobby#", "socket.io#/admin#"], channels);
+        assert_eq!(vec!["joined", "stats"], target.events());
+        assert!(relayed.iter().all(|p| p.uid == "relay"));
+
+        let looped = codec::encode("relay", &relayed[0].packet, &relayed[0].opts);
+        assert!(!relay.relay(&relayed[0].channel, &looped).unwrap());
+    }
+}
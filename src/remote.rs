@@ -0,0 +1,568 @@
+use crate::{Emitter, IntoRooms};
+use redis::{ErrorKind, RedisError, RedisResult};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How long requests wait for the servers' responses by default.
+pub(crate) const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Request types of the socket.io Redis adapter.
+const ALL_ROOMS: u8 = 1;
+const REMOTE_JOIN: u8 = 2;
+const REMOTE_LEAVE: u8 = 3;
+const REMOTE_DISCONNECT: u8 = 4;
+const REMOTE_FETCH: u8 = 5;
+const SERVER_SIDE_EMIT: u8 = 6;
+
+/// A socket on one of the socket.io servers, returned by `fetch_sockets`.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct RemoteSocket {
+    pub id: String,
+    #[serde(default)]
+    pub rooms: Vec<String>,
+    /// The socket's `socket.data`.
+    #[serde(default)]
+    pub data: Value,
+    #[serde(default)]
+    pub handshake: Handshake,
+}
+
+impl RemoteSocket {
+    /// The field of `data` at the dot-separated `path`, e.g. `user.role`.
+    pub fn data_field(&self, path: &str) -> Option<&Value> {
+        path.split('.')
+            .try_fold(&self.data, |value, key| value.get(key))
+    }
+
+    /// The handshake header `name`, case-insensitively. Repeated headers
+    /// are joined with `, `.
+    pub fn header(&self, name: &str) -> Option<String> {
+        let name = name.to_ascii_lowercase();
+        match self.handshake.headers.get(&name)? {
+            Value::String(value) => Some(value.clone()),
+            Value::Array(values) => {
+                let values: Vec<&str> = values.iter().filter_map(Value::as_str).collect();
+                Some(values.join(", "))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The handshake of a `RemoteSocket`, as seen by its server.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Handshake {
+    /// Request headers, lowercased.
+    pub headers: BTreeMap<String, Value>,
+    pub query: BTreeMap<String, Value>,
+    /// The `auth` payload the client connected with.
+    pub auth: Value,
+    pub address: String,
+    pub url: String,
+    pub time: String,
+    pub secure: bool,
+}
+
+/// A request id unique to this process.
+fn request_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    format!("{:x}{:x}", nanos, NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Responses are JSON, or msgpack from servers using a binary parser.
+fn parse(payload: &[u8]) -> Option<Value> {
+    match payload.first() {
+        Some(b'{') => serde_json::from_slice(payload).ok(),
+        _ => rmp_serde::from_slice(payload).ok(),
+    }
+}
+
+fn remote_error(e: serde_json::Error) -> RedisError {
+    RedisError::from((ErrorKind::TypeError, "invalid response", e.to_string()))
+}
+
+impl<N: Clone> Emitter<N> {
+    /// How long requests wait for the socket.io servers to respond, 5
+    /// seconds by default.
+    pub fn request_timeout(mut self, timeout: Duration) -> Emitter<N> {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// A request of `kind` for the selected sockets.
+    fn request(&self, kind: u8) -> Map<String, Value> {
+        let mut request = Map::new();
+        request.insert("uid".to_string(), json!(&*self.uid));
+        request.insert("type".to_string(), json!(kind));
+        request.insert(
+            "opts".to_string(),
+            json!({ "rooms": &self.rooms, "except": [] }),
+        );
+        request
+    }
+
+    fn publish_request(&self, request: Map<String, Value>) -> RedisResult<()> {
+        let channel = self.scheme.request_channel(&self.prefix, &self.nsp);
+        let payload = serde_json::to_vec(&request).map_err(remote_error)?;
+        self.transport.broker().publish(&channel, &payload)
+    }
+
+    /// Publishes `request` and collects a response from every server
+    /// subscribed to the request channel. Brokers that can't count the
+    /// subscribers collect responses until `timeout`.
+    fn query(&self, mut request: Map<String, Value>, timeout: Duration) -> RedisResult<Vec<Value>> {
+        let id = request_id();
+        request.insert("requestId".to_string(), json!(id));
+        let broker = self.transport.broker();
+        let channel = self.scheme.request_channel(&self.prefix, &self.nsp);
+        let expected = broker.subscribers(&channel)?;
+        if expected == Some(0) {
+            return Ok(Vec::new());
+        }
+        let response = self.scheme.response_channel(&self.prefix, &self.nsp);
+        // Servers with `publishOnSpecificResponseChannel` answer on a channel
+        // of the requester's uid.
+        let specific = format!("{}{}#", response, self.uid);
+        let mut subscription = broker.subscribe(&[response, specific])?;
+        let payload = serde_json::to_vec(&request).map_err(remote_error)?;
+        broker.publish(&channel, &payload)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut responses = Vec::new();
+        while expected.is_none_or(|n| responses.len() < n) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            let msg = match subscription.next_message(left)? {
+                Some(msg) => msg,
+                None => break,
+            };
+            if let Some(response) = parse(&msg.payload) {
+                if response["requestId"] == id {
+                    responses.push(response);
+                }
+            }
+        }
+        match expected {
+            Some(n) if responses.len() < n => Err(RedisError::from((
+                ErrorKind::IoError,
+                "timeout reached while waiting for responses",
+                format!("{} of {} servers responded", responses.len(), n),
+            ))),
+            _ => Ok(responses),
+        }
+    }
+
+    /// Whether any socket.io server listens for requests in the namespace,
+    /// counted with `PUBSUB NUMSUB` on the request channel. Brokers that
+    /// can't count subscribers wait up to the request timeout for a reply to
+    /// an `allRooms` request. Failing to reach the broker counts as
+    /// unreachable.
+    pub fn is_cluster_reachable(&self) -> bool {
+        let channel = self.scheme.request_channel(&self.prefix, &self.nsp);
+        match self.transport.broker().subscribers(&channel) {
+            Ok(Some(listening)) => listening > 0,
+            Ok(None) => self
+                .query(self.request(ALL_ROOMS), self.request_timeout)
+                .is_ok_and(|responses| !responses.is_empty()),
+            Err(_) => false,
+        }
+    }
+
+    /// Fetches the selected sockets from every socket.io server.
+    pub fn fetch_sockets(&self) -> RedisResult<Vec<RemoteSocket>> {
+        let mut sockets = Vec::new();
+        for mut response in self.query(self.request(REMOTE_FETCH), self.request_timeout)? {
+            if let Value::Array(found) = response["sockets"].take() {
+                for socket in found {
+                    sockets.push(serde_json::from_value(socket).map_err(remote_error)?);
+                }
+            }
+        }
+        Ok(sockets)
+    }
+
+    /// Fetches the selected sockets matching `predicate`, e.g. the admins
+    /// of a room with `|socket| socket.data_field("role") == Some(&"admin".into())`.
+    /// Sockets are filtered once fetched, not by the servers.
+    pub fn fetch_sockets_where<F>(&self, predicate: F) -> RedisResult<Vec<RemoteSocket>>
+    where
+        F: Fn(&RemoteSocket) -> bool,
+    {
+        let mut sockets = self.fetch_sockets()?;
+        sockets.retain(|socket| predicate(socket));
+        Ok(sockets)
+    }
+
+    /// How many sockets are selected, each counted once.
+    pub fn sockets_count(&self) -> RedisResult<usize> {
+        let sockets = self.fetch_sockets()?;
+        let ids: BTreeSet<&str> = sockets.iter().map(|socket| socket.id.as_str()).collect();
+        Ok(ids.len())
+    }
+
+    /// The uids of the socket.io servers answering requests in the
+    /// namespace, sorted. Only adapters tagging their responses with their
+    /// uid, such as the cluster adapters, are listed; the others are counted
+    /// by `is_cluster_reachable` but can't be told apart.
+    pub fn list_server_uids(&self) -> RedisResult<Vec<String>> {
+        let responses = self.query(self.request(ALL_ROOMS), self.request_timeout)?;
+        let uids: BTreeSet<String> = responses
+            .iter()
+            .filter_map(|response| response["uid"].as_str())
+            .map(String::from)
+            .collect();
+        Ok(uids.into_iter().collect())
+    }
+
+    /// The rooms of the namespace on every socket.io server, including the
+    /// private room of each socket named after its id.
+    pub fn all_rooms(&self) -> RedisResult<BTreeSet<String>> {
+        let mut rooms = BTreeSet::new();
+        for response in self.query(self.request(ALL_ROOMS), self.request_timeout)? {
+            let found = response["rooms"].as_array().into_iter().flatten();
+            rooms.extend(found.filter_map(Value::as_str).map(String::from));
+        }
+        Ok(rooms)
+    }
+
+    /// How many rooms the namespace has, or how many of the selected rooms
+    /// have sockets in them.
+    pub fn rooms_count(&self) -> RedisResult<usize> {
+        let rooms = self.all_rooms()?;
+        if self.rooms.is_empty() {
+            return Ok(rooms.len());
+        }
+        Ok(self.rooms.intersection(&rooms).count())
+    }
+
+    /// Makes the selected sockets join `rooms`.
+    pub fn sockets_join<R: IntoRooms>(&self, rooms: R) -> RedisResult<()> {
+        let mut request = self.request(REMOTE_JOIN);
+        request.insert("rooms".to_string(), json!(rooms.into_rooms()));
+        self.publish_request(request)
+    }
+
+    /// Makes the selected sockets leave `rooms`.
+    pub fn sockets_leave<R: IntoRooms>(&self, rooms: R) -> RedisResult<()> {
+        let mut request = self.request(REMOTE_LEAVE);
+        request.insert("rooms".to_string(), json!(rooms.into_rooms()));
+        self.publish_request(request)
+    }
+
+    /// Calls the `event` handler of every socket.io server with `request`
+    /// through `serverSideEmit`, and returns the value each passed to its
+    /// acknowledgement callback, waiting at most `timeout`.
+    pub fn rpc<Req, Resp>(
+        &self,
+        event: &str,
+        request: &Req,
+        timeout: Duration,
+    ) -> RedisResult<Vec<Resp>>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        let data = serde_json::to_value(request).map_err(remote_error)?;
+        let mut emit = self.request(SERVER_SIDE_EMIT);
+        emit.insert("data".to_string(), json!([event, data]));
+        self.query(emit, timeout)?
+            .into_iter()
+            .map(|mut response| {
+                serde_json::from_value(response["data"].take()).map_err(remote_error)
+            })
+            .collect()
+    }
+
+    /// Disconnects every socket of the namespace, whatever rooms are
+    /// selected. With `close`, the underlying connections are closed as
+    /// well instead of only leaving the namespace.
+    pub fn disconnect_namespace(&self, close: bool) -> RedisResult<()> {
+        let mut request = self.clone().reset().request(REMOTE_DISCONNECT);
+        request.insert("close".to_string(), json!(close));
+        self.publish_request(request)
+    }
+
+    /// Moves every socket of the namespace in room `from` to room `to`,
+    /// cluster-wide, and returns the sockets that were in `from`. Sockets
+    /// joining `from` meanwhile may be moved without being returned.
+    pub fn migrate_room(&self, from: &str, to: &str) -> RedisResult<Vec<RemoteSocket>> {
+        let source = self.clone().reset().to(from);
+        let sockets = source.fetch_sockets()?;
+        if !sockets.is_empty() && from != to {
+            source.sockets_join(to)?;
+            source.sockets_leave(from)?;
+        }
+        Ok(sockets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Broker, BrokerMessage, Subscription};
+    use std::collections::{BTreeSet, HashMap};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+
+    type Sockets = HashMap<String, BTreeSet<String>>;
+
+    /// socket.io servers answering requests the way the Redis adapter
+    /// does.
+    #[derive(Clone, Default)]
+    struct Cluster {
+        servers: Arc<Mutex<Vec<Sockets>>>,
+        subscribers: Arc<Mutex<Vec<Sender<BrokerMessage>>>>,
+        /// Servers subscribed to requests that never answer.
+        silent: Arc<AtomicUsize>,
+        /// Sockets disconnected with their connection closed.
+        closed: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Cluster {
+        fn new(servers: Vec<Vec<(&str, Vec<&str>)>>) -> Cluster {
+            let servers = servers
+                .into_iter()
+                .map(|sockets| {
+                    let rooms = |rooms: Vec<&str>| rooms.into_iter().map(String::from).collect();
+                    sockets
+                        .into_iter()
+                        .map(|(id, joined)| (id.to_string(), rooms(joined)))
+                        .collect()
+                })
+                .collect();
+            Cluster {
+                servers: Arc::new(Mutex::new(servers)),
+                subscribers: Default::default(),
+                silent: Default::default(),
+                closed: Default::default(),
+            }
+        }
+
+        fn respond(&self, response: Value) {
+            for subscriber in self.subscribers.lock().unwrap().iter() {
+                let _ = subscriber.send(BrokerMessage {
+                    channel: "socket.io-response#/#".to_string(),
+                    payload: serde_json::to_vec(&response).unwrap(),
+                });
+            }
+        }
+
+        fn rooms(&self, id: &str) -> Vec<String> {
+            let servers = self.servers.lock().unwrap();
+            let rooms = servers.iter().find_map(|sockets| sockets.get(id));
+            rooms.unwrap().iter().cloned().collect()
+        }
+    }
+
+    fn targeted(rooms: &BTreeSet<String>, request: &Value) -> bool {
+        let targets = request["opts"]["rooms"].as_array().unwrap();
+        targets.is_empty()
+            || targets
+                .iter()
+                .any(|room| rooms.contains(room.as_str().unwrap()))
+    }
+
+    impl Broker for Cluster {
+        fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+            assert_eq!("socket.io-request#/#", channel);
+            let request: Value = serde_json::from_slice(payload).unwrap();
+            let mut servers = self.servers.lock().unwrap();
+            for (n, sockets) in servers.iter_mut().enumerate() {
+                let selected = sockets
+                    .iter_mut()
+                    .filter(|(_, rooms)| targeted(rooms, &request));
+                let rooms = request["rooms"].as_array().into_iter().flatten();
+                let rooms: Vec<String> = rooms.map(|r| r.as_str().unwrap().to_string()).collect();
+                match request["type"].as_u64().unwrap() as u8 {
+                    REMOTE_FETCH => {
+                        let found: Vec<Value> = selected
+                            .map(|(id, rooms)| {
+                                let role = if id == "c" { "admin" } else { "user" };
+                                json!({
+                                    "id": id,
+                                    "rooms": rooms,
+                                    "data": { "user": { "role": role } },
+                                    "handshake": {
+                                        "headers": { "x-forwarded-for": ["10.0.0.1", "10.0.0.2"] },
+                                        "auth": { "token": id },
+                                        "secure": true,
+                                    },
+                                })
+                            })
+                            .collect();
+                        self.respond(
+                            json!({ "requestId": request["requestId"], "sockets": found }),
+                        );
+                    }
+                    ALL_ROOMS => {
+                        let rooms: BTreeSet<&String> = sockets.values().flatten().collect();
+                        let uid = format!("server-{}", n);
+                        let id = &request["requestId"];
+                        self.respond(json!({ "requestId": id, "uid": uid, "rooms": rooms }));
+                    }
+                    SERVER_SIDE_EMIT => {
+                        assert_eq!("add", request["data"][0]);
+                        let terms = request["data"][1].as_array().unwrap();
+                        let sum: u64 = terms.iter().filter_map(Value::as_u64).sum();
+                        let data = json!({ "sum": sum, "sockets": sockets.len() });
+                        self.respond(json!({ "requestId": request["requestId"], "data": data }));
+                    }
+                    REMOTE_JOIN => selected.for_each(|(_, joined)| joined.extend(rooms.clone())),
+                    REMOTE_LEAVE => selected.for_each(|(_, joined)| {
+                        joined.retain(|room| !rooms.contains(room));
+                    }),
+                    REMOTE_DISCONNECT => {
+                        let ids: Vec<String> = selected.map(|(id, _)| id.clone()).collect();
+                        sockets.retain(|id, _| !ids.contains(id));
+                        if request["close"] == true {
+                            self.closed.lock().unwrap().extend(ids);
+                        }
+                    }
+                    kind => panic!("unexpected request type {}", kind),
+                }
+            }
+            Ok(())
+        }
+
+        fn subscribers(&self, _channel: &str) -> RedisResult<Option<usize>> {
+            let silent = self.silent.load(Ordering::Relaxed);
+            Ok(Some(self.servers.lock().unwrap().len() + silent))
+        }
+
+        fn subscribe(&self, channels: &[String]) -> RedisResult<Box<dyn Subscription>> {
+            assert_eq!("socket.io-response#/#emitter#", channels[1]);
+            let (sender, receiver) = mpsc::channel();
+            self.subscribers.lock().unwrap().push(sender);
+            Ok(Box::new(Responses(receiver)))
+        }
+    }
+
+    struct Responses(Receiver<BrokerMessage>);
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Sum {
+        sum: u64,
+        sockets: usize,
+    }
+
+    impl Subscription for Responses {
+        fn next_message(&mut self, timeout: Duration) -> RedisResult<Option<BrokerMessage>> {
+            Ok(self.0.recv_timeout(timeout).ok())
+        }
+    }
+
+    fn cluster() -> Cluster {
+        Cluster::new(vec![
+            vec![("a", vec!["a", "game-1"]), ("b", vec!["b", "lobby"])],
+            vec![("c", vec!["c", "game-1", "chat"])],
+        ])
+    }
+
+    #[test]
+    fn migrates_rooms() {
+        let cluster = cluster();
+        let io = Emitter::with_broker(cluster.clone());
+        let moved = io
+            .clone()
+            .to("lobby")
+            .migrate_room("game-1", "game-2")
+            .unwrap();
+        let mut ids: Vec<_> = moved.iter().map(|socket| socket.id.as_str()).collect();
+        ids.sort_unstable();
+        assert_eq!(vec!["a", "c"], ids);
+        assert_eq!(vec!["a", "game-2"], cluster.rooms("a"));
+        assert_eq!(vec!["c", "chat", "game-2"], cluster.rooms("c"));
+        assert_eq!(vec!["b", "lobby"], cluster.rooms("b"));
+
+        io.to("lobby").sockets_join(vec!["x", "y"]).unwrap();
+        assert_eq!(vec!["b", "lobby", "x", "y"], cluster.rooms("b"));
+    }
+
+    #[test]
+    fn disconnects_namespace() {
+        let cluster = cluster();
+        let io = Emitter::with_broker(cluster.clone()).to("lobby");
+        io.disconnect_namespace(false).unwrap();
+        assert!(cluster
+            .servers
+            .lock()
+            .unwrap()
+            .iter()
+            .all(HashMap::is_empty));
+        assert!(cluster.closed.lock().unwrap().is_empty());
+        cluster.servers.lock().unwrap()[0].insert("d".to_string(), BTreeSet::new());
+        io.disconnect_namespace(true).unwrap();
+        assert_eq!(vec!["d"], *cluster.closed.lock().unwrap());
+    }
+
+    #[test]
+    fn counts_sockets_and_rooms() {
+        let io = Emitter::with_broker(cluster());
+        assert_eq!(3, io.fetch_sockets().unwrap().len());
+        assert_eq!(
+            2,
+            io.clone().to(["game-1", "chat"]).sockets_count().unwrap()
+        );
+        assert_eq!(6, io.rooms_count().unwrap());
+        assert_eq!(1, io.to(["chat", "game-2"]).rooms_count().unwrap());
+    }
+
+    #[test]
+    fn calls_servers() {
+        let io = Emitter::with_broker(cluster());
+        let mut sums: Vec<Sum> = io
+            .rpc("add", &vec![1u64, 2, 3], Duration::from_secs(1))
+            .unwrap();
+        sums.sort_unstable_by_key(|sum| sum.sockets);
+        assert_eq!(
+            vec![Sum { sum: 6, sockets: 1 }, Sum { sum: 6, sockets: 2 }],
+            sums
+        );
+    }
+
+    #[test]
+    fn times_out_on_silent_servers() {
+        let cluster = cluster();
+        cluster.silent.store(1, Ordering::Relaxed);
+        let io = Emitter::with_broker(cluster).request_timeout(Duration::from_millis(20));
+        assert!(io.fetch_sockets().is_err());
+        let started = Instant::now();
+        let timed_out = io
+            .rpc::<Vec<u64>, Sum>("add", &vec![1, 2], Duration::from_millis(50))
+            .unwrap_err();
+        assert_eq!(ErrorKind::IoError, timed_out.kind());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn lists_server_uids() {
+        let io = Emitter::with_broker(cluster());
+        assert_eq!(vec!["server-0", "server-1"], io.list_server_uids().unwrap());
+    }
+
+    #[test]
+    fn fetches_sockets_where() {
+        let admins = Emitter::with_broker(cluster())
+            .to("game-1")
+            .fetch_sockets_where(|socket| socket.data_field("user.role") == Some(&json!("admin")))
+            .unwrap();
+        assert_eq!(1, admins.len());
+        assert_eq!(json!({ "token": "c" }), admins[0].handshake.auth);
+        assert!(admins[0].handshake.secure);
+        let forwarded = admins[0].header("X-Forwarded-For");
+        assert_eq!(Some("10.0.0.1, 10.0.0.2"), forwarded.as_deref());
+        assert_eq!(None, admins[0].data_field("user.name"));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::Broker;
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A packet read back from a recording.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Recorded {
+    pub timestamp: SystemTime,
+    pub channel: String,
+    pub payload: Vec<u8>,
+}
+
+/// Appends every published packet to a file, with the time it was published,
+/// for later use with `Replay`. Each record is a msgpack timestamp in
+/// microseconds since the epoch, channel string and binary payload.
+#[derive(Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<BufWriter<File>>>,
+    forward: Option<Arc<dyn Broker>>,
+}
+
+impl Recorder {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn create<P: AsRef<Path>>(path: P) -> RedisResult<Recorder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            file: Arc::new(Mutex::new(BufWriter::new(file))),
+            forward: None,
+        })
+    }
+
+    /// Publishes the packets through `broker` after recording them, instead
+    /// of only recording them.
+    pub fn forward<B: Broker + 'static>(mut self, broker: B) -> Recorder {
+        self.forward = Some(Arc::new(broker));
+        self
+    }
+}
+
+impl Broker for Recorder {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        {
+            let mut file = self.file.lock().unwrap();
+            let mut record = Vec::with_capacity(payload.len() + channel.len() + 16);
+            rmp::encode::write_uint(&mut record, micros).map_err(io_error)?;
+            rmp::encode::write_str(&mut record, channel).map_err(io_error)?;
+            rmp::encode::write_bin(&mut record, payload).map_err(io_error)?;
+            file.write_all(&record)?;
+            file.flush()?;
+        }
+        match &self.forward {
+            Some(broker) => broker.publish(channel, payload),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("forward", &self.forward.is_some())
+            .finish()
+    }
+}
+
+/// Publishes the packets of a recording again, with the gaps between them
+/// scaled by `speed`.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    records: Vec<Recorded>,
+    speed: f64,
+}
+
+impl Replay {
+    pub fn open<P: AsRef<Path>>(path: P) -> RedisResult<Replay> {
+        let mut buf = Vec::new();
+        File::open(path)?.read_to_end(&mut buf)?;
+        let mut rd = buf.as_slice();
+        let mut records = Vec::new();
+        while !rd.is_empty() {
+            let micros: u64 = rmp::decode::read_int(&mut rd).map_err(decode_error)?;
+            let channel_len = rmp::decode::read_str_len(&mut rd).map_err(decode_error)?;
+            let channel = take(&mut rd, channel_len)?;
+            let channel = String::from_utf8(channel.to_vec()).map_err(decode_error)?;
+            let payload_len = rmp::decode::read_bin_len(&mut rd).map_err(decode_error)?;
+            let payload = take(&mut rd, payload_len)?.to_vec();
+            records.push(Recorded {
+                timestamp: UNIX_EPOCH + Duration::from_micros(micros),
+                channel,
+                payload,
+            });
+        }
+        Ok(Replay {
+            records,
+            speed: 1.0,
+        })
+    }
+
+    /// Replays `speed` times faster than recorded; `f64::INFINITY` publishes
+    /// without any delay.
+    pub fn speed(mut self, speed: f64) -> Replay {
+        self.speed = speed;
+        self
+    }
+
+    pub fn records(&self) -> &[Recorded] {
+        &self.records
+    }
+
+    /// Publishes every record through `broker`, returning how many were sent.
+    pub fn run<B: Broker + ?Sized>(&self, broker: &B) -> RedisResult<usize> {
+        let mut previous = None;
+        for record in &self.records {
+            if let Some(previous) = previous {
+                let gap = record
+                    .timestamp
+                    .duration_since(previous)
+                    .unwrap_or_default();
+                if self.speed.is_finite() && self.speed > 0.0 {
+                    thread::sleep(gap.div_f64(self.speed));
+                }
+            }
+            previous = Some(record.timestamp);
+            broker.publish(&record.channel, &record.payload)?;
+        }
+        Ok(self.records.len())
+    }
+}
+
+fn take<'a>(rd: &mut &'a [u8], len: u32) -> RedisResult<&'a [u8]> {
+    let len = len as usize;
+    if rd.len() < len {
+        return Err(decode_error("truncated record"));
+    }
+    let (head, tail) = rd.split_at(len);
+    *rd = tail;
+    Ok(head)
+}
+
+fn io_error<E: Into<std::io::Error>>(e: E) -> RedisError {
+    RedisError::from(e.into())
+}
+
+fn decode_error<E: fmt::Display>(e: E) -> RedisError {
+    RedisError::from((ErrorKind::TypeError, "invalid recording", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Recorder, Replay};
+    use crate::{Emitter, InMemoryBroker};
+    use std::time::Instant;
+
+    #[test]
+    fn record_and_replay() {
+        let path = std::env::temp_dir().join(format!("emitter-replay-{}", std::process::id()));
+        let live = InMemoryBroker::new();
+        let recorder = Recorder::create(&path).unwrap().forward(live.clone());
+        Emitter::with_broker(recorder)
+            .emit(vec!["first"])
+            .to("room")
+            .emit(vec!["second"]);
+
+        let replayed = InMemoryBroker::new();
+        let started = Instant::now();
+        let sent = Replay::open(&path)
+            .unwrap()
+            .speed(f64::INFINITY)
+            .run(&replayed)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(2, sent);
+        assert_eq!(live.published(), replayed.published());
+        assert_eq!(vec!["first", "second"], replayed.events());
+        assert!(started.elapsed().as_secs() < 1);
+    }
+}
@@ -0,0 +1,216 @@
+//! Rocket integration. Attach `EmitterFairing` and take `&SocketIo` as a
+//! request guard:
+//!
+//! ```no_run
+//! use rocket::http::Status;
+//! use rocket::{launch, post, routes};
+//! use socketio_rust_emitter::{EmitterFairing, SocketIo};
+//!
+//! #[post("/rooms/<room>/notify")]
+//! async fn notify(io: &SocketIo, room: &str) -> Status {
+//!     match io.emit(room, "message", vec!["hello"]).await {
+//!         Ok(()) => Status::Accepted,
+//!         Err(_) => Status::ServiceUnavailable,
+//!     }
+//! }
+//!
+//! // Reads `socketio.url` and optionally `socketio.key` from Rocket.toml.
+//! #[launch]
+//! fn rocket() -> _ {
+//!     rocket::build()
+//!         .attach(EmitterFairing::from_config())
+//!         .mount("/", routes![notify])
+//! }
+//! ```
+
+use crate::{Emitter, IntoRooms};
+use redis::{ErrorKind, RedisError, RedisResult};
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{Build, Orbit, Rocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Manages the emitter of a Rocket app: it is created when the app ignites
+/// and in-flight emits are awaited, up to the grace period, on shutdown.
+pub struct EmitterFairing {
+    emitter: Mutex<Option<Emitter>>,
+    grace: Duration,
+}
+
+impl EmitterFairing {
+    pub fn new(emitter: Emitter) -> EmitterFairing {
+        EmitterFairing {
+            emitter: Mutex::new(Some(emitter)),
+            grace: Duration::from_secs(5),
+        }
+    }
+
+    /// Creates the emitter from the `socketio.url` and optional
+    /// `socketio.key` config values when the app ignites.
+    pub fn from_config() -> EmitterFairing {
+        EmitterFairing {
+            emitter: Mutex::new(None),
+            grace: Duration::from_secs(5),
+        }
+    }
+
+    /// How long shutdown waits for in-flight emits, 5 seconds by default.
+    pub fn grace(mut self, grace: Duration) -> EmitterFairing {
+        self.grace = grace;
+        self
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for EmitterFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "socket.io emitter",
+            kind: Kind::Ignite | Kind::Shutdown,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let configured = self.emitter.lock().unwrap().take();
+        let emitter = match configured {
+            Some(emitter) => emitter,
+            None => {
+                let figment = rocket.figment();
+                let url: String = match figment.extract_inner("socketio.url") {
+                    Ok(url) => url,
+                    Err(e) => {
+                        rocket::error!("socket.io emitter config: {}", e);
+                        return Err(rocket);
+                    }
+                };
+                let key = figment
+                    .extract_inner::<String>("socketio.key")
+                    .unwrap_or_else(|_| "socket.io".to_string());
+                match Emitter::builder().endpoint(&url).prefix(&key).build() {
+                    Ok(emitter) => emitter,
+                    Err(e) => {
+                        rocket::error!("socket.io emitter: {}", e);
+                        return Err(rocket);
+                    }
+                }
+            }
+        };
+        Ok(rocket.manage(SocketIo {
+            emitter,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }))
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        let io = match rocket.state::<SocketIo>() {
+            Some(io) => io,
+            None => return,
+        };
+        let deadline = Instant::now() + self.grace;
+        while io.in_flight.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+            rocket::tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+/// The emitter managed by `EmitterFairing`, taken by routes as `&SocketIo`.
+/// Emits run on the blocking thread pool.
+#[derive(Debug)]
+pub struct SocketIo {
+    emitter: Emitter,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl SocketIo {
+    pub fn emitter(&self) -> &Emitter {
+        &self.emitter
+    }
+
+    /// Emits `event` with `args` to `rooms` in the emitter's namespace.
+    pub async fn emit<R: IntoRooms>(
+        &self,
+        rooms: R,
+        event: &str,
+        args: Vec<&str>,
+    ) -> RedisResult<()> {
+        let mut emitter = self.emitter.clone();
+        emitter.rooms.extend(rooms.into_rooms());
+        let mut message = vec![event.to_string()];
+        message.extend(args.into_iter().map(str::to_string));
+        let in_flight = self.in_flight.clone();
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        let result = rocket::tokio::task::spawn_blocking(move || {
+            let message: Vec<&str> = message.iter().map(String::as_str).collect();
+            let result = emitter.send(&emitter.rooms, &message);
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            result
+        })
+        .await;
+        result.unwrap_or_else(|e| {
+            Err(RedisError::from((
+                ErrorKind::ClientError,
+                "emit task failed",
+                e.to_string(),
+            )))
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r SocketIo {
+    type Error = &'static str;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.rocket().state::<SocketIo>() {
+            Some(io) => Outcome::Success(io),
+            None => Outcome::Error((
+                Status::InternalServerError,
+                "EmitterFairing is not attached",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryBroker;
+    use rocket::local::blocking::Client;
+    use rocket::{post, routes};
+
+    #[post("/rooms/<room>/notify")]
+    async fn notify(io: &SocketIo, room: &str) -> Status {
+        match io.emit(room, "message", vec!["hello"]).await {
+            Ok(()) => Status::Accepted,
+            Err(_) => Status::ServiceUnavailable,
+        }
+    }
+
+    #[test]
+    fn fairing_and_guard() {
+        let broker = InMemoryBroker::new();
+        let rocket = rocket::build()
+            .attach(EmitterFairing::new(Emitter::with_broker(broker.clone())))
+            .mount("/", routes![notify]);
+        let client = Client::tracked(rocket).unwrap();
+        let res = client.post("/rooms/news/notify").dispatch();
+        assert_eq!(Status::Accepted, res.status());
+        let published = broker.published();
+        assert_eq!(1, published.len());
+        assert_eq!("socket.io#/#news#", published[0].channel);
+
+        let client = Client::tracked(rocket::build().mount("/", routes![notify])).unwrap();
+        let res = client.post("/rooms/news/notify").dispatch();
+        assert_eq!(Status::InternalServerError, res.status());
+
+        let rocket = rocket::build().attach(EmitterFairing::from_config());
+        let error = Client::tracked(rocket).err().unwrap();
+        assert!(matches!(
+            error.kind(),
+            rocket::error::ErrorKind::FailedFairings(_)
+        ));
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::{btree_map, hash_map, BTreeSet, HashSet};
+use std::fmt;
+use std::iter::{Chain, Cloned, Copied, Filter, FilterMap, FlatMap, Flatten, Map, Rev, Skip, Take};
+
+/// A room name.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct Room(String);
+
+impl Room {
+    pub fn new<S: Into<String>>(name: S) -> Room {
+        Room(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Room {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for Room {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<Room> for String {
+    fn from(room: Room) -> String {
+        room.0
+    }
+}
+
+/// The id of a socket.io socket. Every socket is in the room named after
+/// its id, so targeting it reaches that socket only.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct SocketId(String);
+
+impl SocketId {
+    pub fn new<S: Into<String>>(id: S) -> SocketId {
+        SocketId(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SocketId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for SocketId {
+    fn from(id: &str) -> SocketId {
+        SocketId::new(id)
+    }
+}
+
+impl From<String> for SocketId {
+    fn from(id: String) -> SocketId {
+        SocketId(id)
+    }
+}
+
+impl IntoRoom for SocketId {
+    fn into_room(self) -> Room {
+        Room(self.0)
+    }
+}
+
+/// Maps a value to the room it stands for, e.g.
+/// `impl IntoRoom for ConversationId` returning `Room::new(format!("conv:{}", id))`.
+pub trait IntoRoom {
+    fn into_room(self) -> Room;
+}
+
+impl IntoRoom for Room {
+    fn into_room(self) -> Room {
+        self
+    }
+}
+
+impl<T: IntoRoom + Clone> IntoRoom for &T {
+    fn into_room(self) -> Room {
+        self.clone().into_room()
+    }
+}
+
+impl IntoRoom for &str {
+    fn into_room(self) -> Room {
+        Room::new(self)
+    }
+}
+
+impl IntoRoom for String {
+    fn into_room(self) -> Room {
+        Room(self)
+    }
+}
+
+/// Anything `Emitter::to` accepts: a single room, or a collection or iterator
+/// of them.
+pub trait IntoRooms {
+    fn into_rooms(self) -> Vec<String>;
+}
+
+impl<T: IntoRoom> IntoRooms for T {
+    fn into_rooms(self) -> Vec<String> {
+        vec![self.into_room().into()]
+    }
+}
+
+impl<T: AsRef<str>> IntoRooms for &[T] {
+    fn into_rooms(self) -> Vec<String> {
+        self.iter().map(|room| room.as_ref().to_string()).collect()
+    }
+}
+
+impl<T: AsRef<str>> IntoRooms for &Vec<T> {
+    fn into_rooms(self) -> Vec<String> {
+        self.as_slice().into_rooms()
+    }
+}
+
+// Owned collections and iterator chains of rooms, or of references to them.
+macro_rules! iter_rooms {
+    ($($ty:ty, [$($params:tt)*];)*) => {
+        $(
+            impl<$($params)*> IntoRooms for $ty
+            where
+                $ty: IntoIterator,
+                <$ty as IntoIterator>::Item: IntoRoom,
+            {
+                fn into_rooms(self) -> Vec<String> {
+                    IntoIterator::into_iter(self)
+                        .map(|room| room.into_room().into())
+                        .collect()
+                }
+            }
+        )*
+    };
+}
+
+iter_rooms! {
+    Vec<T>, [T];
+    [T; N], [T, const N: usize];
+    HashSet<T>, [T];
+    BTreeSet<T>, [T];
+    std::vec::IntoIter<T>, [T];
+    std::slice::Iter<'a, T>, ['a, T];
+    hash_map::Keys<'a, K, V>, ['a, K, V];
+    btree_map::Keys<'a, K, V>, ['a, K, V];
+    Map<I, F>, [I, F];
+    Filter<I, P>, [I, P];
+    FilterMap<I, F>, [I, F];
+    Chain<A, B>, [A, B];
+    Cloned<I>, [I];
+    Copied<I>, [I];
+    Rev<I>, [I];
+    Take<I>, [I];
+    Skip<I>, [I];
+    FlatMap<I, U, F>, [I, U: IntoIterator, F];
+    Flatten<I>, [I: Iterator<Item = J>, J: IntoIterator];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IntoRoom, Room, SocketId};
+    use crate::{Emitter, InMemoryBroker};
+    use std::collections::HashMap;
+
+    #[derive(Clone, Copy)]
+    struct ConversationId(u32);
+
+    impl IntoRoom for ConversationId {
+        fn into_room(self) -> Room {
+            Room::new(format!("conv:{}", self.0))
+        }
+    }
+
+    #[test]
+    fn to_typed_rooms() {
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .to(ConversationId(1))
+            .to(vec![ConversationId(2), ConversationId(3)])
+            .to(Room::new("lobby"))
+            .emit(vec!["test"]);
+
+        assert_eq!(
+            &["conv:1", "conv:2", "conv:3", "lobby"],
+            broker.published()[0].opts.rooms()
+        );
+    }
+
+    #[test]
+    fn to_many_rooms() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+        let dynamic = vec!["c".to_string(), "d".to_string()];
+        let users = [1, 2];
+
+        io.clone()
+            .to("a")
+            .in_(["b"])
+            .to(&dynamic)
+            .to(users.iter().map(|id| format!("user:{}", id)))
+            .emit(vec!["test"]);
+        io.to(dynamic).emit(vec!["test"]);
+
+        let published = broker.published();
+        assert_eq!(
+            &["a", "b", "c", "d", "user:1", "user:2"],
+            published[0].opts.rooms()
+        );
+        assert_eq!(&["c", "d"], published[1].opts.rooms());
+    }
+
+    #[test]
+    fn to_rooms_from_iterator_adapters() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+        let names = ["a".to_string(), "b".to_string(), "c".to_string()];
+        let members: HashMap<String, u32> = vec![("m".to_string(), 1)].into_iter().collect();
+        let teams = vec![vec!["t1", "t2"], vec!["t3"]];
+
+        io.clone().to(names.iter()).emit(vec!["slice"]);
+        io.clone().to(names.iter().rev().take(2)).emit(vec!["rev"]);
+        io.clone().to(names.iter().skip(2)).emit(vec!["skip"]);
+        io.clone().to(members.keys()).emit(vec!["keys"]);
+        io.clone()
+            .to(teams.iter().flat_map(|team| team.iter()))
+            .emit(vec!["flat"]);
+        io.clone()
+            .to(teams.into_iter().flatten())
+            .emit(vec!["flatten"]);
+        io.to([ConversationId(4)].iter()).emit(vec!["typed"]);
+
+        let rooms: Vec<Vec<String>> = broker
+            .published()
+            .iter()
+            .map(|packet| packet.opts.rooms().to_vec())
+            .collect();
+        assert_eq!(
+            vec![
+                vec!["a", "b", "c"],
+                vec!["b", "c"],
+                vec!["c"],
+                vec!["m"],
+                vec!["t1", "t2", "t3"],
+                vec!["t1", "t2", "t3"],
+                vec!["conv:4"],
+            ],
+            rooms
+        );
+    }
+
+    #[test]
+    fn emit_to_sockets() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone()).to("ignored");
+        io.emit_to_sockets(vec!["sid2", "sid1"], "kick", vec!["idle"])
+            .unwrap();
+        io.emit_to_sockets(Vec::<SocketId>::new(), "kick", vec![])
+            .unwrap();
+
+        let published = broker.published();
+        assert_eq!(1, published.len());
+        assert_eq!("socket.io#/#", published[0].channel);
+        assert_eq!(&["sid1", "sid2"], published[0].opts.rooms());
+        assert_eq!(&["kick", "idle"], published[0].packet.data());
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Per-event sample rates applied by the emitter, for shedding non-critical
+/// traffic. Clones share the rates, so they can be changed while emitters
+/// are running.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    rates: Arc<RwLock<HashMap<String, f64>>>,
+    state: Arc<AtomicU64>,
+}
+
+impl Default for Sampler {
+    fn default() -> Sampler {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Sampler {
+            rates: Default::default(),
+            state: Arc::new(AtomicU64::new(seed)),
+        }
+    }
+}
+
+impl Sampler {
+    pub fn new() -> Sampler {
+        Sampler::default()
+    }
+
+    /// Emits only `rate` (0.0 to 1.0) of the packets for `event`.
+    pub fn rate(self, event: &str, rate: f64) -> Sampler {
+        self.set_rate(event, rate);
+        self
+    }
+
+    pub fn set_rate(&self, event: &str, rate: f64) {
+        let rate = rate.clamp(0.0, 1.0);
+        self.rates.write().unwrap().insert(event.to_string(), rate);
+    }
+
+    /// Emits every packet for `event` again.
+    pub fn clear_rate(&self, event: &str) {
+        self.rates.write().unwrap().remove(event);
+    }
+
+    pub(crate) fn sample(&self, event: Option<&str>) -> bool {
+        let rate = match event.and_then(|e| self.rates.read().unwrap().get(e).copied()) {
+            Some(rate) => rate,
+            None => return true,
+        };
+        rate >= 1.0 || uniform(&self.state) < rate
+    }
+}
+
+/// A uniformly distributed value in `[0, 1)`, from splitmix64 over `state`.
+pub(crate) fn uniform(state: &AtomicU64) -> f64 {
+    let mut z = state
+        .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sampler;
+    use crate::{Emitter, InMemoryBroker};
+
+    #[test]
+    fn samples_configured_events() {
+        let broker = InMemoryBroker::new();
+        let sampler = Sampler::new().rate("cursor:move", 0.1).rate("typing", 0.0);
+        let mut io = Emitter::with_broker(broker.clone()).sampler(sampler.clone());
+        for _ in 0..1000 {
+            io = io
+                .emit(vec!["cursor:move"])
+                .emit(vec!["typing"])
+                .emit(vec!["message"]);
+        }
+        let events = broker.events();
+        let sampled = events.iter().filter(|e| *e == "cursor:move").count();
+        assert!(sampled > 30 && sampled < 250, "{} sampled", sampled);
+        assert!(!events.iter().any(|e| e == "typing"));
+        assert_eq!(1000, events.iter().filter(|e| *e == "message").count());
+
+        sampler.clear_rate("typing");
+        io.emit(vec!["typing"]);
+        broker.assert_emitted("typing");
+    }
+}
@@ -0,0 +1,525 @@
+// Without threads on wasm32 the scheduler and poller threads and what only they use are
+// compiled but never started.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use crate::pool::ConnectionPool;
+use crate::{BridgeHandle, Broker, Emitter};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, BinaryHeap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// When a recurring emit runs again.
+#[derive(Clone)]
+pub(crate) enum Repeat {
+    Interval(Duration),
+    #[cfg(feature = "cron")]
+    Cron(Box<cron::Schedule>),
+}
+
+impl Repeat {
+    /// The next run after one that was due at `due`. Interval emits that
+    /// fell behind skip the missed runs instead of catching up.
+    pub(crate) fn next(&self, due: Instant) -> Option<Instant> {
+        match self {
+            Repeat::Interval(interval) => Some((due + *interval).max(Instant::now())),
+            #[cfg(feature = "cron")]
+            Repeat::Cron(schedule) => {
+                let next = schedule.upcoming(chrono::Utc).next()?;
+                let wait = (next - chrono::Utc::now()).to_std().unwrap_or_default();
+                Some(Instant::now() + wait)
+            }
+        }
+    }
+}
+
+/// Cancels a recurring emit. Dropping the handle leaves the emit running.
+#[derive(Debug, Clone)]
+pub struct ScheduleHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScheduleHandle {
+    /// Stops the emit; a run that already started still publishes.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, AtomicOrdering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(AtomicOrdering::SeqCst)
+    }
+}
+
+struct Job {
+    due: Instant,
+    seq: u64,
+    emitter: Emitter,
+    rooms: BTreeSet<String>,
+    message: Vec<String>,
+    repeat: Option<Repeat>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Job) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Job) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Job) -> Ordering {
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+/// Runs delayed emits on a background thread, started on first use and
+/// shared by all emitters in the process.
+struct Scheduler {
+    jobs: Mutex<BinaryHeap<Reverse<Job>>>,
+    wake: Condvar,
+}
+
+impl Scheduler {
+    fn global() -> &'static Arc<Scheduler> {
+        static GLOBAL: OnceLock<Arc<Scheduler>> = OnceLock::new();
+        GLOBAL.get_or_init(|| {
+            let scheduler = Arc::new(Scheduler {
+                jobs: Mutex::new(BinaryHeap::new()),
+                wake: Condvar::new(),
+            });
+            let worker = scheduler.clone();
+            thread::Builder::new()
+                .name("socketio-emitter-scheduler".to_string())
+                .spawn(move || worker.run())
+                .expect("failed to start the emit scheduler");
+            scheduler
+        })
+    }
+
+    fn run(&self) {
+        let mut jobs = self.jobs.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            match jobs.peek() {
+                None => jobs = self.wake.wait(jobs).unwrap(),
+                Some(Reverse(job)) if job.due > now => {
+                    let timeout = job.due - now;
+                    jobs = self.wake.wait_timeout(jobs, timeout).unwrap().0;
+                }
+                Some(_) => {
+                    let Reverse(mut job) = jobs.pop().unwrap();
+                    if job.cancelled.load(AtomicOrdering::SeqCst) {
+                        continue;
+                    }
+                    drop(jobs);
+                    let message: Vec<&str> = job.message.iter().map(String::as_str).collect();
+                    if let Err(_e) = job.emitter.send(&job.rooms, &message) {
+                        #[cfg(feature = "logging")]
+                        log::error!("dropped scheduled packet: {}", _e);
+                    }
+                    jobs = self.jobs.lock().unwrap();
+                    if let Some(next) = job.repeat.as_ref().and_then(|r| r.next(job.due)) {
+                        job.due = next;
+                        jobs.push(Reverse(job));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Queues an emit of `message` to `rooms` at `due`, repeating it if `repeat`
+/// is set.
+pub(crate) fn schedule(
+    due: Instant,
+    emitter: Emitter,
+    rooms: BTreeSet<String>,
+    message: Vec<String>,
+    repeat: Option<Repeat>,
+) -> ScheduleHandle {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let scheduler = Scheduler::global();
+    scheduler.jobs.lock().unwrap().push(Reverse(Job {
+        due,
+        seq: SEQ.fetch_add(1, AtomicOrdering::Relaxed),
+        emitter,
+        rooms,
+        message,
+        repeat,
+        cancelled: cancelled.clone(),
+    }));
+    scheduler.wake.notify_one();
+    ScheduleHandle { cancelled }
+}
+
+/// Keeps delayed emits in a Redis sorted set scored by due time, so they
+/// survive restarts. Every emitter with the store attached polls the set and
+/// publishes due packets; each one is claimed with `ZREM`, so only one
+/// emitter publishes it, and put back if the publish fails. Clones share
+/// their connections and poller.
+#[derive(Debug, Clone)]
+pub struct RedisSchedule {
+    connections: ConnectionPool,
+    key: String,
+    poll_interval: Duration,
+    poller: Arc<Mutex<Option<BridgeHandle>>>,
+}
+
+impl RedisSchedule {
+    pub fn new(client: redis::Client, key: &str) -> RedisSchedule {
+        RedisSchedule {
+            connections: ConnectionPool::new(client),
+            key: key.to_string(),
+            poll_interval: Duration::from_secs(1),
+            poller: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> RedisSchedule {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub(crate) fn add(&self, due: SystemTime, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let entry = encode_entry(channel, payload)?;
+        self.connections.run(|con| {
+            redis::cmd("ZADD")
+                .arg(&self.key)
+                .arg(millis(due))
+                .arg(&entry)
+                .query(con)
+        })
+    }
+
+    /// Publishes the entries that are due through `broker`, returning how many
+    /// this call claimed.
+    pub fn publish_due<B: Broker + ?Sized>(&self, broker: &B) -> RedisResult<usize> {
+        self.claim_due(|channel, payload| broker.publish(channel, &payload))
+    }
+
+    /// Claims the due entries and hands them to `publish`. An entry whose
+    /// publish fails is put back, and the error ends the call.
+    fn claim_due<F>(&self, publish: F) -> RedisResult<usize>
+    where
+        F: Fn(&str, Vec<u8>) -> RedisResult<()>,
+    {
+        // Scores stay as Redis formats them, to put entries back unchanged.
+        let due: Vec<(Vec<u8>, String)> = self.connections.run(|con| {
+            redis::cmd("ZRANGEBYSCORE")
+                .arg(&self.key)
+                .arg("-inf")
+                .arg(millis(SystemTime::now()))
+                .arg("WITHSCORES")
+                .arg("LIMIT")
+                .arg(0)
+                .arg(100)
+                .query(con)
+        })?;
+        let mut published = 0;
+        for (entry, score) in due {
+            let claimed: i64 = self
+                .connections
+                .run(|con| redis::cmd("ZREM").arg(&self.key).arg(&entry).query(con))?;
+            if claimed != 1 {
+                continue;
+            }
+            let (channel, payload) = decode_entry(&entry)?;
+            if let Err(e) = publish(&channel, payload) {
+                self.connections.run(|con| {
+                    redis::cmd("ZADD")
+                        .arg(&self.key)
+                        .arg(&score)
+                        .arg(&entry)
+                        .query::<()>(con)
+                })?;
+                return Err(e);
+            }
+            published += 1;
+        }
+        Ok(published)
+    }
+
+    /// Starts polling for due packets, publishing them like `emitter`'s own
+    /// packets, unless this store is already being polled.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn spawn_poller(&self, emitter: Emitter) {
+        let mut poller = self.poller.lock().unwrap();
+        if poller.is_some() {
+            return;
+        }
+        let store = self.clone();
+        *poller = Some(BridgeHandle::spawn(move |stop| {
+            while !stop.load(AtomicOrdering::SeqCst) {
+                thread::park_timeout(store.poll_interval);
+                if stop.load(AtomicOrdering::SeqCst) {
+                    break;
+                }
+                let published = store
+                    .claim_due(|channel, payload| emitter.publish_serialized(channel, payload));
+                if let Err(_e) = published {
+                    #[cfg(feature = "logging")]
+                    log::warn!("polling scheduled packets failed: {}", _e);
+                }
+            }
+        }));
+    }
+
+    /// Stops polling for due packets, waiting for a poll in progress. Entries
+    /// stay in Redis for other emitters, or until polling again.
+    pub fn stop_polling(&self) {
+        let poller = self.poller.lock().unwrap().take();
+        if let Some(poller) = poller {
+            poller.stop();
+        }
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Entries are a msgpack array of a unique id, the channel and the payload;
+/// the id keeps identical packets scheduled for the same time apart.
+fn encode_entry(channel: &str, payload: &[u8]) -> RedisResult<Vec<u8>> {
+    let id = format!(
+        "{}-{}-{}",
+        std::process::id(),
+        SEQ.fetch_add(1, AtomicOrdering::Relaxed),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    );
+    let mut entry = Vec::with_capacity(payload.len() + channel.len() + 32);
+    rmp::encode::write_array_len(&mut entry, 3).map_err(entry_error)?;
+    rmp::encode::write_str(&mut entry, &id).map_err(entry_error)?;
+    rmp::encode::write_str(&mut entry, channel).map_err(entry_error)?;
+    rmp::encode::write_bin(&mut entry, payload).map_err(entry_error)?;
+    Ok(entry)
+}
+
+fn decode_entry(entry: &[u8]) -> RedisResult<(String, Vec<u8>)> {
+    let mut rd = entry;
+    rmp::decode::read_array_len(&mut rd).map_err(entry_error)?;
+    let id_len = rmp::decode::read_str_len(&mut rd).map_err(entry_error)?;
+    take(&mut rd, id_len)?;
+    let channel_len = rmp::decode::read_str_len(&mut rd).map_err(entry_error)?;
+    let channel = take(&mut rd, channel_len)?;
+    let channel = String::from_utf8(channel.to_vec()).map_err(entry_error)?;
+    let payload_len = rmp::decode::read_bin_len(&mut rd).map_err(entry_error)?;
+    let payload = take(&mut rd, payload_len)?.to_vec();
+    Ok((channel, payload))
+}
+
+fn take<'a>(rd: &mut &'a [u8], len: u32) -> RedisResult<&'a [u8]> {
+    let len = len as usize;
+    if rd.len() < len {
+        return Err(entry_error("truncated entry"));
+    }
+    let (head, tail) = rd.split_at(len);
+    *rd = tail;
+    Ok(head)
+}
+
+fn entry_error<E: std::fmt::Display>(e: E) -> RedisError {
+    RedisError::from((
+        ErrorKind::TypeError,
+        "invalid schedule entry",
+        e.to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_entry, encode_entry};
+    use crate::{Broker, Emitter, InMemoryBroker, Opts, Packet, RedisSchedule};
+    use redis::{ErrorKind, RedisError, RedisResult};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Answers each command with the next of `replies`, sending the
+    /// arguments of every command it reads.
+    fn scripted_redis(replies: Vec<Vec<u8>>) -> (redis::Client, mpsc::Receiver<Vec<Vec<u8>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            for reply in replies {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let count: usize = line.trim()[1..].parse().unwrap();
+                let mut args = Vec::new();
+                for _ in 0..count {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let mut arg = vec![0; line.trim()[1..].parse::<usize>().unwrap() + 2];
+                    reader.read_exact(&mut arg).unwrap();
+                    arg.truncate(arg.len() - 2);
+                    args.push(arg);
+                }
+                tx.send(args).unwrap();
+                stream.write_all(&reply).unwrap();
+            }
+        });
+        let client = redis::Client::open(format!("redis://127.0.0.1:{}/", port)).unwrap();
+        (client, rx)
+    }
+
+    /// A due entry as `ZRANGEBYSCORE ... WITHSCORES` returns it.
+    fn due(entry: &[u8]) -> Vec<u8> {
+        let mut reply = format!("*2\r\n${}\r\n", entry.len()).into_bytes();
+        reply.extend_from_slice(entry);
+        reply.extend_from_slice(b"\r\n$13\r\n1700000000000\r\n");
+        reply
+    }
+
+    struct FailingBroker;
+
+    impl Broker for FailingBroker {
+        fn publish(&self, _channel: &str, _payload: &[u8]) -> RedisResult<()> {
+            Err(RedisError::from((ErrorKind::IoError, "broker down")))
+        }
+    }
+
+    #[test]
+    fn delayed_emits() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+        io.clone()
+            .to("room")
+            .emit_after(Duration::from_millis(60), vec!["second"])
+            .emit_at(Instant::now() + Duration::from_millis(20), vec!["first"])
+            .emit(vec!["now"]);
+
+        assert_eq!(vec!["now"], broker.events());
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(vec!["now", "first", "second"], broker.events());
+        assert_eq!(&["room"], broker.published()[2].opts.rooms());
+        assert!(broker.published()[1].opts.rooms().is_empty());
+    }
+
+    #[test]
+    fn recurring_emits() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+        let handle = io
+            .clone()
+            .to("room")
+            .emit_every(Duration::from_millis(20), vec!["tick"]);
+
+        thread::sleep(Duration::from_millis(110));
+        handle.cancel();
+        let ticks = broker.events().len();
+        assert!(ticks >= 3, "only {} ticks", ticks);
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(ticks, broker.events().len());
+        assert!(handle.is_cancelled());
+        assert_eq!(&["room"], broker.published()[0].opts.rooms());
+    }
+
+    #[cfg(feature = "cron")]
+    #[test]
+    fn cron_expressions() {
+        let io = Emitter::with_broker(InMemoryBroker::new());
+        assert!(io.emit_cron("not a schedule", vec!["tick"]).is_err());
+        io.emit_cron("0 0 9 * * Mon-Fri", vec!["tick"])
+            .unwrap()
+            .cancel();
+    }
+
+    #[test]
+    fn stops_polling() {
+        let client = redis::Client::open("redis://127.0.0.1:1/").unwrap();
+        let store = RedisSchedule::new(client, "scheduled").poll_interval(Duration::from_secs(60));
+        let io = Emitter::with_broker(InMemoryBroker::new())
+            .schedule_store(store.clone())
+            .schedule_store(store.clone());
+        // An unreachable store drops the packet instead of panicking.
+        io.emit_after(Duration::from_secs(1), vec!["lost"]);
+
+        let started = Instant::now();
+        store.stop_polling();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(store.poller.lock().unwrap().is_none());
+        store.stop_polling();
+    }
+
+    #[test]
+    fn puts_back_failed_publishes() {
+        let entry = encode_entry("socket.io#/#", b"\x93\x01").unwrap();
+        let (client, commands) =
+            scripted_redis(vec![due(&entry), b":1\r\n".to_vec(), b":1\r\n".to_vec()]);
+        let store = RedisSchedule::new(client, "scheduled");
+
+        assert!(store.publish_due(&FailingBroker).is_err());
+        let commands: Vec<_> = commands.iter().take(3).collect();
+        assert_eq!(b"ZRANGEBYSCORE".to_vec(), commands[0][0]);
+        assert_eq!(
+            vec![b"ZREM".to_vec(), b"scheduled".to_vec(), entry.clone()],
+            commands[1]
+        );
+        assert_eq!(
+            vec![
+                b"ZADD".to_vec(),
+                b"scheduled".to_vec(),
+                b"1700000000000".to_vec(),
+                entry
+            ],
+            commands[2]
+        );
+    }
+
+    #[test]
+    fn reports_scheduled_packets() {
+        let broker = InMemoryBroker::new();
+        let receipts = Arc::new(Mutex::new(Vec::new()));
+        let recorded = receipts.clone();
+        let io = Emitter::with_broker(broker.clone()).on_publish(move |receipt| {
+            recorded
+                .lock()
+                .unwrap()
+                .push(receipt.unwrap().event.clone());
+        });
+        let scheduled = crate::encode("emitter", &Packet::new("/", vec!["tick"]), &Opts::default());
+        let entry = encode_entry("socket.io#/#room#", &scheduled).unwrap();
+        let (client, _commands) = scripted_redis(vec![due(&entry), b":1\r\n".to_vec()]);
+        let store = RedisSchedule::new(client, "scheduled");
+
+        let published = store.claim_due(|channel, payload| io.publish_serialized(channel, payload));
+        assert_eq!(1, published.unwrap());
+        assert_eq!(vec![Some("tick".to_string())], *receipts.lock().unwrap());
+        assert_eq!(vec!["tick"], broker.events());
+    }
+
+    #[test]
+    fn entries() {
+        let a = encode_entry("socket.io#/#", b"\x93\x01").unwrap();
+        let b = encode_entry("socket.io#/#", b"\x93\x01").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(
+            ("socket.io#/#".to_string(), b"\x93\x01".to_vec()),
+            decode_entry(&a).unwrap()
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{Emitter, IntoRooms};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// An emit handled by `EmitService`: `message` goes to the emitter's rooms
+/// plus `rooms`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmitRequest {
+    pub rooms: Vec<String>,
+    pub message: Vec<String>,
+}
+
+impl EmitRequest {
+    pub fn new(message: Vec<&str>) -> EmitRequest {
+        EmitRequest {
+            rooms: Vec::new(),
+            message: message.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    pub fn to<R: IntoRooms>(mut self, rooms: R) -> EmitRequest {
+        self.rooms.extend(rooms.into_rooms());
+        self
+    }
+}
+
+/// The emit pipeline as a tower `Service`, so timeouts, retries, rate
+/// limits and load shedding from the tower ecosystem can wrap publishing.
+/// Publishes run on the Tokio blocking pool and the service is always ready.
+#[derive(Debug, Clone)]
+pub struct EmitService {
+    emitter: Emitter,
+}
+
+impl EmitService {
+    pub fn new(emitter: Emitter) -> EmitService {
+        EmitService { emitter }
+    }
+}
+
+impl Service<EmitRequest> for EmitService {
+    type Response = ();
+    type Error = RedisError;
+    type Future = Pin<Box<dyn Future<Output = RedisResult<()>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<RedisResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: EmitRequest) -> Self::Future {
+        let emitter = self.emitter.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let mut rooms = emitter.rooms.clone();
+                rooms.extend(request.rooms);
+                let message: Vec<&str> = request.message.iter().map(String::as_str).collect();
+                emitter.send(&rooms, &message)
+            })
+            .await
+            .unwrap_or_else(|e| {
+                Err(RedisError::from((
+                    ErrorKind::ClientError,
+                    "emit task failed",
+                    e.to_string(),
+                )))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Broker, InMemoryBroker};
+    use std::time::Duration;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    struct SlowBroker;
+
+    impl Broker for SlowBroker {
+        fn publish(&self, _: &str, _: &[u8]) -> RedisResult<()> {
+            std::thread::sleep(Duration::from_millis(200));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn service_with_middleware() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let broker = InMemoryBroker::new();
+        let service = EmitService::new(Emitter::with_broker(broker.clone()).to("all"));
+        let request = EmitRequest::new(vec!["message", "hi"]).to("news");
+        runtime.block_on(service.oneshot(request)).unwrap();
+        let published = broker.published();
+        assert_eq!(1, published.len());
+        assert_eq!(vec!["all", "news"], published[0].opts.rooms());
+        assert_eq!(vec!["message", "hi"], published[0].packet.data());
+
+        let service = ServiceBuilder::new()
+            .timeout(Duration::from_millis(20))
+            .service(EmitService::new(Emitter::with_broker(SlowBroker)));
+        let result = runtime.block_on(service.oneshot(EmitRequest::new(vec!["message"])));
+        assert!(result.unwrap_err().is::<tower::timeout::error::Elapsed>());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{Broker, Emitter, IntoEmitter};
+use redis::RedisResult;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How `ShardedEmitter` picks the connection for a publish.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShardBy {
+    /// Each publish takes the next connection.
+    RoundRobin,
+    /// Publishes are spread by channel, so packets to a room keep their
+    /// order.
+    Room,
+}
+
+/// Publishes over `connections` persistent Redis connections, for producers
+/// that saturate a single connection. Turned into an emitter with
+/// `Emitter::new`; clones of that emitter share the connections.
+pub struct ShardedEmitter {
+    client: redis::Client,
+    shards: Vec<Mutex<Option<redis::Connection>>>,
+    next: AtomicUsize,
+    by: ShardBy,
+    command_timeout: Option<Duration>,
+}
+
+impl ShardedEmitter {
+    pub fn new(client: redis::Client, connections: usize) -> ShardedEmitter {
+        ShardedEmitter {
+            client,
+            shards: (0..connections.max(1)).map(|_| Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+            by: ShardBy::RoundRobin,
+            command_timeout: None,
+        }
+    }
+
+    /// Picks connections with `by`, round-robin by default.
+    pub fn by(mut self, by: ShardBy) -> ShardedEmitter {
+        self.by = by;
+        self
+    }
+
+    pub fn command_timeout(mut self, timeout: Duration) -> ShardedEmitter {
+        self.command_timeout = Some(timeout);
+        self
+    }
+
+    fn shard(&self, channel: &str) -> usize {
+        match self.by {
+            ShardBy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % self.shards.len(),
+            ShardBy::Room => {
+                let mut hasher = DefaultHasher::new();
+                channel.hash(&mut hasher);
+                hasher.finish() as usize % self.shards.len()
+            }
+        }
+    }
+
+    /// Runs `query` on the connection of `shard`, connecting first if needed.
+    /// A connection that fails is dropped and reopened by the next query.
+    fn with_connection<T, F>(&self, shard: usize, query: F) -> RedisResult<T>
+    where
+        F: FnOnce(&mut redis::Connection) -> RedisResult<T>,
+    {
+        let mut slot = self.shards[shard].lock().unwrap();
+        if slot.is_none() {
+            let con = self.client.get_connection()?;
+            con.set_read_timeout(self.command_timeout)?;
+            con.set_write_timeout(self.command_timeout)?;
+            *slot = Some(con);
+        }
+        let result = query(slot.as_mut().unwrap());
+        if let Err(e) = &result {
+            if e.is_io_error() || e.is_connection_dropped() || e.is_timeout() {
+                *slot = None;
+            }
+        }
+        result
+    }
+}
+
+impl Broker for ShardedEmitter {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        self.with_connection(self.shard(channel), |con| {
+            redis::cmd("PUBLISH").arg(channel).arg(payload).query(con)
+        })
+    }
+
+    /// Pipelines the messages of each connection, in their original order.
+    fn publish_batch(&self, messages: &[(&str, &[u8])]) -> Vec<RedisResult<()>> {
+        let mut batches: Vec<Vec<usize>> = vec![Vec::new(); self.shards.len()];
+        match self.by {
+            ShardBy::RoundRobin => batches[self.shard("")] = (0..messages.len()).collect(),
+            ShardBy::Room => {
+                for (i, (channel, _)) in messages.iter().enumerate() {
+                    batches[self.shard(channel)].push(i);
+                }
+            }
+        }
+        let mut results: Vec<RedisResult<()>> = messages.iter().map(|_| Ok(())).collect();
+        for (shard, batch) in batches.iter().enumerate() {
+            if batch.is_empty() {
+                continue;
+            }
+            let mut pipe = redis::pipe();
+            for &i in batch {
+                let (channel, payload) = &messages[i];
+                pipe.cmd("PUBLISH").arg(channel).arg(payload).ignore();
+            }
+            if let Err(e) = self.with_connection(shard, |con| pipe.query::<()>(con)) {
+                for &i in batch {
+                    results[i] = Err(redis::RedisError::from((
+                        e.kind(),
+                        "pipelined publish failed",
+                        e.to_string(),
+                    )));
+                }
+            }
+        }
+        results
+    }
+}
+
+impl IntoEmitter for ShardedEmitter {
+    fn into_emitter(self) -> Emitter {
+        Emitter::with_broker(self)
+    }
+}
+
+impl fmt::Debug for ShardedEmitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedEmitter")
+            .field("client", &self.client)
+            .field("connections", &self.shards.len())
+            .field("by", &self.by)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    type Received = Arc<Mutex<Vec<(usize, String)>>>;
+
+    /// Answers every `PUBLISH` with `:0` and records the connection number
+    /// and channel of each.
+    fn fake_redis() -> (redis::Client, Received) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        thread::spawn(move || {
+            for (n, stream) in listener.incoming().enumerate() {
+                let log = log.clone();
+                thread::spawn(move || {
+                    let mut stream = stream.unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                        let mut args = Vec::new();
+                        for _ in 0..3 {
+                            line.clear();
+                            reader.read_line(&mut line).unwrap();
+                            let len: usize = line.trim()[1..].parse().unwrap();
+                            let mut arg = vec![0; len + 2];
+                            reader.read_exact(&mut arg).unwrap();
+                            args.push(String::from_utf8_lossy(&arg[..len]).into_owned());
+                        }
+                        log.lock().unwrap().push((n, args.swap_remove(1)));
+                        stream.write_all(b":0\r\n").unwrap();
+                        line.clear();
+                    }
+                });
+            }
+        });
+        let client = redis::Client::open(format!("redis://127.0.0.1:{}/", port)).unwrap();
+        (client, received)
+    }
+
+    #[test]
+    fn spreads_publishes() {
+        let (client, received) = fake_redis();
+        let io = Emitter::new(ShardedEmitter::new(client.clone(), 2));
+        for _ in 0..4 {
+            io.clone().to("a").emit(vec!["event"]);
+        }
+        let connections: Vec<usize> = received.lock().unwrap().iter().map(|r| r.0).collect();
+        assert_eq!(vec![0, 1, 0, 1], connections);
+
+        received.lock().unwrap().clear();
+        let io = Emitter::new(ShardedEmitter::new(client, 4).by(ShardBy::Room));
+        let rooms = ["a", "b", "c", "d", "e", "f"];
+        for room in rooms.iter().chain(rooms.iter()) {
+            io.clone().to(*room).emit(vec!["event"]);
+        }
+        let results = io.emit_batch(rooms.iter().map(|room| ("event", vec![], *room)).collect());
+        assert!(results.iter().all(Result::is_ok));
+
+        let received = received.lock().unwrap();
+        assert_eq!(18, received.len());
+        for (connection, channel) in received.iter() {
+            let same_room = received.iter().filter(|r| &r.1 == channel);
+            assert!(same_room.map(|r| r.0).all(|c| c == *connection));
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use hickory_resolver::proto::rr::rdata::SRV;
+use hickory_resolver::Resolver;
+use redis::{ConnectionAddr, ConnectionInfo, ErrorKind, RedisError, RedisResult};
+use std::cmp::Reverse;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Resolves the Redis endpoint from a DNS SRV record and re-resolves it once
+/// the record expires or `refresh` elapses, whichever comes first.
+#[derive(Debug, Clone)]
+pub struct SrvDiscovery {
+    name: String,
+    refresh: Duration,
+    resolved: Arc<Mutex<Option<(ConnectionAddr, Instant)>>>,
+}
+
+impl SrvDiscovery {
+    pub fn new(name: &str) -> SrvDiscovery {
+        SrvDiscovery {
+            name: name.to_string(),
+            refresh: Duration::from_secs(30),
+            resolved: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn refresh(mut self, refresh: Duration) -> SrvDiscovery {
+        self.refresh = refresh;
+        self
+    }
+
+    pub(crate) fn client(&self, base: &ConnectionInfo) -> RedisResult<redis::Client> {
+        let mut resolved = self.resolved.lock().unwrap();
+        let addr = match &*resolved {
+            Some((addr, expires)) if Instant::now() < *expires => addr.clone(),
+            _ => {
+                let (addr, expires) = self.resolve()?;
+                *resolved = Some((addr.clone(), expires));
+                addr
+            }
+        };
+        redis::Client::open(ConnectionInfo {
+            addr,
+            redis: base.redis.clone(),
+        })
+    }
+
+    fn resolve(&self) -> RedisResult<(ConnectionAddr, Instant)> {
+        let lookup_err = |e: &dyn std::fmt::Display| {
+            RedisError::from((
+                ErrorKind::IoError,
+                "SRV lookup failed",
+                format!("{}: {}", self.name, e),
+            ))
+        };
+        let resolver = Resolver::from_system_conf().map_err(|e| lookup_err(&e))?;
+        let lookup = resolver
+            .srv_lookup(self.name.as_str())
+            .map_err(|e| lookup_err(&e))?;
+        let expires = lookup
+            .as_lookup()
+            .valid_until()
+            .min(Instant::now() + self.refresh);
+        let addr = endpoints(lookup.iter()).into_iter().next().ok_or_else(|| {
+            RedisError::from((
+                ErrorKind::IoError,
+                "SRV lookup returned no records",
+                self.name.clone(),
+            ))
+        })?;
+        Ok((addr, expires))
+    }
+}
+
+/// The endpoints of `records`, lowest priority first and, within a
+/// priority, highest weight first.
+fn endpoints<'a, I: Iterator<Item = &'a SRV>>(records: I) -> Vec<ConnectionAddr> {
+    let mut records: Vec<&SRV> = records.collect();
+    records.sort_by_key(|srv| (srv.priority(), Reverse(srv.weight())));
+    records
+        .into_iter()
+        .map(|srv| {
+            let host = srv.target().to_utf8();
+            ConnectionAddr::Tcp(host.trim_end_matches('.').to_string(), srv.port())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::endpoints;
+    use hickory_resolver::proto::rr::rdata::SRV;
+    use hickory_resolver::Name;
+    use redis::ConnectionAddr;
+
+    #[test]
+    fn orders_endpoints() {
+        let srv = |priority, weight, host: &str| {
+            SRV::new(priority, weight, 6379, Name::from_ascii(host).unwrap())
+        };
+        let records = [
+            srv(20, 100, "backup.example.com."),
+            srv(10, 10, "light.example.com."),
+            srv(10, 90, "heavy.example.com."),
+        ];
+        let hosts: Vec<ConnectionAddr> = ["heavy", "light", "backup"]
+            .iter()
+            .map(|host| ConnectionAddr::Tcp(format!("{}.example.com", host), 6379))
+            .collect();
+        assert_eq!(hosts, endpoints(records.iter()));
+    }
+}
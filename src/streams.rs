@@ -0,0 +1,170 @@
+use crate::{decode, Packet};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use redis::{ErrorKind, RedisError, RedisResult};
+use rmp_serde::Serializer;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// `BROADCAST` in the socket.io cluster adapter message types.
+const BROADCAST: i32 = 3;
+
+/// Appends packets to the stream read by @socket.io/redis-streams-adapter
+/// (with `XADD`) instead of publishing them on pub/sub channels.
+///
+/// The stream is trimmed with `MAXLEN ~` as the adapter does. Entry ids are
+/// left to Redis (`*`): they are the offsets each server reads the stream
+/// from, and the servers keep track of them, so the emitter keeps none.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RedisStreams {
+    stream: String,
+    max_len: usize,
+}
+
+impl Default for RedisStreams {
+    fn default() -> RedisStreams {
+        RedisStreams {
+            stream: "socket.io".to_string(),
+            max_len: 10_000,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BroadcastMessage<'a> {
+    packet: &'a Packet,
+    opts: BroadcastOpts<'a>,
+}
+
+#[derive(Serialize)]
+struct BroadcastOpts<'a> {
+    rooms: &'a [String],
+    except: &'a [String],
+    flags: &'a HashMap<String, bool>,
+}
+
+impl RedisStreams {
+    pub fn new(stream: &str) -> RedisStreams {
+        RedisStreams {
+            stream: stream.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Approximate maximum length the stream is trimmed to, matching the
+    /// adapter's `maxLen` option.
+    pub fn max_len(mut self, max_len: usize) -> RedisStreams {
+        self.max_len = max_len;
+        self
+    }
+
+    pub(crate) fn command(&self, payload: &[u8]) -> RedisResult<redis::Cmd> {
+        let (uid, packet, opts) = decode(payload).map_err(|e| {
+            RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+        })?;
+        let message = BroadcastMessage {
+            packet: &packet,
+            opts: BroadcastOpts {
+                rooms: opts.rooms(),
+                except: &[],
+                flags: opts.flags(),
+            },
+        };
+        let mut data = Vec::new();
+        message
+            .serialize(&mut Serializer::new(&mut data).with_struct_map())
+            .map_err(|e| {
+                RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+            })?;
+
+        // The adapter reads data that doesn't start with `{` as base64 msgpack.
+        let mut cmd = redis::cmd("XADD");
+        cmd.arg(&self.stream)
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(self.max_len)
+            .arg("*")
+            .arg("uid")
+            .arg(uid)
+            .arg("nsp")
+            .arg(packet.nsp())
+            .arg("type")
+            .arg(BROADCAST.to_string())
+            .arg("data")
+            .arg(BASE64.encode(data));
+        Ok(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RedisStreams, BASE64};
+    use crate::{encode, Opts, Packet};
+    use base64::Engine;
+    use rmpv::Value;
+
+    #[test]
+    fn xadd_layout() {
+        let packet = Packet {
+            _type: 2,
+            data: vec!["test".to_string()],
+            nsp: "/chat".to_string(),
+        };
+        let opts = Opts {
+            rooms: vec!["room1".to_string()],
+            ..Default::default()
+        };
+        let payload = encode("emitter", &packet, &opts);
+
+        let cmd = RedisStreams::default().command(&payload).unwrap();
+
+        let packed = String::from_utf8_lossy(&cmd.get_packed_command()).into_owned();
+        let args: Vec<_> = packed.split("\r\n").skip(2).step_by(2).collect();
+        assert_eq!(
+            &[
+                "XADD",
+                "socket.io",
+                "MAXLEN",
+                "~",
+                "10000",
+                "*",
+                "uid",
+                "emitter",
+                "nsp",
+                "/chat",
+                "type",
+                "3",
+                "data"
+            ],
+            &args[..13]
+        );
+        assert_eq!(14, args.len());
+
+        let data = BASE64.decode(args[13]).unwrap();
+        let data = rmpv::decode::read_value(&mut &data[..]).unwrap();
+        let map = |entries: Vec<(&str, Value)>| {
+            Value::Map(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+        };
+        assert_eq!(
+            map(vec![
+                (
+                    "packet",
+                    map(vec![
+                        ("type", 2.into()),
+                        ("data", Value::Array(vec!["test".into()])),
+                        ("nsp", "/chat".into()),
+                    ])
+                ),
+                (
+                    "opts",
+                    map(vec![
+                        ("rooms", Value::Array(vec!["room1".into()])),
+                        ("except", Value::Array(vec![])),
+                        ("flags", Value::Map(vec![])),
+                    ])
+                ),
+            ]),
+            data
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{Emitter, IntoRooms, Opts, Packet};
+
+/// How `TenantEmitter` keeps a tenant's packets apart from everyone else's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TenantScope {
+    /// Publish under `prefix:tenant`, for socket.io servers that subscribe
+    /// per tenant.
+    KeyPrefix,
+    /// Prefix every room with `tenant:` and send packets without rooms to
+    /// the `tenant` room instead of every socket.
+    RoomPrefix,
+}
+
+/// An emitter scoped to one tenant, returned by `Emitter::tenant`. Every
+/// packet emitted through it, including through `of` and `to`, stays within
+/// the tenant.
+#[derive(Debug, Clone)]
+pub struct TenantEmitter {
+    tenant: String,
+    emitter: Emitter,
+}
+
+impl TenantEmitter {
+    pub(crate) fn new(emitter: &Emitter, tenant: &str, scope: TenantScope) -> TenantEmitter {
+        let mut emitter = emitter.clone();
+        // A fresh namespace cache, so `nsp` hands out scoped handles.
+        emitter.namespaces = Default::default();
+        let emitter = match scope {
+            TenantScope::KeyPrefix => {
+                emitter.prefix = format!("{}:{}", emitter.prefix, tenant).into();
+                emitter
+            }
+            TenantScope::RoomPrefix => {
+                let tenant = tenant.to_string();
+                emitter.middleware(move |_: &mut Packet, opts: &mut Opts| {
+                    let prefix = format!("{}:", tenant);
+                    let rooms = opts.rooms_mut();
+                    if rooms.is_empty() {
+                        rooms.push(tenant.clone());
+                    }
+                    for room in rooms.iter_mut() {
+                        if *room != tenant && !room.starts_with(&prefix) {
+                            *room = format!("{}{}", prefix, room);
+                        }
+                    }
+                    rooms.sort();
+                    rooms.dedup();
+                    Ok(())
+                })
+            }
+        };
+        TenantEmitter {
+            tenant: tenant.to_string(),
+            emitter,
+        }
+    }
+
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// A scoped emitter, for targeting rooms or setting flags.
+    pub fn emitter(&self) -> Emitter {
+        self.emitter.clone()
+    }
+
+    pub fn of(&self, nsp: &str) -> Emitter {
+        self.emitter().of(nsp)
+    }
+
+    pub fn to<R: IntoRooms>(&self, rooms: R) -> Emitter {
+        self.emitter().to(rooms)
+    }
+
+    pub fn emit(&self, message: Vec<&str>) {
+        self.emitter().emit(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TenantScope;
+    use crate::{Emitter, InMemoryBroker};
+
+    #[test]
+    fn tenant_scopes() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+
+        let acme = io.tenant("acme");
+        acme.emit(vec!["all"]);
+        acme.of("/chat").to("r1").emit(vec!["room"]);
+        let acme = io.tenant_with("acme", TenantScope::RoomPrefix);
+        acme.emit(vec!["all"]);
+        acme.to(vec!["r1", "acme:r2"]).emit(vec!["rooms"]);
+        acme.emitter().nsp("/chat").to("r1").emit(vec!["handle"]);
+        io.to("r1").emit(vec!["unscoped"]);
+
+        let published = broker.published();
+        let channels: Vec<_> = published.iter().map(|p| p.channel.as_str()).collect();
+        assert_eq!(
+            vec![
+                "socket.io:acme#/#",
+                "socket.io:acme#/chat#r1#",
+                "socket.io#/#acme#",
+                "socket.io#/#",
+                "socket.io#/chat#acme:r1#",
+                "socket.io#/#r1#",
+            ],
+            channels
+        );
+        assert_eq!(&["acme"], published[2].opts.rooms());
+        assert_eq!(&["acme:r1", "acme:r2"], published[3].opts.rooms());
+    }
+}
@@ -0,0 +1,182 @@
+//! Helpers for testing code that emits, enabled with the `test-util` feature.
+
+use crate::{Emitter, InMemoryBroker, Published};
+use serde_json::Value;
+
+/// Hands out emitters that capture their packets, and asserts on what was
+/// emitted.
+///
+/// ```ignore
+/// let capture = CapturingEmitter::new();
+/// notify_order_shipped(capture.emitter(), order);
+/// capture
+///     .assert_emitted("order:shipped")
+///     .to_room("user:42")
+///     .with_payload(json!({ "id": 7 }));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CapturingEmitter {
+    broker: InMemoryBroker,
+}
+
+impl CapturingEmitter {
+    pub fn new() -> CapturingEmitter {
+        CapturingEmitter::default()
+    }
+
+    /// An emitter whose packets are captured by this instance.
+    pub fn emitter(&self) -> Emitter {
+        Emitter::with_broker(self.broker.clone())
+    }
+
+    pub fn published(&self) -> Vec<Published> {
+        self.broker.published()
+    }
+
+    pub fn clear(&self) {
+        self.broker.clear();
+    }
+
+    /// Starts an assertion on the packets emitted as `event`, panicking if
+    /// there are none.
+    pub fn assert_emitted(&self, event: &str) -> EmittedAssertion {
+        let candidates: Vec<_> = self
+            .published()
+            .into_iter()
+            .filter(|p| p.event() == Some(event))
+            .collect();
+        assert!(
+            !candidates.is_empty(),
+            "expected `{}` to be emitted, got {:?}",
+            event,
+            self.broker.events()
+        );
+        EmittedAssertion {
+            description: format!("`{}`", event),
+            candidates,
+        }
+    }
+
+    pub fn assert_not_emitted(&self, event: &str) {
+        self.broker.assert_not_emitted(event);
+    }
+}
+
+/// Narrows down the packets matched by `CapturingEmitter::assert_emitted`;
+/// every step panics if no packet is left.
+#[derive(Debug, Clone)]
+pub struct EmittedAssertion {
+    description: String,
+    candidates: Vec<Published>,
+}
+
+impl EmittedAssertion {
+    pub fn to_room(self, room: &str) -> EmittedAssertion {
+        self.filter(&format!("to room `{}`", room), |p| {
+            p.opts.rooms().iter().any(|r| r == room)
+        })
+    }
+
+    pub fn in_nsp(self, nsp: &str) -> EmittedAssertion {
+        self.filter(&format!("in namespace `{}`", nsp), |p| {
+            p.packet.nsp() == nsp
+        })
+    }
+
+    pub fn with_flag(self, flag: &str) -> EmittedAssertion {
+        self.filter(&format!("with flag `{}`", flag), |p| {
+            p.opts.flags().get(flag) == Some(&true)
+        })
+    }
+
+    /// Matches the arguments after the event name. Arguments are compared as
+    /// JSON when they parse as JSON; several arguments compare as an array.
+    pub fn with_payload(self, expected: Value) -> EmittedAssertion {
+        self.filter(&format!("with payload {}", expected), |p| {
+            payload(p) == expected
+        })
+    }
+
+    /// The matching packets.
+    pub fn packets(&self) -> &[Published] {
+        &self.candidates
+    }
+
+    fn filter<F>(self, step: &str, keep: F) -> EmittedAssertion
+    where
+        F: Fn(&Published) -> bool,
+    {
+        let description = format!("{} {}", self.description, step);
+        let (candidates, rejected): (Vec<_>, Vec<_>) =
+            self.candidates.into_iter().partition(|p| keep(p));
+        assert!(
+            !candidates.is_empty(),
+            "expected {} to be emitted, got {:?}",
+            description,
+            rejected.iter().map(describe).collect::<Vec<_>>()
+        );
+        EmittedAssertion {
+            description,
+            candidates,
+        }
+    }
+}
+
+fn payload(published: &Published) -> Value {
+    let args: Vec<Value> = published.packet.data()[1..]
+        .iter()
+        .map(|arg| serde_json::from_str(arg).unwrap_or_else(|_| Value::String(arg.clone())))
+        .collect();
+    match args.len() {
+        1 => args.into_iter().next().unwrap(),
+        _ => Value::Array(args),
+    }
+}
+
+fn describe(published: &Published) -> String {
+    format!(
+        "{} rooms={:?} flags={:?} payload={}",
+        published.packet.nsp(),
+        published.opts.rooms(),
+        published.opts.flags(),
+        payload(published)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CapturingEmitter;
+    use serde_json::json;
+
+    #[test]
+    fn assertions() {
+        let capture = CapturingEmitter::new();
+        capture
+            .emitter()
+            .of("/orders")
+            .to("user:42")
+            .volatile()
+            .emit(vec!["order:shipped", r#"{"id": 7}"#])
+            .emit(vec!["order:shipped", "plain", "1"]);
+
+        capture
+            .assert_emitted("order:shipped")
+            .in_nsp("/orders")
+            .to_room("user:42")
+            .with_flag("volatile")
+            .with_payload(json!({ "id": 7 }));
+        let second = capture
+            .assert_emitted("order:shipped")
+            .with_payload(json!(["plain", 1]));
+        assert!(second.packets()[0].opts.rooms().is_empty());
+        capture.assert_not_emitted("order:cancelled");
+    }
+
+    #[test]
+    #[should_panic(expected = "to room `user:1`")]
+    fn reports_mismatch() {
+        let capture = CapturingEmitter::new();
+        capture.emitter().to("user:42").emit(vec!["order:shipped"]);
+        capture.assert_emitted("order:shipped").to_room("user:1");
+    }
+}
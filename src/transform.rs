@@ -0,0 +1,63 @@
+use crate::{Opts, Packet};
+
+/// Rewrites a packet into any number of packets inside `emit`, e.g. to rename
+/// events, add flags or fan one packet out per locale. Transformers run in
+/// the order registered with `Emitter::transformer`, each on every packet
+/// the previous one returned, before the middleware. Returning no packets
+/// drops it.
+pub trait PacketTransformer: Send + Sync {
+    fn transform(&self, packet: Packet, opts: Opts) -> Vec<(Packet, Opts)>;
+}
+
+impl<F> PacketTransformer for F
+where
+    F: Fn(Packet, Opts) -> Vec<(Packet, Opts)> + Send + Sync,
+{
+    fn transform(&self, packet: Packet, opts: Opts) -> Vec<(Packet, Opts)> {
+        self(packet, opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Emitter, InMemoryBroker, Opts, Packet};
+
+    #[test]
+    fn transformer_chain() {
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .transformer(|mut packet: Packet, opts: Opts| {
+                if packet.data()[0] == "greet" {
+                    packet.data_mut()[0] = "greeting".to_string();
+                }
+                vec![(packet, opts)]
+            })
+            .transformer(|packet: Packet, opts: Opts| {
+                [("en", "hello"), ("fr", "bonjour")]
+                    .iter()
+                    .map(|(locale, text)| {
+                        let mut packet = packet.clone();
+                        let mut opts = opts.clone();
+                        packet.data_mut().push(text.to_string());
+                        *opts.rooms_mut() = vec![format!("locale:{}", locale)];
+                        opts.flags_mut().insert("localized".to_string(), true);
+                        (packet, opts)
+                    })
+                    .collect()
+            })
+            .emit(vec!["greet"]);
+
+        let published = broker.published();
+        assert_eq!(2, published.len());
+        assert_eq!("socket.io#/#locale:en#", published[0].channel);
+        assert_eq!(&["greeting", "hello"], published[0].packet.data());
+        assert_eq!(&["greeting", "bonjour"], published[1].packet.data());
+        assert_eq!(Some(&true), published[1].opts.flags().get("localized"));
+
+        let broker = InMemoryBroker::new();
+        Emitter::with_broker(broker.clone())
+            .transformer(|_: Packet, _: Opts| Vec::new())
+            .emit(vec!["dropped"]);
+        assert!(broker.published().is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{Broker, HttpRequest};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::time::Duration;
+
+/// Publishes through the Upstash Redis REST API instead of a TCP connection.
+#[derive(Debug, Clone)]
+pub struct Upstash {
+    url: String,
+    token: String,
+    timeout: Option<Duration>,
+    sharded: bool,
+    agent: ureq::Agent,
+}
+
+impl Upstash {
+    /// `url` is the database's REST URL (`UPSTASH_REDIS_REST_URL`) and `token`
+    /// its REST token (`UPSTASH_REDIS_REST_TOKEN`).
+    pub fn new(url: &str, token: &str) -> Upstash {
+        Upstash {
+            url: url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            timeout: None,
+            sharded: false,
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    pub fn from_env() -> RedisResult<Upstash> {
+        let var = |name: &'static str| {
+            std::env::var(name).map_err(|_| {
+                RedisError::from((
+                    ErrorKind::InvalidClientConfig,
+                    "missing Upstash configuration",
+                    name.to_string(),
+                ))
+            })
+        };
+        Ok(Upstash::new(
+            &var("UPSTASH_REDIS_REST_URL")?,
+            &var("UPSTASH_REDIS_REST_TOKEN")?,
+        ))
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Upstash {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Publishes with `SPUBLISH` for adapters using sharded pub/sub.
+    pub fn sharded(mut self) -> Upstash {
+        self.sharded = true;
+        self
+    }
+}
+
+impl Broker for Upstash {
+    fn publish(&self, channel: &str, msg: &[u8]) -> RedisResult<()> {
+        let HttpRequest {
+            method,
+            url,
+            headers,
+            body,
+        } = HttpRequest::upstash_publish(&self.url, &self.token, self.sharded, channel, msg);
+        let mut request = self.agent.request(method, &url);
+        for (name, value) in &headers {
+            request = request.set(name, value);
+        }
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        request.send_bytes(&body).map(|_| ()).map_err(|e| {
+            RedisError::from((
+                ErrorKind::IoError,
+                "Upstash REST publish failed",
+                e.to_string(),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Upstash;
+    use crate::{Broker, HttpRequest};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// The request line and headers, and the body.
+    type Received = (Vec<String>, Vec<u8>);
+
+    /// Answers one request like the REST API and sends what it received.
+    fn fake_upstash() -> (String, mpsc::Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push(line.trim_end().to_string());
+            }
+            let length = head
+                .iter()
+                .find_map(|line| {
+                    line.to_lowercase()
+                        .strip_prefix("content-length: ")?
+                        .parse()
+                        .ok()
+                })
+                .unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let reply = "{\"result\":1}";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                reply.len(),
+                reply
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+            tx.send((head, body)).unwrap();
+        });
+        (url, rx)
+    }
+
+    #[test]
+    fn publishes_over_rest() {
+        assert_eq!(
+            HttpRequest {
+                method: "POST",
+                url: "https://db.upstash.io/spublish/socket.io%23%2F%23room%201%23".to_string(),
+                headers: vec![("Authorization".to_string(), "Bearer secret".to_string())],
+                body: b"\x93\x01".to_vec(),
+            },
+            HttpRequest::upstash_publish(
+                "https://db.upstash.io",
+                "secret",
+                true,
+                "socket.io#/#room 1#",
+                b"\x93\x01"
+            )
+        );
+
+        let (url, received) = fake_upstash();
+        Upstash::new(&url, "secret")
+            .publish("socket.io#/#", b"\x93\x01")
+            .unwrap();
+        let (head, body) = received.recv().unwrap();
+        assert_eq!("POST /publish/socket.io%23%2F%23 HTTP/1.1", head[0]);
+        assert!(head.contains(&"Authorization: Bearer secret".to_string()));
+        assert_eq!(b"\x93\x01".to_vec(), body);
+    }
+}
@@ -0,0 +1,96 @@
+//! warp filters, enabled with the `warp` feature. `with_emitter` hands each
+//! request a clone of the emitter, with the namespace and rooms it was
+//! given as the route's defaults:
+//!
+//! ```no_run
+//! use socketio_rust_emitter::{warp::{emit, with_emitter}, Emitter};
+//! use warp::Filter;
+//!
+//! # async fn run() {
+//! let io = Emitter::new("redis://127.0.0.1/");
+//! let notify = warp::path!("rooms" / String / "notify")
+//!     .and(with_emitter(io.clone().of("/chat")))
+//!     .and_then(|room: String, io: Emitter| async move {
+//!         emit(io.to(room.as_str()), vec!["message", "hello"])
+//!             .await
+//!             .map(|()| warp::reply())
+//!     });
+//! warp::serve(notify).run(([127, 0, 0, 1], 3030)).await;
+//! # }
+//! ```
+
+use crate::Emitter;
+use redis::{ErrorKind, RedisError};
+use std::convert::Infallible;
+use std::sync::Arc;
+use warp::reject::{Reject, Rejection};
+use warp::Filter;
+
+/// Extracts a clone of `emitter` for every request.
+pub fn with_emitter(
+    emitter: Emitter,
+) -> impl Filter<Extract = (Emitter,), Error = Infallible> + Clone + Send + Sync + 'static {
+    let emitter = Arc::new(emitter);
+    warp::any().map(move || Emitter::clone(&emitter))
+}
+
+/// A failed emit, as a warp rejection.
+#[derive(Debug)]
+pub struct EmitRejection(pub RedisError);
+
+impl Reject for EmitRejection {}
+
+/// Emits `message` to `emitter`'s rooms on the Tokio blocking pool,
+/// rejecting the request with `EmitRejection` when publishing fails.
+pub async fn emit(emitter: Emitter, message: Vec<&str>) -> Result<(), Rejection> {
+    let message: Vec<String> = message.iter().map(|s| s.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        let message: Vec<&str> = message.iter().map(String::as_str).collect();
+        emitter.send(&emitter.rooms, &message)
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(RedisError::from((
+            ErrorKind::ClientError,
+            "emit task failed",
+            e.to_string(),
+        )))
+    })
+    .map_err(|e| warp::reject::custom(EmitRejection(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryBroker;
+
+    #[test]
+    fn route_defaults() {
+        let broker = InMemoryBroker::new();
+        let io = Emitter::with_broker(broker.clone());
+        let notify = warp::path!("rooms" / String)
+            .and(with_emitter(io.of("/chat").to("all")))
+            .and_then(|room: String, io: Emitter| async move {
+                emit(io.to(room.as_str()), vec!["message", "hi"])
+                    .await
+                    .map(|()| warp::reply())
+            });
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        for room in &["news", "sport"] {
+            let res = runtime.block_on(
+                warp::test::request()
+                    .path(&format!("/rooms/{}", room))
+                    .reply(&notify),
+            );
+            assert_eq!(200, res.status());
+        }
+        let published = broker.published();
+        assert_eq!(2, published.len());
+        assert_eq!("/chat", published[0].packet.nsp());
+        assert_eq!(vec!["all", "news"], published[0].opts.rooms());
+        assert_eq!(vec!["all", "sport"], published[1].opts.rooms());
+    }
+}
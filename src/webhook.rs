@@ -0,0 +1,159 @@
+use crate::{decode, Broker};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::thread;
+use std::time::Duration;
+
+/// How packets are encoded in the request body.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WebhookFormat {
+    /// The msgpack payload as published to Redis (`application/msgpack`), with
+    /// the channel in the `X-Socketio-Channel` header.
+    Msgpack,
+    /// `{"channel", "uid", "packet", "opts"}` as `application/json`.
+    Json,
+}
+
+/// POSTs every packet to an HTTP relay, retrying transport errors and 5xx
+/// responses with exponential backoff.
+#[derive(Debug, Clone)]
+pub struct WebhookBroker {
+    url: String,
+    headers: Vec<(String, String)>,
+    format: WebhookFormat,
+    retries: u32,
+    backoff: Duration,
+    agent: ureq::Agent,
+}
+
+#[derive(Serialize)]
+struct JsonBody<'a> {
+    channel: &'a str,
+    uid: String,
+    packet: crate::Packet,
+    opts: crate::Opts,
+}
+
+impl WebhookBroker {
+    pub fn new(url: &str) -> WebhookBroker {
+        WebhookBroker {
+            url: url.to_string(),
+            headers: Vec::new(),
+            format: WebhookFormat::Msgpack,
+            retries: 3,
+            backoff: Duration::from_millis(100),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> WebhookBroker {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn bearer_auth(self, token: &str) -> WebhookBroker {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    pub fn format(mut self, format: WebhookFormat) -> WebhookBroker {
+        self.format = format;
+        self
+    }
+
+    /// Number of retries after the first attempt, and the delay before the
+    /// first retry (doubled on every subsequent one).
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> WebhookBroker {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> WebhookBroker {
+        self.agent = ureq::AgentBuilder::new().timeout(timeout).build();
+        self
+    }
+
+    fn body(&self, channel: &str, payload: &[u8]) -> RedisResult<(&'static str, Vec<u8>)> {
+        match self.format {
+            WebhookFormat::Msgpack => Ok(("application/msgpack", payload.to_vec())),
+            WebhookFormat::Json => {
+                let (uid, packet, opts) = decode(payload).map_err(|e| {
+                    RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+                })?;
+                let body = JsonBody {
+                    channel,
+                    uid,
+                    packet,
+                    opts,
+                };
+                let json = serde_json::to_vec(&body).map_err(|e| {
+                    RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+                })?;
+                Ok(("application/json", json))
+            }
+        }
+    }
+}
+
+impl Broker for WebhookBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let (content_type, body) = self.body(channel, payload)?;
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .agent
+                .post(&self.url)
+                .set("Content-Type", content_type)
+                .set("X-Socketio-Channel", channel);
+            for (name, value) in &self.headers {
+                request = request.set(name, value);
+            }
+            let err = match request.send_bytes(&body) {
+                Ok(_) => return Ok(()),
+                Err(ureq::Error::Status(code, _)) if code < 500 => {
+                    return Err(webhook_error(format!("HTTP {}", code)))
+                }
+                Err(e) => e,
+            };
+            if attempt >= self.retries {
+                return Err(webhook_error(err.to_string()));
+            }
+            attempt += 1;
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
+}
+
+fn webhook_error(detail: String) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "webhook publish failed", detail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WebhookBroker, WebhookFormat};
+    use crate::{encode, Opts, Packet};
+
+    #[test]
+    fn json_body() {
+        let packet = Packet {
+            _type: 2,
+            data: vec!["test".to_string()],
+            nsp: "/".to_string(),
+        };
+        let opts = Opts {
+            rooms: vec![],
+            ..Default::default()
+        };
+        let payload = encode("emitter", &packet, &opts);
+
+        let broker = WebhookBroker::new("http://localhost").format(WebhookFormat::Json);
+        let (content_type, body) = broker.body("socket.io#/#", &payload).unwrap();
+
+        assert_eq!("application/json", content_type);
+        assert_eq!(
+            r##"{"channel":"socket.io#/#","uid":"emitter","packet":{"type":2,"data":["test"],"nsp":"/"},"opts":{"rooms":[],"flags":{}}}"##,
+            String::from_utf8(body).unwrap()
+        );
+    }
+}
@@ -0,0 +1,357 @@
+use crate::{decode, Broker};
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::HandshakeError;
+use tungstenite::{Message, WebSocket};
+
+type Socket = WebSocket<TcpStream>;
+
+/// Connects to a socket.io server over the engine.io websocket transport and
+/// forwards every packet as an event on a privileged namespace, for servers
+/// that are reachable when Redis is not. Only `ws://` URLs are supported.
+///
+/// The server is expected to broadcast the forwarded packets itself:
+///
+/// ```js
+/// io.of("/emitter").use(authorize).on("connection", (socket) => {
+///   socket.on("emit", ({ packet, opts }) => {
+///     io.of(packet.nsp).to(opts.rooms).emit(...packet.data);
+///   });
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct WebSocketBroker {
+    url: String,
+    namespace: String,
+    event: String,
+    auth: Option<String>,
+    timeout: Option<Duration>,
+    connect_timeout: Duration,
+    socket: Arc<Mutex<Option<Socket>>>,
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+    channel: &'a str,
+    uid: String,
+    packet: crate::Packet,
+    opts: crate::Opts,
+}
+
+impl WebSocketBroker {
+    /// `url` is the server's base URL, e.g. `ws://localhost:3000`. The
+    /// connection is opened on the first publish and reopened after errors.
+    pub fn new(url: &str) -> WebSocketBroker {
+        WebSocketBroker {
+            url: url.trim_end_matches('/').to_string(),
+            namespace: "/emitter".to_string(),
+            event: "emit".to_string(),
+            auth: None,
+            timeout: None,
+            connect_timeout: Duration::from_secs(10),
+            socket: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn namespace(mut self, namespace: &str) -> WebSocketBroker {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    pub fn event(mut self, event: &str) -> WebSocketBroker {
+        self.event = event.to_string();
+        self
+    }
+
+    /// Sends `{"token": token}` as the namespace's auth payload.
+    pub fn auth_token(mut self, token: &str) -> WebSocketBroker {
+        self.auth = Some(serde_json::json!({ "token": token }).to_string());
+        self
+    }
+
+    /// Read/write timeout for the connection handshake and every send.
+    pub fn timeout(mut self, timeout: Duration) -> WebSocketBroker {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Total time for opening the connection, from the TCP connect to the
+    /// namespace's acknowledgement, 10 seconds by default. A publish that
+    /// needs a new connection fails once it runs out.
+    pub fn connect_timeout(mut self, timeout: Duration) -> WebSocketBroker {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    fn connect(&self) -> RedisResult<Socket> {
+        let deadline = Instant::now() + self.connect_timeout;
+        let url = format!("{}/socket.io/?EIO=4&transport=websocket", self.url);
+        let request = url.into_client_request().map_err(ws_error)?;
+        let host = request.uri().host().unwrap_or("");
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = request.uri().port_u16().unwrap_or(80);
+        let stream = connect_tcp((host, port), deadline)?;
+        stream.set_write_timeout(Some(self.time_left(deadline)?))?;
+        stream.set_read_timeout(Some(self.time_left(deadline)?))?;
+        let (mut socket, _) = tungstenite::client(request, stream).map_err(|e| match e {
+            HandshakeError::Failure(e) => ws_error(e),
+            HandshakeError::Interrupted(_) => timed_out(),
+        })?;
+        let mut connected = false;
+        let socket = loop {
+            socket
+                .get_mut()
+                .set_read_timeout(Some(self.time_left(deadline)?))?;
+            let message = match socket.read() {
+                Ok(message) => message,
+                Err(_) if Instant::now() >= deadline => return Err(timed_out()),
+                Err(e) => return Err(ws_error(e)),
+            };
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => return Err(closed()),
+                _ => continue,
+            };
+            if text.starts_with('0') && !connected {
+                // engine.io open packet: connect to the namespace.
+                connected = true;
+                socket
+                    .send(Message::Text(self.connect_packet()))
+                    .map_err(ws_error)?;
+            } else if text == "2" {
+                socket.send(Message::Text("3".into())).map_err(ws_error)?;
+            } else if text.starts_with(&self.packet_prefix('0')) {
+                break socket;
+            } else if text.starts_with(&self.packet_prefix('4')) {
+                return Err(RedisError::from((
+                    ErrorKind::AuthenticationFailed,
+                    "socket.io connection refused",
+                    text,
+                )));
+            }
+        };
+        socket.get_ref().set_read_timeout(self.timeout)?;
+        socket.get_ref().set_write_timeout(self.timeout)?;
+        Ok(socket)
+    }
+
+    /// The time left until `deadline`, capped at the read/write timeout.
+    fn time_left(&self, deadline: Instant) -> RedisResult<Duration> {
+        let left = remaining(deadline)?;
+        Ok(self.timeout.map_or(left, |timeout| timeout.min(left)))
+    }
+
+    /// The socket.io packet prefix (`4` + type + namespace) for this namespace.
+    fn packet_prefix(&self, packet_type: char) -> String {
+        if self.namespace == "/" {
+            format!("4{}", packet_type)
+        } else {
+            format!("4{}{},", packet_type, self.namespace)
+        }
+    }
+
+    fn connect_packet(&self) -> String {
+        format!(
+            "{}{}",
+            self.packet_prefix('0'),
+            self.auth.as_deref().unwrap_or("")
+        )
+    }
+
+    fn event_packet(&self, channel: &str, payload: &[u8]) -> RedisResult<String> {
+        let (uid, packet, opts) = decode(payload).map_err(|e| {
+            RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+        })?;
+        let event = Event {
+            channel,
+            uid,
+            packet,
+            opts,
+        };
+        let json = serde_json::to_string(&(&self.event, event)).map_err(|e| {
+            RedisError::from((ErrorKind::TypeError, "invalid packet", e.to_string()))
+        })?;
+        Ok(format!("{}{}", self.packet_prefix('2'), json))
+    }
+}
+
+/// Connects to the first address of `addr` that accepts before `deadline`.
+fn connect_tcp<A: ToSocketAddrs>(addr: A, deadline: Instant) -> RedisResult<TcpStream> {
+    let mut last_error = None;
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, remaining(deadline)?) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.map_or_else(
+        || RedisError::from((ErrorKind::IoError, "websocket host not found")),
+        RedisError::from,
+    ))
+}
+
+/// The time left until `deadline`, or an error once it has passed.
+fn remaining(deadline: Instant) -> RedisResult<Duration> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .filter(|left| !left.is_zero())
+        .ok_or_else(timed_out)
+}
+
+/// Answers the pings the server sent since the last publish, so an idle
+/// connection is not dropped by the server's ping timeout.
+fn drain(socket: &mut Socket) -> RedisResult<()> {
+    let mut pings = 0;
+    socket.get_ref().set_nonblocking(true)?;
+    let result = loop {
+        match socket.read() {
+            Ok(Message::Text(text)) if text == "2" => pings += 1,
+            Ok(Message::Close(_)) => break Err(closed()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+            Err(e) => break Err(ws_error(e)),
+        }
+    };
+    socket.get_ref().set_nonblocking(false)?;
+    result?;
+    for _ in 0..pings {
+        socket.send(Message::Text("3".into())).map_err(ws_error)?;
+    }
+    Ok(())
+}
+
+impl Broker for WebSocketBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let packet = self.event_packet(channel, payload)?;
+        let mut socket = self.socket.lock().unwrap();
+        if let Some(open) = socket.as_mut() {
+            if drain(open).is_err() {
+                *socket = None;
+            }
+        }
+        let open = match socket.as_mut() {
+            Some(open) => open,
+            None => socket.insert(self.connect()?),
+        };
+        let result = open.send(Message::Text(packet)).map_err(ws_error);
+        if result.is_err() {
+            *socket = None;
+        }
+        result
+    }
+}
+
+fn closed() -> RedisError {
+    RedisError::from((ErrorKind::IoError, "socket.io connection closed"))
+}
+
+fn timed_out() -> RedisError {
+    RedisError::from((ErrorKind::IoError, "socket.io connect timed out"))
+}
+
+fn ws_error(e: tungstenite::Error) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "websocket error", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WebSocketBroker;
+    use crate::{encode, Broker, Opts, Packet};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tungstenite::Message;
+
+    fn payload() -> Vec<u8> {
+        let packet = Packet {
+            _type: 2,
+            data: vec!["test".to_string()],
+            nsp: "/".to_string(),
+        };
+        let opts = Opts {
+            rooms: vec!["room".to_string()],
+            ..Default::default()
+        };
+        encode("emitter", &packet, &opts)
+    }
+
+    /// A socket.io server that opens the engine.io session, then
+    /// acknowledges the namespace if `ack` or keeps pinging if not. Returns
+    /// its URL and the events it received.
+    fn fake_server(ack: bool) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let mut received = Vec::new();
+            socket
+                .send(Message::Text(r#"0{"sid":"a"}"#.into()))
+                .unwrap();
+            while let Ok(Message::Text(text)) = socket.read() {
+                if text.starts_with("42") {
+                    received.push(text);
+                } else if !ack {
+                    let _ = socket.send(Message::Text("2".into()));
+                } else if text.starts_with("40") {
+                    socket.send(Message::Text("40/emitter,{}".into())).unwrap();
+                }
+            }
+            received
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn forwards_packets() {
+        let (url, server) = fake_server(true);
+        WebSocketBroker::new(&url)
+            .publish("socket.io#/#room#", &payload())
+            .unwrap();
+        let received = server.join().unwrap();
+        assert!(received
+            .last()
+            .unwrap()
+            .starts_with(r#"42/emitter,["emit","#));
+    }
+
+    #[test]
+    fn times_out_connecting() {
+        // Accepts the TCP connection but never answers the handshake.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let started = Instant::now();
+        let broker = WebSocketBroker::new(&url).connect_timeout(Duration::from_millis(200));
+        assert!(broker.publish("socket.io#/#room#", &payload()).is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // Keeps pinging without acknowledging the namespace.
+        let (url, _server) = fake_server(false);
+        let started = Instant::now();
+        let broker = WebSocketBroker::new(&url).connect_timeout(Duration::from_millis(200));
+        assert!(broker.publish("socket.io#/#room#", &payload()).is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn packets() {
+        let payload = payload();
+
+        let broker = WebSocketBroker::new("ws://localhost:3000/").auth_token("secret");
+        assert_eq!(r#"40/emitter,{"token":"secret"}"#, broker.connect_packet());
+        assert_eq!(
+            r##"42/emitter,["emit",{"channel":"socket.io#/#room#","uid":"emitter","packet":{"type":2,"data":["test"],"nsp":"/"},"opts":{"rooms":["room"],"flags":{}}}]"##,
+            broker.event_packet("socket.io#/#room#", &payload).unwrap()
+        );
+        assert_eq!(
+            "40",
+            WebSocketBroker::new("ws://localhost")
+                .namespace("/")
+                .connect_packet()
+        );
+    }
+}
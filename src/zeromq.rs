@@ -0,0 +1,86 @@
+use crate::Broker;
+use redis::{ErrorKind, RedisError, RedisResult};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Publishes packets on a ZeroMQ PUB socket as two-frame messages: the
+/// socket.io channel (usable as a SUB prefix filter) followed by the packet.
+#[derive(Clone)]
+pub struct ZmqBroker {
+    socket: Arc<Mutex<zmq::Socket>>,
+}
+
+impl ZmqBroker {
+    /// Connects a PUB socket to `endpoint`, e.g. the XSUB side of a fanout proxy.
+    pub fn connect(endpoint: &str) -> RedisResult<ZmqBroker> {
+        let socket = zmq::Context::new().socket(zmq::PUB).map_err(zmq_error)?;
+        socket.connect(endpoint).map_err(zmq_error)?;
+        Ok(ZmqBroker::new(socket))
+    }
+
+    /// Binds a PUB socket on `endpoint` for subscribers to connect to.
+    pub fn bind(endpoint: &str) -> RedisResult<ZmqBroker> {
+        let socket = zmq::Context::new().socket(zmq::PUB).map_err(zmq_error)?;
+        socket.bind(endpoint).map_err(zmq_error)?;
+        Ok(ZmqBroker::new(socket))
+    }
+
+    pub fn new(socket: zmq::Socket) -> ZmqBroker {
+        ZmqBroker {
+            socket: Arc::new(Mutex::new(socket)),
+        }
+    }
+}
+
+impl Broker for ZmqBroker {
+    fn publish(&self, channel: &str, payload: &[u8]) -> RedisResult<()> {
+        let socket = self.socket.lock().unwrap();
+        socket
+            .send_multipart(frames(channel, payload), 0)
+            .map_err(zmq_error)
+    }
+}
+
+/// The channel first, so subscribers can filter on it as a prefix.
+fn frames<'a>(channel: &'a str, payload: &'a [u8]) -> [&'a [u8]; 2] {
+    [channel.as_bytes(), payload]
+}
+
+impl fmt::Debug for ZmqBroker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZmqBroker").finish()
+    }
+}
+
+fn zmq_error(e: zmq::Error) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "ZeroMQ error", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frames, ZmqBroker};
+    use crate::{encode, Broker, Opts, Packet};
+
+    #[test]
+    fn sends_two_frames() {
+        let context = zmq::Context::new();
+        let receiver = context.socket(zmq::PAIR).unwrap();
+        receiver.bind("inproc://zmq-broker").unwrap();
+        let sender = context.socket(zmq::PAIR).unwrap();
+        sender.connect("inproc://zmq-broker").unwrap();
+
+        let payload = encode("emitter", &Packet::new("/", vec!["news"]), &Opts::default());
+        assert_eq!(
+            [&b"socket.io#/#room#"[..], &payload[..]],
+            frames("socket.io#/#room#", &payload)
+        );
+
+        ZmqBroker::new(sender)
+            .publish("socket.io#/#room#", &payload)
+            .unwrap();
+        assert_eq!(
+            vec![b"socket.io#/#room#".to_vec(), payload],
+            receiver.recv_multipart(0).unwrap()
+        );
+    }
+}
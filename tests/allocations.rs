@@ -0,0 +1,64 @@
+//! Counts allocations with a global allocator, which is why this lives in
+//! its own test binary instead of with the unit tests.
+
+use redis::RedisResult;
+use socketio_rust_emitter::{Broker, Emitter};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct Counting;
+
+thread_local! {
+    static COUNTED: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = COUNTED.try_with(|counted| counted.set(counted.get().map(|n| n + 1)));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// How many allocations `f` makes on this thread.
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    COUNTED.with(|counted| counted.set(Some(0)));
+    f();
+    COUNTED.with(|counted| counted.take()).unwrap()
+}
+
+/// Accepts packets without allocating.
+struct Discard;
+
+impl Broker for Discard {
+    fn publish(&self, _channel: &str, _payload: &[u8]) -> RedisResult<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn small_payloads_serialize_without_allocating() {
+    let io = Emitter::with_broker(Discard).to("user:1");
+    let emit = |arg: &str| {
+        let io = io.clone();
+        allocations(|| {
+            io.emit(vec!["notification", arg]);
+        })
+    };
+    // Caches the channel.
+    emit("");
+
+    // Building the packet allocates the same for any argument, so only the
+    // serialization can make a difference.
+    let tiny = emit("x");
+    let small = emit(&"x".repeat(400));
+    let large = emit(&"x".repeat(1024));
+    assert_eq!(tiny, small);
+    assert!(large > small, "{} vs {}", large, small);
+}